@@ -1,6 +1,10 @@
-use crate::Color;
-use once_cell::sync::OnceCell;
+use crate::{error::ThemeLoadError, Color, PlainSqlPrinter};
+use once_cell::sync::{Lazy, OnceCell};
 use owo_colors::{AnsiColors, OwoColorize};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    RwLock,
+};
 use syntect::{
     easy::HighlightLines,
     highlighting::{Style, ThemeSet},
@@ -10,35 +14,85 @@ use tracing::error;
 
 pub(crate) static SYNTAXES: OnceCell<SyntaxSet> = OnceCell::new();
 static THEMES: OnceCell<ThemeSet> = OnceCell::new();
+static DEFAULT_THEME: RwLock<String> = RwLock::new(String::new());
+// https://no-color.org
+static COLOR_ENABLED: Lazy<AtomicBool> =
+    Lazy::new(|| AtomicBool::new(std::env::var_os("NO_COLOR").map_or(true, |val| val.is_empty())));
+
+const ANSI_THEME: &str = "ansi";
 
 pub struct SqlPrinter {
-    pub(crate) highlighter: HighlightLines<'static>,
+    pub(crate) highlighter: Option<HighlightLines<'static>>,
 }
 
 impl Default for SqlPrinter {
     fn default() -> Self {
+        if !COLOR_ENABLED.load(Ordering::Relaxed) {
+            return Self::plain();
+        }
+        let default_theme = DEFAULT_THEME.read().expect("Failed to lock theme");
+        let theme = if default_theme.is_empty() {
+            ANSI_THEME
+        } else {
+            default_theme.as_str()
+        };
+        Self::with_theme(theme).expect("Failed to load default theme")
+    }
+}
+
+fn themes() -> &'static ThemeSet {
+    THEMES.get_or_init(|| syntect::dumps::from_binary(include_bytes!("../assets/themes.themedump")))
+}
+
+impl SqlPrinter {
+    pub fn with_theme(name: &str) -> Result<Self, ThemeLoadError> {
         let syntax_set = SYNTAXES.get_or_init(|| {
             syntect::dumps::from_uncompressed_data(include_bytes!("../assets/sqlite.packdump"))
                 .expect("failed to load syntaxes")
         });
-        let themes = THEMES.get_or_init(|| {
-            syntect::dumps::from_binary(include_bytes!("../assets/themes.themedump"))
-        });
-        let theme = themes
+        let theme = themes()
             .themes
-            .get("ansi")
-            .expect("Failed to load ansi theme");
+            .get(name)
+            .ok_or_else(|| ThemeLoadError(name.to_owned(), Self::theme_names()))?;
         let sql_syntax = syntax_set
             .find_syntax_by_name("SQL")
             .expect("Failed to load SQL syntax")
             .to_owned();
         let highlighter = HighlightLines::new(&sql_syntax, theme);
 
-        Self { highlighter }
+        Ok(Self {
+            highlighter: Some(highlighter),
+        })
+    }
+
+    /// Creates a printer that outputs SQL without any ANSI escape codes
+    pub fn plain() -> Self {
+        Self { highlighter: None }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.highlighter.is_none()
+    }
+
+    /// Controls whether [`SqlPrinter::default`] highlights its output.
+    /// Defaults to `true` unless the `NO_COLOR` environment variable is set.
+    pub fn set_color_enabled(enabled: bool) {
+        COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn theme_names() -> Vec<String> {
+        themes().themes.keys().cloned().collect()
+    }
+
+    /// Sets the theme used by [`SqlPrinter::default`]
+    pub fn set_default_theme(name: &str) -> Result<(), ThemeLoadError> {
+        if !themes().themes.contains_key(name) {
+            return Err(ThemeLoadError(name.to_owned(), Self::theme_names()));
+        }
+        *DEFAULT_THEME.write().expect("Failed to lock theme") = name.to_owned();
+        Ok(())
     }
-}
 
-impl SqlPrinter {
     pub fn print(&mut self, sql: &str) -> String {
         self.print_inner(sql, None)
     }
@@ -48,12 +102,14 @@ impl SqlPrinter {
     }
 
     fn print_inner(&mut self, sql: &str, background: Option<Color>) -> String {
+        let Some(highlighter) = &mut self.highlighter else {
+            return PlainSqlPrinter.print(sql);
+        };
         let formatted = sql
             .split('\n')
             .map(|line| {
                 let line = format!("{}\n", line.replace("    ", " "));
-                let regions = self
-                    .highlighter
+                let regions = highlighter
                     .highlight_line(&line, SYNTAXES.get().expect("Syntaxes weren't initialized"))?;
 
                 Ok(to_ansi_colored(&regions[..], background))
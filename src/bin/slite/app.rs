@@ -3,27 +3,36 @@ use clap::{ArgAction, Args, CommandFactory, Parser, ValueEnum};
 use clap_complete::{generate, Shell};
 use color_eyre::Report;
 use confique::{toml, Config};
+use crossterm::{
+    cursor::MoveTo,
+    execute,
+    terminal::{Clear, ClearType},
+};
 use elm_ui::{Command, Message};
 use minus::Pager;
 use normpath::PathExt;
-use notify_debouncer_mini::DebouncedEvent;
+use notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebouncedEvent};
 use owo_colors::OwoColorize;
 use regex::Regex;
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags};
 use serde::{de::Visitor, Deserialize, Serialize};
 use slite::{
     error::InitializationError,
-    read_extension_dir, read_sql_files,
+    object_changes, read_extension_dir, read_sql_dirs, read_sql_files,
     tui::{AppMessage, BroadcastWriter, ConfigHandler, MigratorFactory},
-    Migrator, Options, SqlPrinter,
+    ChangeKind, ExtensionSpec, IgnoreRules, Migrator, ObjectType, Options, SqlPrinter, SqlScript,
+    FIELDS_TARGET,
 };
 use std::{
     fmt::Write,
     fs,
-    io::{self, IsTerminal},
+    io::{self, IsTerminal, Read, Write as _},
     path::{Path, PathBuf},
+    process::ExitCode,
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 use tokio::sync::mpsc;
 use tracing::metadata::LevelFilter;
@@ -50,6 +59,20 @@ enum Migrate {
     Script,
 }
 
+#[derive(ValueEnum, Clone, Default)]
+enum DiffFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Default)]
+enum LogFormat {
+    #[default]
+    Tree,
+    Json,
+}
+
 #[derive(ValueEnum, Clone)]
 enum AppConfig {
     Generate,
@@ -58,11 +81,62 @@ enum AppConfig {
 #[derive(clap::Subcommand, Clone)]
 #[command(author, version, about)]
 enum AppCommand {
-    Migrate { migrate: Migrate },
-    Config { config: AppConfig },
-    Diff,
-    Print { from: SchemaType },
-    Completions { shell: Shell },
+    Migrate {
+        migrate: Migrate,
+        /// Skip the confirmation prompt when running a migration
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        yes: bool,
+        /// Allow the migration to drop tables, columns, indexes, views, and triggers
+        #[arg(long, action = ArgAction::SetTrue)]
+        allow_deletions: bool,
+        /// Run ANALYZE on the target after migrating
+        #[arg(long, action = ArgAction::SetTrue)]
+        analyze: bool,
+        /// Leave out the comments describing each change when generating a script
+        #[arg(long, action = ArgAction::SetTrue)]
+        no_comments: bool,
+        /// Only migrate the named objects along with their indexes and triggers. Can be passed
+        /// multiple times.
+        #[arg(long = "object")]
+        objects: Vec<String>,
+    },
+    Config {
+        config: AppConfig,
+    },
+    Diff {
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DiffFormat,
+        #[arg(long, action = ArgAction::SetTrue)]
+        exit_code: bool,
+        /// Re-print the diff whenever the source or target changes
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "exit_code")]
+        watch: bool,
+    },
+    Print {
+        from: SchemaType,
+        /// Print source objects as written in the schema files, including comments
+        #[arg(long, action = ArgAction::SetTrue)]
+        with_comments: bool,
+    },
+    /// Write the source schema to a single file with objects ordered by type and name
+    DumpSchema {
+        /// File to write the schema to. Defaults to stdout.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Run a script generated by `migrate script` against the target in a single transaction
+    Apply {
+        /// Script to apply
+        #[arg(long, value_parser = source_parser)]
+        script: PathBuf,
+    },
+    /// Summarize the differences between the source and the target.
+    /// Exits with a non-zero status code if they're out of sync.
+    Status,
+    Validate,
+    Completions {
+        shell: Shell,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -167,9 +241,13 @@ impl<'a> MakeWriter<'a> for PagerWrapper {
 
 #[derive(Debug, Clone, Default, Args, confique::Config, Serialize, Deserialize)]
 pub struct Conf {
-    #[config(env = "SLITE_SOURCE_DIR")]
-    #[arg(short, long, value_parser = source_parser)]
-    pub source: Option<PathBuf>,
+    #[config(
+        env = "SLITE_SOURCE_DIR",
+        parse_env = confique::env::parse::list_by_comma,
+        deserialize_with = path_list_deserializer
+    )]
+    #[arg(short, long, value_parser = schema_source_parser)]
+    pub source: Option<Vec<PathBuf>>,
     #[config(env = "SLITE_PRE_MIGRATION_DIR")]
     #[arg(short='e', long, value_parser = source_parser)]
     pub pre_migration: Option<PathBuf>,
@@ -179,9 +257,19 @@ pub struct Conf {
     #[config(env = "SLITE_TARGET_DB")]
     #[arg(short, long, value_parser = destination_parser)]
     pub target: Option<PathBuf>,
+    #[config(env = "SLITE_TARGET_URI")]
+    #[arg(long, visible_alias = "target-url", conflicts_with = "target")]
+    pub target_uri: Option<String>,
     #[config(env = "SLITE_EXTENSION_DIR")]
     #[arg(short='d', long, value_parser = source_parser)]
     pub extension_dir: Option<PathBuf>,
+    /// Names of system-installed extensions to load, such as mod_spatialite
+    #[config(
+        env = "SLITE_EXTENSIONS",
+        parse_env = confique::env::parse::list_by_comma
+    )]
+    #[arg(short = 'x', long = "extension")]
+    pub extensions: Option<Vec<String>>,
     #[config(env = "SLITE_IGNORE_PATTERN")]
     #[arg(short, long, value_parser = regex_parser)]
     pub ignore: Option<SerdeRegex>,
@@ -196,6 +284,7 @@ pub struct Conf {
 impl Conf {
     fn migrator_config_changed(&self, other: &Self) -> bool {
         self.extension_dir != other.extension_dir
+            || self.extensions != other.extensions
             || self.ignore != other.ignore
             || self.pre_migration != other.pre_migration
             || self.post_migration != other.post_migration
@@ -208,10 +297,36 @@ struct Cli {
     command: Option<AppCommand>,
     #[command(flatten)]
     config: Conf,
+    /// Suppress log output
+    #[arg(short, long, global = true, action = ArgAction::SetTrue)]
+    quiet: bool,
+    /// Open the target database in read-only mode
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    read_only: bool,
+    /// Path to a config file to use instead of searching for slite.toml
+    #[arg(long = "config", global = true, value_parser = config_parser)]
+    config_file: Option<PathBuf>,
+    /// Disable colored output. Also respects the NO_COLOR environment variable
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    no_color: bool,
+    /// Syntax highlighting theme used when printing SQL
+    #[arg(long, global = true)]
+    theme: Option<String>,
+    /// Format of log output for commands that don't use the TUI
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_format: LogFormat,
+    /// Source schema SQL to use instead of reading it from files
+    #[arg(long, global = true, conflicts_with = "source")]
+    source_inline: Option<String>,
 }
 
+const STDIN_SOURCE: &str = "-";
+
 fn source_parser(val: &str) -> Result<PathBuf, Report> {
     let path = PathBuf::from(val.to_owned());
+    if val == STDIN_SOURCE {
+        return Ok(path);
+    }
     match path.try_exists() {
         Ok(true) => Ok(path),
         Ok(false) => Err(color_eyre::eyre::eyre!("Path does not exist")),
@@ -219,15 +334,141 @@ fn source_parser(val: &str) -> Result<PathBuf, Report> {
     }
 }
 
-fn destination_parser(val: &str) -> Result<PathBuf, Report> {
+fn schema_source_parser(val: &str) -> Result<PathBuf, Report> {
+    let path = source_parser(val)?;
+    validate_source(&path)?;
+    Ok(path)
+}
+
+fn validate_source(path: &Path) -> Result<(), Report> {
+    if path.is_file() && is_sqlite_database(path) {
+        return Err(color_eyre::eyre::eyre!(
+            "Source {} is a SQLite database, not a schema directory. Did you mean to pass it as the target?",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn validate_target(path: &Path) -> Result<(), Report> {
+    let is_schema = if path.is_dir() {
+        // A directory of databases may also contain scripts, so it's only treated as a schema
+        // directory if there's nothing to migrate
+        read_target_dir(path)?.is_empty()
+            && fs::read_dir(path)?.any(|entry| {
+                entry
+                    .map(|e| e.path().extension().map(|e| e == "sql").unwrap_or(false))
+                    .unwrap_or(false)
+            })
+    } else {
+        path.extension().map(|e| e == "sql").unwrap_or(false) && !is_sqlite_database(path)
+    };
+    if is_schema {
+        return Err(color_eyre::eyre::eyre!(
+            "Target {} contains SQL scripts, not a database. Did you mean to pass it as the source?",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn is_sqlite_database(path: &Path) -> bool {
+    let mut header = [0; 16];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| &header == b"SQLite format 3\0")
+        .unwrap_or(false)
+}
+
+fn path_list_deserializer<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PathList {
+        Single(PathBuf),
+        Multiple(Vec<PathBuf>),
+    }
+
+    Ok(match PathList::deserialize(deserializer)? {
+        PathList::Single(path) => vec![path],
+        PathList::Multiple(paths) => paths,
+    })
+}
+
+fn load_extension_specs(
+    extension_dir: Option<PathBuf>,
+    extensions: Option<Vec<String>>,
+) -> Vec<ExtensionSpec> {
+    extension_dir
+        .and_then(|dir| read_extension_dir(dir).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(ExtensionSpec::Path)
+        .chain(
+            extensions
+                .unwrap_or_default()
+                .into_iter()
+                .map(ExtensionSpec::Name),
+        )
+        .collect()
+}
+
+fn config_parser(val: &str) -> Result<PathBuf, Report> {
     let path = PathBuf::from(val.to_owned());
     match (path.try_exists(), path.is_file()) {
-        (Ok(true), false) => Err(color_eyre::eyre::eyre!("Destination must be a file")),
-        (Ok(_), _) => Ok(path),
+        (Ok(true), true) => Ok(path),
+        (Ok(true), false) => Err(color_eyre::eyre::eyre!("Config must be a file")),
+        (Ok(false), _) => Err(color_eyre::eyre::eyre!("Config file {val} does not exist")),
         (Err(e), _) => Err(color_eyre::eyre::eyre!("{e}")),
     }
 }
 
+fn destination_parser(val: &str) -> Result<PathBuf, Report> {
+    let path = PathBuf::from(val.to_owned());
+    match (path.try_exists(), path.is_file() || path.is_dir()) {
+        (Ok(true), false) => Err(color_eyre::eyre::eyre!(
+            "Destination must be a file or a directory of databases"
+        )),
+        (Ok(_), _) => {
+            validate_target(&path)?;
+            Ok(path)
+        }
+        (Err(e), _) => Err(color_eyre::eyre::eyre!("{e}")),
+    }
+}
+
+const DATABASE_EXTENSIONS: [&str; 4] = ["db", "db3", "sqlite", "sqlite3"];
+
+fn read_target_dir(dir: &Path) -> Result<Vec<PathBuf>, Report> {
+    let mut targets = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| DATABASE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+                    .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    targets.sort();
+    Ok(targets)
+}
+
+fn read_schema(source: &[PathBuf]) -> Result<Vec<SqlScript>, Report> {
+    let (stdin, dirs): (Vec<_>, Vec<_>) =
+        source.iter().partition(|s| *s == Path::new(STDIN_SOURCE));
+    let mut schema = read_sql_dirs(&dirs)?;
+    if !stdin.is_empty() {
+        schema.push(io::read_to_string(io::stdin())?.into());
+    }
+    Ok(schema)
+}
+
 fn regex_parser(val: &str) -> Result<SerdeRegex, regex::Error> {
     Ok(SerdeRegex(Regex::new(val)?))
 }
@@ -303,7 +544,7 @@ impl ConfigHandler<Conf> for ConfigStore {
             self.send_config_changed(&new_config)?;
         }
 
-        if self.contains_path(&events, &new_config.source) {
+        if self.contains_path(&events, new_config.source.iter().flatten()) {
             self.tx
                 .blocking_send(elm_ui::Command::simple(Message::custom(
                     AppMessage::FileChanged,
@@ -324,9 +565,11 @@ impl ConfigHandler<Conf> for ConfigStore {
         let partial = confique_partial_conf::PartialConf {
             source: cli_config.source,
             target: cli_config.target,
+            target_uri: cli_config.target_uri,
             pre_migration: cli_config.pre_migration,
             post_migration: cli_config.post_migration,
             extension_dir: cli_config.extension_dir,
+            extensions: cli_config.extensions,
             ignore: cli_config.ignore,
             log_level: cli_config.log_level,
             pager: cli_config.pager,
@@ -343,7 +586,7 @@ impl ConfigHandler<Conf> for ConfigStore {
         let config = self.create_config(path);
         let mut paths = vec![path.to_path_buf()];
         if let Some(source) = config.source {
-            paths.push(source);
+            paths.extend(source);
         }
         if let Some(before) = config.pre_migration {
             paths.push(before);
@@ -356,17 +599,19 @@ impl ConfigHandler<Conf> for ConfigStore {
 }
 
 impl ConfigStore {
-    fn contains_path(&self, events: &[DebouncedEvent], search: &Option<PathBuf>) -> bool {
+    fn contains_path<'a>(
+        &self,
+        events: &[DebouncedEvent],
+        search: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> bool {
+        let search: Vec<_> = search.into_iter().collect();
         events.iter().any(|e| {
-            search
-                .as_ref()
-                .map(|p| {
-                    e.path
-                        .normalize()
-                        .unwrap()
-                        .starts_with(p.normalize().unwrap())
-                })
-                .unwrap_or(false)
+            search.iter().any(|p| {
+                e.path
+                    .normalize()
+                    .unwrap()
+                    .starts_with(p.normalize().unwrap())
+            })
         })
     }
 
@@ -377,13 +622,17 @@ impl ConfigStore {
         self.tx
             .blocking_send(Command::simple(Message::custom(AppMessage::ConfigChanged(
                 slite::Config {
-                    extensions: new_config
-                        .extension_dir
+                    extensions: load_extension_specs(
+                        new_config.extension_dir.clone(),
+                        new_config.extensions.clone(),
+                    ),
+                    ignore: new_config
+                        .ignore
                         .clone()
-                        .map(read_extension_dir)
-                        .unwrap()
+                        .map(|r| IgnoreRules::from(r.0))
                         .unwrap_or_default(),
-                    ignore: new_config.ignore.clone().map(|r| r.0),
+                    only: None,
+                    only_objects: Vec::new(),
                     before_migration: new_config
                         .pre_migration
                         .clone()
@@ -394,6 +643,10 @@ impl ConfigStore {
                         .clone()
                         .map(read_sql_files)
                         .unwrap_or_default(),
+                    attachments: Vec::new(),
+                    treat_hooks_as_schema: true,
+                    target_pragmas: Vec::new(),
+                    sql_normalizer: None,
                 },
             ))))
     }
@@ -401,13 +654,15 @@ impl ConfigStore {
     fn update_log_level(&self, log_level: &Option<SerdeLevel>) {
         self.reload_handle
             .modify(|l| {
-                *l = Targets::default().with_target(
-                    "slite",
-                    log_level
-                        .as_ref()
-                        .unwrap_or(&SerdeLevel(LevelFilter::INFO))
-                        .0,
-                )
+                *l = Targets::default()
+                    .with_target(
+                        "slite",
+                        log_level
+                            .as_ref()
+                            .unwrap_or(&SerdeLevel(LevelFilter::INFO))
+                            .0,
+                    )
+                    .with_target(FIELDS_TARGET, LevelFilter::OFF)
             })
             .unwrap();
     }
@@ -415,27 +670,37 @@ impl ConfigStore {
 
 pub struct App {
     cli: Cli,
-    source: PathBuf,
+    source: Vec<PathBuf>,
     target: PathBuf,
-    schema: Vec<String>,
+    target_uri: Option<String>,
+    schema: Vec<SqlScript>,
     config: slite::Config,
     log_level: LevelFilter,
     pager: Option<Pager>,
     cli_config: Conf,
+    config_path: Option<PathBuf>,
 }
 
 impl App {
     pub fn from_args() -> Result<Self, Report> {
-        owo_colors::set_override(io::stdout().is_terminal());
         color_eyre::install()?;
 
         let cli = Cli::parse();
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|val| !val.is_empty());
+        let color_enabled = io::stdout().is_terminal() && !cli.no_color && !no_color_env;
+        owo_colors::set_override(color_enabled);
+        SqlPrinter::set_color_enabled(color_enabled);
+        if let Some(theme) = &cli.theme {
+            SqlPrinter::set_default_theme(theme)?;
+        }
         let cli_config = cli.config.clone();
         let cli_config_ = cli_config.clone();
         let partial = confique_partial_conf::PartialConf {
             source: cli_config.source,
             target: cli_config.target,
+            target_uri: cli_config.target_uri,
             extension_dir: cli_config.extension_dir,
+            extensions: cli_config.extensions,
             ignore: cli_config.ignore,
             log_level: cli_config.log_level,
             pager: cli_config.pager,
@@ -444,7 +709,9 @@ impl App {
         };
 
         let direct_path = PathBuf::from("./slite.toml");
-        let path = if direct_path.exists() {
+        let path = if let Some(config_file) = &cli.config_file {
+            Some(config_file.clone())
+        } else if direct_path.exists() {
             Some(direct_path)
         } else {
             let git_root = match gix_discover::upwards_opts(
@@ -474,34 +741,72 @@ impl App {
             }
         };
         let mut conf_builder = Conf::builder().preloaded(partial).env();
-        if let Some(path) = path {
+        if let Some(path) = &path {
             conf_builder = conf_builder.file(path);
         }
         let conf = conf_builder.load().unwrap();
 
         let source = conf.source.unwrap_or_default();
         let target = conf.target.unwrap_or_default();
+        let target_uri = conf.target_uri;
+        // Paths from the config file and environment don't go through the argument parsers
+        for source in source.iter().filter(|s| *s != Path::new(STDIN_SOURCE)) {
+            validate_source(source)?;
+        }
+        validate_target(&target)?;
 
-        let extensions = conf
-            .extension_dir
-            .map(read_extension_dir)
-            .unwrap()
-            .unwrap_or_default();
+        let extensions = load_extension_specs(conf.extension_dir, conf.extensions);
 
-        let ignore = conf.ignore.map(|i| i.0);
+        let ignore = conf
+            .ignore
+            .map(|i| IgnoreRules::from(i.0))
+            .unwrap_or_default();
         let before_migration = conf.pre_migration.map(read_sql_files).unwrap_or_default();
         let after_migration = conf.post_migration.map(read_sql_files).unwrap_or_default();
+        let only_objects = match &cli.command {
+            Some(AppCommand::Migrate { objects, .. }) => objects.clone(),
+            _ => Vec::new(),
+        };
         let config = slite::Config {
             extensions,
             ignore,
+            only: None,
+            only_objects,
             before_migration,
             after_migration,
+            attachments: Vec::new(),
+            treat_hooks_as_schema: true,
+            target_pragmas: Vec::new(),
+            sql_normalizer: None,
         };
         let log_level = conf.log_level.unwrap_or(SerdeLevel(LevelFilter::INFO));
-        let schema = read_sql_files(&source);
+        if cli.command.is_none() && target.is_dir() {
+            return Err(color_eyre::eyre::eyre!(
+                "A directory of target databases is not supported in the TUI"
+            ));
+        }
+        if cli.command.is_none() && source.iter().any(|s| s == Path::new(STDIN_SOURCE)) {
+            return Err(color_eyre::eyre::eyre!(
+                "Reading the source schema from stdin is not supported in the TUI"
+            ));
+        }
+        if cli.command.is_none() && cli.source_inline.is_some() {
+            return Err(color_eyre::eyre::eyre!(
+                "--source-inline is not supported in the TUI"
+            ));
+        }
+        // Inline SQL takes precedence over any source directories from the config file
+        let (source, schema) = match &cli.source_inline {
+            Some(source_inline) => (Vec::new(), vec![SqlScript::from(source_inline.as_str())]),
+            None => {
+                let schema = read_schema(&source)?;
+                (source, schema)
+            }
+        };
 
         let pager = if conf.pager.unwrap_or_default()
             && cli.command.is_some()
+            && !matches!(cli.command, Some(AppCommand::Diff { watch: true, .. }))
             && io::stdout().is_terminal()
         {
             let output = minus::Pager::new();
@@ -517,15 +822,17 @@ impl App {
             cli,
             source,
             target,
+            target_uri,
             schema,
             config,
             pager,
             cli_config: cli_config_,
+            config_path: path,
             log_level: log_level.0,
         })
     }
 
-    pub async fn run(mut self) -> Result<(), Report> {
+    pub async fn run(mut self) -> Result<ExitCode, Report> {
         match self.cli.command.clone() {
             Some(AppCommand::Completions { shell }) => {
                 generate(
@@ -535,36 +842,89 @@ impl App {
                     &mut io::stdout(),
                 );
             }
+            Some(AppCommand::Validate) => {
+                Migrator::validate(&self.schema, self.config.clone())?;
+                self.write("Schema is valid")?;
+            }
+            Some(AppCommand::DumpSchema { out }) => {
+                self.handle_dump_schema_command(out)?;
+            }
+            Some(AppCommand::Migrate {
+                migrate: migrate @ (Migrate::Run | Migrate::DryRun),
+                yes,
+                allow_deletions,
+                analyze,
+                ..
+            }) if self.target.is_dir() => {
+                return self.handle_multi_target_migrate(
+                    &migrate,
+                    yes,
+                    Options {
+                        allow_deletions,
+                        analyze,
+                        ..Default::default()
+                    },
+                );
+            }
+            Some(_) if self.target.is_dir() => {
+                return Err(color_eyre::eyre::eyre!(
+                    "A directory of target databases is only supported by migrate run and migrate dry-run"
+                ));
+            }
             Some(command) => {
-                let target_db = Connection::open(self.target.clone())?;
+                let target_db = self.open_target()?;
 
                 match command {
-                    AppCommand::Migrate { migrate } => {
-                        self.handle_migrate_command(&migrate, target_db)?;
-                    }
-                    AppCommand::Print { from } => {
-                        let migrator = self.get_migrator(
+                    AppCommand::Migrate {
+                        migrate,
+                        yes,
+                        allow_deletions,
+                        analyze,
+                        no_comments,
+                        ..
+                    } => {
+                        self.handle_migrate_command(
+                            &migrate,
+                            yes,
                             Options {
-                                allow_deletions: true,
-                                dry_run: true,
+                                allow_deletions,
+                                analyze,
+                                script_comments: !no_comments,
+                                ..Default::default()
                             },
                             target_db,
                         )?;
-                        self.print_schema(migrator, &from)?;
                     }
-                    AppCommand::Diff => {
-                        let mut migrator = self.get_migrator(
-                            Options {
-                                allow_deletions: true,
-                                dry_run: true,
-                            },
-                            target_db,
-                        )?;
-                        self.write(&migrator.diff()?)?;
+                    AppCommand::Print {
+                        from,
+                        with_comments,
+                    } => {
+                        let migrator =
+                            Migrator::new_read_only(&self.schema, target_db, self.config.clone())?;
+                        self.print_schema(migrator, &from, with_comments)?;
+                    }
+                    AppCommand::Diff { format, watch, .. } if watch => {
+                        self.watch_diff(&format, target_db)?;
+                    }
+                    AppCommand::Diff {
+                        format, exit_code, ..
+                    } => {
+                        let has_changes = self.handle_diff_command(&format, target_db)?;
+                        if exit_code && has_changes {
+                            return Ok(ExitCode::FAILURE);
+                        }
+                    }
+                    AppCommand::Status => {
+                        if self.handle_status_command(target_db)? {
+                            return Ok(ExitCode::FAILURE);
+                        }
                     }
                     AppCommand::Config { config } => {
                         self.handle_config_command(&config)?;
                     }
+                    AppCommand::Apply { script } => {
+                        self.handle_apply_command(&script, target_db)?;
+                    }
                     _ => {}
                 }
             }
@@ -572,32 +932,43 @@ impl App {
                 self.run_tui().await?;
             }
         }
-        Ok(())
+        Ok(ExitCode::SUCCESS)
     }
 
     fn init_logger(&mut self) {
-        if let Some(pager) = self.pager.as_mut() {
-            Registry::default()
-                .with(
-                    HierarchicalLayer::default()
-                        .with_writer(PagerWrapper {
-                            pager: pager.clone(),
-                        })
-                        .with_indent_lines(true)
-                        .with_level(false)
-                        .with_filter(self.log_level),
-                )
-                .init();
+        let log_level = if self.cli.quiet {
+            LevelFilter::OFF
         } else {
-            Registry::default()
-                .with(
-                    HierarchicalLayer::default()
-                        .with_indent_lines(true)
-                        .with_level(false)
-                        .with_filter(self.log_level),
-                )
-                .init();
-        }
+            self.log_level
+        };
+        // Structured fields are only useful for the JSON output since the tree output already
+        // includes them in the message
+        let tree_filter = Targets::default()
+            .with_default(log_level)
+            .with_target(FIELDS_TARGET, LevelFilter::OFF);
+        let layer = match (&self.cli.log_format, self.pager.as_mut()) {
+            (LogFormat::Json, _) => tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(io::stderr)
+                .with_filter(log_level)
+                .boxed(),
+            (LogFormat::Tree, Some(pager)) => HierarchicalLayer::default()
+                .with_writer(PagerWrapper {
+                    pager: pager.clone(),
+                })
+                .with_indent_lines(true)
+                .with_level(false)
+                .with_filter(tree_filter)
+                .boxed(),
+            (LogFormat::Tree, None) => HierarchicalLayer::default()
+                .with_indent_lines(true)
+                .with_level(false)
+                .with_filter(tree_filter)
+                .boxed(),
+        };
+        Registry::default().with(layer).init();
     }
 
     fn write(&mut self, out: &str) -> Result<(), Report> {
@@ -609,6 +980,26 @@ impl App {
         Ok(())
     }
 
+    fn open_flags(&self) -> OpenFlags {
+        if self.cli.read_only {
+            OpenFlags::default()
+                .difference(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE)
+                | OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::default()
+        }
+    }
+
+    fn open_target(&self) -> Result<Connection, rusqlite::Error> {
+        match &self.target_uri {
+            Some(target_uri) => Connection::open_with_flags(
+                target_uri,
+                self.open_flags() | OpenFlags::SQLITE_OPEN_URI,
+            ),
+            None => Connection::open_with_flags(&self.target, self.open_flags()),
+        }
+    }
+
     fn get_migrator(
         &self,
         options: Options,
@@ -620,15 +1011,26 @@ impl App {
     fn handle_migrate_command(
         &mut self,
         migrate: &Migrate,
+        yes: bool,
+        options: Options,
         target_db: Connection,
     ) -> Result<(), Report> {
         match migrate {
             Migrate::Run => {
+                if self.cli.read_only {
+                    return Err(color_eyre::eyre::eyre!(
+                        "Cannot run a migration against a read-only target"
+                    ));
+                }
+                if !yes && !self.confirm_migration()? {
+                    println!("Migration cancelled");
+                    return Ok(());
+                }
                 self.init_logger();
                 self.get_migrator(
                     Options {
-                        allow_deletions: true,
                         dry_run: false,
+                        ..options
                     },
                     target_db,
                 )?
@@ -638,8 +1040,8 @@ impl App {
                 self.init_logger();
                 self.get_migrator(
                     Options {
-                        allow_deletions: true,
                         dry_run: true,
+                        ..options
                     },
                     target_db,
                 )?
@@ -648,18 +1050,245 @@ impl App {
             Migrate::Script => {
                 self.get_migrator(
                     Options {
-                        allow_deletions: true,
                         dry_run: true,
+                        ..options
                     },
                     target_db,
                 )?
-                .migrate_with_callback(|statement| self.write(&statement).unwrap())?;
+                // Statements are terminated so the output can be passed to the apply command
+                .migrate_with_callback(|statement| {
+                    let statement = statement.sql.trim_end();
+                    if statement.ends_with(';') || statement.starts_with("--") {
+                        self.write(statement).unwrap();
+                    } else {
+                        self.write(&format!("{statement};")).unwrap();
+                    }
+                })?;
             }
         }
         Ok(())
     }
 
-    fn print_schema(&mut self, mut migrator: Migrator, from: &SchemaType) -> Result<(), Report> {
+    fn handle_apply_command(&mut self, script: &Path, target_db: Connection) -> Result<(), Report> {
+        if self.cli.read_only {
+            return Err(color_eyre::eyre::eyre!(
+                "Cannot apply a script to a read-only target"
+            ));
+        }
+        let script = if script == Path::new(STDIN_SOURCE) {
+            io::read_to_string(io::stdin())?
+        } else {
+            fs::read_to_string(script)?
+        };
+        self.init_logger();
+        Migrator::apply_script(&script, target_db, self.config.clone(), Options::default())?;
+        Ok(())
+    }
+
+    fn handle_multi_target_migrate(
+        &mut self,
+        migrate: &Migrate,
+        yes: bool,
+        options: Options,
+    ) -> Result<ExitCode, Report> {
+        let targets = read_target_dir(&self.target)?;
+        if targets.is_empty() {
+            return Err(color_eyre::eyre::eyre!(
+                "No databases found in {}",
+                self.target.display()
+            ));
+        }
+        let dry_run = matches!(migrate, Migrate::DryRun);
+        if !dry_run {
+            if self.cli.read_only {
+                return Err(color_eyre::eyre::eyre!(
+                    "Cannot run a migration against a read-only target"
+                ));
+            }
+            if !yes && !self.confirm_migration()? {
+                println!("Migration cancelled");
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
+        self.init_logger();
+
+        // The schema is only loaded once and each target's migrator gets its own copy of it
+        let mut source_migrator =
+            self.get_migrator(options.clone(), Connection::open_in_memory()?)?;
+        // Each database gets its own migrator so a failure doesn't affect the remaining targets
+        let results: Vec<_> = targets
+            .into_iter()
+            .map(|target| {
+                let result = Connection::open_with_flags(&target, self.open_flags())
+                    .map_err(Report::from)
+                    .and_then(|target_db| {
+                        source_migrator
+                            .for_target(
+                                target_db,
+                                Options {
+                                    dry_run,
+                                    ..options.clone()
+                                },
+                            )?
+                            .migrate()?;
+                        Ok(())
+                    });
+                (target, result)
+            })
+            .collect();
+
+        let mut summary = String::new();
+        for (target, result) in &results {
+            match result {
+                Ok(()) => writeln!(summary, "{} {}", "ok".green(), target.display())?,
+                Err(e) => writeln!(summary, "{} {}: {e}", "failed".red(), target.display())?,
+            }
+        }
+        let failures = results.iter().filter(|(_, r)| r.is_err()).count();
+        write!(
+            summary,
+            "{} of {} databases migrated successfully",
+            results.len() - failures,
+            results.len()
+        )?;
+        self.write(&summary)?;
+
+        Ok(if failures == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+
+    fn confirm_migration(&self) -> Result<bool, Report> {
+        if !io::stdin().is_terminal() {
+            return Err(color_eyre::eyre::eyre!(
+                "Refusing to migrate without confirmation, pass --yes to run non-interactively"
+            ));
+        }
+        print!("Run database migration? [y/N] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    fn handle_diff_command(
+        &mut self,
+        format: &DiffFormat,
+        target_db: Connection,
+    ) -> Result<bool, Report> {
+        let mut migrator = Migrator::new_read_only(&self.schema, target_db, self.config.clone())?;
+        // Changes are detected the same way for every format so the exit code doesn't depend on
+        // how the diff is printed
+        let changes = object_changes(&migrator.parse_metadata()?, &self.config);
+        let has_changes = changes
+            .iter()
+            .any(|c| c.change_kind != ChangeKind::Unchanged);
+        match format {
+            DiffFormat::Text => self.write(&migrator.diff()?)?,
+            DiffFormat::Json => self.write(&serde_json::to_string_pretty(&changes)?)?,
+        }
+        Ok(has_changes)
+    }
+
+    fn watch_diff(&mut self, format: &DiffFormat, target_db: Connection) -> Result<(), Report> {
+        if self.source.iter().any(|s| s == Path::new(STDIN_SOURCE)) {
+            return Err(color_eyre::eyre::eyre!(
+                "Reading the source schema from stdin is not supported with --watch"
+            ));
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut debouncer = new_debouncer(Duration::from_millis(250), move |events| {
+            if let Ok(events) = events {
+                let _ = tx.send(events);
+            }
+        })?;
+        for path in self.source.iter().chain([&self.target]) {
+            if path.exists() {
+                debouncer.watcher().watch(path, RecursiveMode::Recursive)?;
+            }
+        }
+
+        let mut target_db = Some(target_db);
+        loop {
+            execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+            let target_db = match target_db.take() {
+                Some(target_db) => target_db,
+                None => self.open_target()?,
+            };
+            if let Err(e) = self.handle_diff_command(format, target_db) {
+                eprintln!("{e:?}");
+            }
+            if rx.recv().is_err() {
+                return Ok(());
+            }
+            if self.cli.source_inline.is_some() {
+                continue;
+            }
+            match read_schema(&self.source) {
+                Ok(schema) => self.schema = schema,
+                Err(e) => eprintln!("{e:?}"),
+            }
+        }
+    }
+
+    fn handle_status_command(&mut self, target_db: Connection) -> Result<bool, Report> {
+        let mut migrator = Migrator::new_read_only(&self.schema, target_db, self.config.clone())?;
+        let metadata = migrator.parse_metadata()?;
+        let summary = metadata.source.diff_summary(&metadata.target, &self.config);
+        let mut drifted = false;
+        for (object_type, changes) in &summary {
+            let label = match object_type {
+                ObjectType::Table => "Tables",
+                ObjectType::Index => "Indexes",
+                ObjectType::View => "Views",
+                ObjectType::Trigger => "Triggers",
+            };
+            if changes.is_empty() {
+                self.write(&format!("{label}: {}", "in sync".green()))?;
+                continue;
+            }
+            drifted = true;
+            let count = |kind: ChangeKind| changes.iter().filter(|(_, c)| *c == kind).count();
+            let names = changes
+                .iter()
+                .map(|(name, change_kind)| match change_kind {
+                    ChangeKind::Added => format!("+{name}").green().to_string(),
+                    ChangeKind::Removed => format!("-{name}").red().to_string(),
+                    ChangeKind::Modified | ChangeKind::Unchanged => {
+                        format!("~{name}").yellow().to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.write(&format!(
+                "{label}: {} created, {} dropped, {} modified ({names})",
+                count(ChangeKind::Added),
+                count(ChangeKind::Removed),
+                count(ChangeKind::Modified),
+            ))?;
+        }
+        if drifted {
+            self.write(&"Target is out of sync with the source".yellow().to_string())?;
+        } else {
+            self.write(&"Target is in sync with the source".green().to_string())?;
+        }
+        Ok(drifted)
+    }
+
+    fn print_schema(
+        &mut self,
+        mut migrator: Migrator,
+        from: &SchemaType,
+        with_comments: bool,
+    ) -> Result<(), Report> {
+        if with_comments && matches!(from, SchemaType::Target) {
+            return Err(color_eyre::eyre::eyre!(
+                "--with-comments can only be used when printing the source"
+            ));
+        }
         let mut sql_printer = SqlPrinter::default();
         let metadata = migrator.parse_metadata()?;
         let source = match from {
@@ -667,12 +1296,34 @@ impl App {
             SchemaType::Target => metadata.target,
         };
         for object in source.all_objects() {
-            self.write(&sql_printer.print(&object.sql))?;
+            let sql = if with_comments {
+                migrator
+                    .source_definition(&object.name)
+                    .unwrap_or(&object.sql)
+            } else {
+                &object.sql
+            };
+            self.write(&sql_printer.print(sql))?;
         }
 
         Ok(())
     }
 
+    fn handle_dump_schema_command(&self, out: Option<PathBuf>) -> Result<(), Report> {
+        // Only the source is needed, so there's no reason to touch the target
+        let mut migrator = Migrator::new_read_only(
+            &self.schema,
+            Connection::open_in_memory()?,
+            self.config.clone(),
+        )?;
+        let dump = migrator.parse_metadata()?.source.dump();
+        match out {
+            Some(out) => fs::write(out, dump)?,
+            None => io::stdout().write_all(dump.as_bytes())?,
+        }
+        Ok(())
+    }
+
     fn handle_config_command(&self, config: &AppConfig) -> Result<(), Report> {
         match config {
             AppConfig::Generate => match Path::new("slite.toml").try_exists() {
@@ -694,8 +1345,11 @@ impl App {
     async fn run_tui(self) -> Result<(), Report> {
         BroadcastWriter::disable();
 
-        let (filter, reload_handle) =
-            reload::Layer::new(Targets::default().with_target("slite", self.log_level));
+        let (filter, reload_handle) = reload::Layer::new(
+            Targets::default()
+                .with_target("slite", self.log_level)
+                .with_target(FIELDS_TARGET, LevelFilter::OFF),
+        );
         Registry::default()
             .with(
                 HierarchicalLayer::default()
@@ -706,9 +1360,20 @@ impl App {
             )
             .init();
 
+        let open_flags = self.open_flags();
+        let migrator_factory = match self.target_uri {
+            Some(target_uri) => MigratorFactory::new(self.source, target_uri, self.config)?
+                .with_open_flags(open_flags | OpenFlags::SQLITE_OPEN_URI),
+            None => MigratorFactory::new(self.source, self.target, self.config)?
+                .with_open_flags(open_flags),
+        };
+        let config_path = self
+            .config_path
+            .unwrap_or_else(|| PathBuf::from("slite.toml"));
         app_tui::run_tui(
-            MigratorFactory::new(self.source, self.target, self.config)?,
+            migrator_factory,
             self.cli_config,
+            config_path,
             reload_handle,
         )
         .await?;
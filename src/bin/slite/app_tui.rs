@@ -1,5 +1,6 @@
 use color_eyre::{eyre, Report};
 use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{
         disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
@@ -21,7 +22,7 @@ use crate::app::{Conf, ConfigStore};
 
 pub enum TuiAppMessage {
     PathChanged(Option<PathBuf>, Option<PathBuf>),
-    SourceChanged(PathBuf, PathBuf),
+    SourceChanged(Vec<PathBuf>, Vec<PathBuf>),
     TargetChanged(PathBuf, PathBuf),
     ConfigCreated(ReloadableConfig<Conf>),
 }
@@ -31,6 +32,7 @@ struct TuiApp<'a, B: Backend> {
     state: AppState<'a>,
     reload_handle: Option<Handle<Targets, Registry>>,
     cli_config: Option<Conf>,
+    config_path: PathBuf,
     config: Option<ReloadableConfig<Conf>>,
     _phantom: PhantomData<B>,
 }
@@ -40,11 +42,13 @@ impl<'a, B: Backend> TuiApp<'a, B> {
         migrator_factory: MigratorFactory,
         reload_handle: Handle<Targets, Registry>,
         cli_config: Conf,
+        config_path: PathBuf,
     ) -> Result<TuiApp<'a, B>, Report> {
         Ok(TuiApp {
             state: AppState::new(migrator_factory)?,
             reload_handle: Some(reload_handle),
             cli_config: Some(cli_config),
+            config_path,
             config: None,
             _phantom: Default::default(),
         })
@@ -54,15 +58,21 @@ impl<'a, B: Backend> TuiApp<'a, B> {
 pub async fn run_tui(
     migrator_factory: MigratorFactory,
     cli_config: Conf,
+    config_path: PathBuf,
     reload_handle: Handle<Targets, Registry>,
 ) -> Result<(), Report> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, SetTitle("Slite"))?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        SetTitle("Slite")
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let app = TuiApp::new(migrator_factory, reload_handle, cli_config)?;
+    let app = TuiApp::new(migrator_factory, reload_handle, cli_config, config_path)?;
     let program = Program::new(app);
 
     program
@@ -71,7 +81,11 @@ pub async fn run_tui(
         .map_err(|e| eyre::eyre!("{e}"))?;
 
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
 
     Ok(())
@@ -85,10 +99,11 @@ impl<'a, B: Backend> Model for TuiApp<'a, B> {
     fn init(&mut self) -> Result<OptionalCommand, Self::Error> {
         let cli_config = self.cli_config.take().unwrap();
         let reload_handle = self.reload_handle.take().unwrap();
+        let config_path = self.config_path.clone();
 
         let config_cmd = Command::new_blocking(|tx, _| {
             let handler = ConfigStore::new(cli_config, tx, reload_handle);
-            let config = ReloadableConfig::new(PathBuf::from("slite.toml"), handler);
+            let config = ReloadableConfig::new(config_path, handler);
             Some(Message::custom(TuiAppMessage::ConfigCreated(config)))
         });
 
@@ -111,11 +126,16 @@ impl<'a, B: Backend> Model for TuiApp<'a, B> {
                         config.switch_path(previous.as_deref(), current.as_deref());
                     }
                     (
-                        TuiAppMessage::SourceChanged(previous_source, current_source),
+                        TuiAppMessage::SourceChanged(previous_sources, current_sources),
                         Some(config),
                     ) => {
-                        config.switch_path(Some(previous_source), Some(current_source));
-                        self.state.set_schema_dir(current_source.clone())?;
+                        for previous_source in previous_sources {
+                            config.switch_path(Some(previous_source), None);
+                        }
+                        for current_source in current_sources {
+                            config.switch_path(None, Some(current_source));
+                        }
+                        self.state.set_schema_dirs(current_sources.clone())?;
                     }
                     (
                         TuiAppMessage::TargetChanged(previous_target, current_target),
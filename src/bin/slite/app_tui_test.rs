@@ -1,6 +1,7 @@
 use crate::{app::Conf, app_tui::TuiApp};
 use confique::Config;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
+use elm_ui::Message;
 use elm_ui_tester::{TerminalView, UiTester};
 use ratatui::{
     backend::TestBackend,
@@ -8,11 +9,14 @@ use ratatui::{
     style::{Color, Modifier},
     Terminal,
 };
+use rusqlite::Connection;
 use serial_test::serial;
 use slite::{
     read_extension_dir, read_sql_files,
-    tui::{BroadcastWriter, MigratorFactory},
+    tui::{BroadcastWriter, MigratorFactory, MEMORY_TARGET},
+    ExtensionSpec, IgnoreRules, Migrator, ObjectType, Options, FIELDS_TARGET,
 };
+use std::path::PathBuf;
 use tempfile::TempDir;
 use tracing::metadata::LevelFilter;
 use tracing_subscriber::{filter::Targets, prelude::*, reload, Layer, Registry};
@@ -87,6 +91,185 @@ async fn test_view_target() {
     });
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_split_view() {
+    let (tester, _tempdir) = setup(80, 50);
+    for _ in 0..2 {
+        tester
+            .send_key(KeyEvent::new(KeyCode::Right, KeyModifiers::empty()))
+            .await;
+    }
+    tester
+        .wait_for(|term| term.terminal_view().contains("╭SQL"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| {
+            let view = term.terminal_view();
+            view.contains("╭Source") && view.contains("╭Target") && !view.contains("╭SQL")
+        })
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    // Toggling again goes back to the unified diff
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| term.terminal_view().contains("╭SQL"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    let (_, view) = tester.wait_for_completion().unwrap();
+    assert!(!view.terminal_view().contains("╭Target"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_copy_sql() {
+    let (tester, _tempdir) = setup(80, 50);
+    tester
+        .wait_for(|term| term.terminal_view().contains("CREATE TABLE album"))
+        .await
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::empty()))
+        .await;
+    // The test environment may not have a clipboard, but a status is shown either way
+    tester
+        .wait_for(|term| {
+            let view = term.terminal_view();
+            view.contains("Copied to clipboard") || view.contains("Clipboard unavailable")
+        })
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    // The status is cleared on the next key press
+    tester
+        .send_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| {
+            let view = term.terminal_view();
+            !view.contains("Copied to clipboard") && !view.contains("Clipboard unavailable")
+        })
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    tester.wait_for_completion().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_mouse_scroll() {
+    // Keep the terminal short enough that the SQL doesn't fit in the pane
+    let (tester, _tempdir) = setup(80, 12);
+    tester
+        .wait_for(|term| term.terminal_view().contains("CREATE TABLE album"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_msg(mouse_event(MouseEventKind::ScrollDown))
+        .await;
+    tester
+        .wait_for(|term| {
+            let view = term.terminal_view();
+            !view.contains("CREATE TABLE album") && view.contains("album_id INTEGER")
+        })
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester.send_msg(mouse_event(MouseEventKind::ScrollUp)).await;
+    tester
+        .wait_for(|term| term.terminal_view().contains("CREATE TABLE album"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    tester.wait_for_completion().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_help() {
+    let (tester, _tempdir) = setup(80, 50);
+    tester
+        .wait_for(|term| term.terminal_view().contains("album"))
+        .await
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('?'), KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| term.terminal_view().contains("Filter objects"))
+        .await
+        .unwrap();
+    // Navigation keys are ignored while the help is open
+    tester
+        .send_key(KeyEvent::new(KeyCode::Right, KeyModifiers::empty()))
+        .await;
+    tester
+        .send_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| !term.terminal_view().contains("Filter objects"))
+        .await
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    let (_, view) = tester.wait_for_completion().unwrap();
+    assert_eq!(Color::White, view.get(5, 1).fg);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_dismiss_migrate_popup() {
+    let (tester, _tempdir) = setup(80, 50);
+    for _ in 0..3 {
+        tester
+            .send_key(KeyEvent::new(KeyCode::Right, KeyModifiers::empty()))
+            .await;
+    }
+    tester
+        .wait_for(|term| term.terminal_view().contains("Controls"))
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        tester
+            .send_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()))
+            .await;
+    }
+    tester
+        .send_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| term.terminal_view().contains("Run database migration?"))
+        .await
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| !term.terminal_view().contains("Run database migration?"))
+        .await
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    let (_, view) = tester.wait_for_completion().unwrap();
+    assert!(!view.terminal_view().contains("Migration completed"));
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn test_dry_run() {
@@ -175,10 +358,140 @@ async fn test_generate_script() {
     });
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn test_save_script() {
+    let (tester, tempdir) = setup(100, 60);
+    for _ in 0..3 {
+        tester
+            .send_key(KeyEvent::new(KeyCode::Right, KeyModifiers::empty()))
+            .await;
+    }
+    tester
+        .wait_for(|term| term.terminal_view().contains("Controls"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    for _ in 0..2 {
+        tester
+            .send_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()))
+            .await;
+    }
+    tester
+        .send_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()))
+        .await;
+    tester
+        .wait_for(|term| term.terminal_view().contains("Migration script saved to"))
+        .await
+        .map_err(|e| e.terminal_view())
+        .unwrap();
+    tester
+        .send_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::empty()))
+        .await;
+    tester.wait_for_completion().unwrap();
+
+    let script_path = std::fs::read_dir(".")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("migration-") && name.ends_with(".sql")
+        })
+        .max()
+        .unwrap();
+    let script = std::fs::read_to_string(&script_path).unwrap();
+    std::fs::remove_file(&script_path).unwrap();
+    assert!(!script.contains('\u{1b}'));
+
+    let (conf, config) = load_config();
+    let target = tempdir.path().join("test.db");
+    Migrator::apply_script(
+        &script,
+        Connection::open(&target).unwrap(),
+        config.clone(),
+        Options::default(),
+    )
+    .unwrap();
+    let factory = MigratorFactory::new(conf.source.unwrap(), target, config.clone()).unwrap();
+    let metadata = factory.metadata();
+    assert!(metadata
+        .source
+        .structurally_equal(&metadata.target, &config));
+}
+
+#[test]
+fn test_memory_target() {
+    let source_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        source_dir.path().join("schema.sql"),
+        "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);",
+    )
+    .unwrap();
+    let mut factory = MigratorFactory::new(
+        vec![source_dir.path().to_path_buf()],
+        MEMORY_TARGET,
+        slite::Config::default(),
+    )
+    .unwrap();
+    assert!(factory.metadata().target.tables().is_empty());
+
+    factory
+        .create_migrator(Options::default())
+        .unwrap()
+        .migrate()
+        .unwrap();
+    factory.update_schemas().unwrap();
+    assert_eq!(
+        vec!["Item"],
+        factory
+            .metadata()
+            .target
+            .tables()
+            .keys()
+            .collect::<Vec<_>>()
+    );
+    assert!(!PathBuf::from(MEMORY_TARGET).exists());
+}
+
+#[test]
+fn test_metadata_diff() {
+    let source_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        source_dir.path().join("schema.sql"),
+        "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);",
+    )
+    .unwrap();
+    let mut factory = MigratorFactory::new(
+        vec![source_dir.path().to_path_buf()],
+        MEMORY_TARGET,
+        slite::Config::default(),
+    )
+    .unwrap();
+    let item_unchanged = |factory: &MigratorFactory| {
+        factory.metadata_diff()[&ObjectType::Table]["Item"]
+            .hunks
+            .is_empty()
+    };
+    assert!(!item_unchanged(&factory));
+
+    factory
+        .create_migrator(Options::default())
+        .unwrap()
+        .migrate()
+        .unwrap();
+    // The cached diff is only recomputed on refresh
+    assert!(!item_unchanged(&factory));
+    factory.update_schemas().unwrap();
+    assert!(item_unchanged(&factory));
+}
+
 fn setup<'a>(width: u16, height: u16) -> (UiTester<TuiApp<'a, TestBackend>, Buffer>, TempDir) {
     BroadcastWriter::disable();
-    let (filter, reload_handle) =
-        reload::Layer::new(Targets::default().with_target("slite", LevelFilter::INFO));
+    let (filter, reload_handle) = reload::Layer::new(
+        Targets::default()
+            .with_target("slite", LevelFilter::INFO)
+            .with_target(FIELDS_TARGET, LevelFilter::OFF),
+    );
     Registry::default()
         .with(
             HierarchicalLayer::default()
@@ -190,27 +503,14 @@ fn setup<'a>(width: u16, height: u16) -> (UiTester<TuiApp<'a, TestBackend>, Buff
         )
         .try_init()
         .ok();
-    let mut conf = Conf::builder().file("./test/slite.toml").load().unwrap();
-    let extensions = conf
-        .extension_dir
-        .map(read_extension_dir)
-        .unwrap()
-        .unwrap_or_default();
+    let (mut conf, config) = load_config();
     let tempdir = tempfile::tempdir().unwrap();
     conf.target = Some(tempdir.path().join("test.db"));
-    let ignore = conf.ignore.map(|i| i.0);
-    let before_migration = conf.pre_migration.map(read_sql_files).unwrap_or_default();
-    let after_migration = conf.post_migration.map(read_sql_files).unwrap_or_default();
-    let config = slite::Config {
-        extensions,
-        ignore,
-        before_migration,
-        after_migration,
-    };
     let app = TuiApp::<TestBackend>::new(
         MigratorFactory::new(conf.source.unwrap(), conf.target.unwrap(), config).unwrap(),
         reload_handle,
         Conf::default(),
+        PathBuf::from("slite.toml"),
     )
     .unwrap();
     let backend = TestBackend::new(width, height);
@@ -218,3 +518,53 @@ fn setup<'a>(width: u16, height: u16) -> (UiTester<TuiApp<'a, TestBackend>, Buff
     let tester = UiTester::new_tui(app, terminal);
     (tester, tempdir)
 }
+
+fn mouse_event(kind: MouseEventKind) -> Message {
+    Message::TermEvent(Event::Mouse(MouseEvent {
+        kind,
+        column: 40,
+        row: 5,
+        modifiers: KeyModifiers::empty(),
+    }))
+}
+
+fn load_config() -> (Conf, slite::Config) {
+    let mut conf = Conf::builder().file("./test/slite.toml").load().unwrap();
+    let extensions = conf
+        .extension_dir
+        .take()
+        .map(read_extension_dir)
+        .unwrap()
+        .unwrap_or_default()
+        .into_iter()
+        .map(ExtensionSpec::Path)
+        .collect();
+    let ignore = conf
+        .ignore
+        .take()
+        .map(|i| IgnoreRules::from(i.0))
+        .unwrap_or_default();
+    let before_migration = conf
+        .pre_migration
+        .take()
+        .map(read_sql_files)
+        .unwrap_or_default();
+    let after_migration = conf
+        .post_migration
+        .take()
+        .map(read_sql_files)
+        .unwrap_or_default();
+    let config = slite::Config {
+        extensions,
+        ignore,
+        only: None,
+        only_objects: Vec::new(),
+        before_migration,
+        after_migration,
+        attachments: Vec::new(),
+        treat_hooks_as_schema: true,
+        target_pragmas: Vec::new(),
+        sql_normalizer: None,
+    };
+    (conf, config)
+}
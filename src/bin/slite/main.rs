@@ -3,9 +3,12 @@ mod app;
 #[cfg(feature = "application")]
 mod app_tui;
 
+#[cfg(feature = "application")]
+use std::process::ExitCode;
+
 #[cfg(feature = "application")]
 #[tokio::main]
-pub async fn main() -> Result<(), color_eyre::eyre::Report> {
+pub async fn main() -> Result<ExitCode, color_eyre::eyre::Report> {
     let app = app::App::from_args()?;
     app.run().await
 }
@@ -1,10 +1,15 @@
 use rusqlite::{
-    types::FromSql, Connection, LoadExtensionGuard, Params, Row, Transaction, TransactionBehavior,
+    backup::Backup, types::FromSql, Connection, DatabaseName, LoadExtensionGuard, Params, Row,
+    Transaction,
 };
-use std::{fmt::Display, path::PathBuf};
-use tracing::{debug, span, trace, warn, Level};
+use std::{fmt::Display, iter, path::Path, time::Duration};
+use tracing::{debug, info, span, trace, warn, Level};
 
-use crate::{InitializationError, Metadata, MigrationError, QueryError, Settings, SqlPrinter};
+use crate::{
+    ExtensionSpec, InitializationError, Metadata, MigrationError, MigrationProgress, Phase,
+    QueryError, ScriptEvent, Settings, SqlPrinter, SqlScript, SqlSource, ValidationError,
+    FIELDS_TARGET,
+};
 
 macro_rules! event {
     ($level:expr, $($args:tt)*) => {{
@@ -28,7 +33,23 @@ impl PristineConnection {
     pub fn new(settings: Settings) -> Result<Self, InitializationError> {
         let connection = Connection::open_in_memory()
             .map_err(|e| InitializationError::ConnectionFailure(":memory:".to_owned(), e))?;
-        load_extensions(&connection, &settings.config.extensions).unwrap();
+        Self::from_connection(connection, settings)
+    }
+
+    pub fn from_connection(
+        connection: Connection,
+        settings: Settings,
+    ) -> Result<Self, InitializationError> {
+        load_extensions(&connection, &settings.config.extensions)?;
+        // Attached schemas are created from scratch in memory so the real files are never touched
+        attach_databases(
+            &connection,
+            settings
+                .config
+                .attachments
+                .iter()
+                .map(|(alias, _)| (alias.as_str(), Path::new(":memory:"))),
+        )?;
 
         Ok(Self {
             connection,
@@ -37,7 +58,7 @@ impl PristineConnection {
         })
     }
 
-    pub fn initialize_schema<S: AsRef<str>>(
+    pub fn initialize_schema<S: SqlSource>(
         &mut self,
         schema: impl IntoIterator<Item = S>,
     ) -> Result<(), InitializationError> {
@@ -48,8 +69,12 @@ impl PristineConnection {
             self.connection
                 .execute_batch(definition.as_ref())
                 .map_err(|e| {
+                    let msg = match definition.path() {
+                        Some(path) => format!("Error creating schema from {path:?}"),
+                        None => "Error creating schema".to_owned(),
+                    };
                     InitializationError::QueryFailure(
-                        "Error creating schema".to_owned(),
+                        msg,
                         QueryError(definition.as_ref().to_owned(), e),
                     )
                 })?;
@@ -57,6 +82,65 @@ impl PristineConnection {
         Ok(())
     }
 
+    // The copy is made with the backup API, which doesn't carry over connection settings like
+    // foreign_keys, so those are copied separately
+    pub fn try_clone(&mut self, settings: Settings) -> Result<Self, InitializationError> {
+        let connection = Connection::open_in_memory()
+            .map_err(|e| InitializationError::ConnectionFailure(":memory:".to_owned(), e))?;
+        let mut copy = Self::from_connection(connection, settings)?;
+        let schemas = iter::once(DatabaseName::Main).chain(
+            self.settings
+                .config
+                .attachments
+                .iter()
+                .map(|(alias, _)| DatabaseName::Attached(alias)),
+        );
+        for schema in schemas {
+            Backup::new_with_names(&self.connection, schema, &mut copy.connection, schema)
+                .and_then(|backup| backup.run_to_completion(100, Duration::ZERO, None))
+                .map_err(|e| InitializationError::ConnectionFailure(":memory:".to_owned(), e))?;
+        }
+        let foreign_keys = self.get_pragma::<i32>("foreign_keys").map_err(|e| {
+            InitializationError::QueryFailure(
+                "Failed to retrieve foreign_keys pragma".to_owned(),
+                e,
+            )
+        })?;
+        copy.connection
+            .execute_batch(&format!("PRAGMA foreign_keys = {foreign_keys}"))
+            .map_err(|e| {
+                InitializationError::QueryFailure(
+                    "Failed to set foreign_keys pragma".to_owned(),
+                    QueryError(format!("PRAGMA foreign_keys = {foreign_keys}"), e),
+                )
+            })?;
+        Ok(copy)
+    }
+
+    pub fn validate_schema<S: SqlSource>(
+        &mut self,
+        schema: impl IntoIterator<Item = S>,
+    ) -> Result<(), InitializationError> {
+        let validate_span = span!(Level::TRACE, "Validating schema");
+        let _guard = validate_span.entered();
+        let mut errors = vec![];
+        // Keep going after failures so every broken definition is reported at once
+        for definition in schema {
+            trace!("\n{}", self.sql_printer.print(definition.as_ref()));
+            if let Err(e) = self.connection.execute_batch(definition.as_ref()) {
+                errors.push(ValidationError {
+                    path: definition.path().map(|p| p.to_path_buf()),
+                    error: QueryError(definition.as_ref().to_owned(), e),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InitializationError::ValidationFailure(errors))
+        }
+    }
+
     pub fn get_pragma<T: FromSql>(&mut self, pragma: &str) -> Result<T, QueryError> {
         get_pragma(
             &self.connection,
@@ -72,14 +156,16 @@ impl PristineConnection {
             &self.connection,
             Level::TRACE,
             "Executing query against reference database",
-            &self.settings.config.ignore,
+            &self.settings.config,
             &mut self.sql_printer,
         )
     }
 
     pub fn get_cols(&mut self, table: &str) -> Result<Vec<String>, QueryError> {
+        let (schema, table) = self.settings.config.split_schema(table);
         get_cols(
             &self.connection,
+            schema,
             table,
             Level::TRACE,
             "Executing query against reference database",
@@ -90,85 +176,119 @@ impl PristineConnection {
 
 pub(crate) struct TargetTransaction<'conn, F>
 where
-    F: FnMut(String),
+    F: FnMut(ScriptEvent),
 {
     transaction: Transaction<'conn>,
     sql_printer: SqlPrinter,
-    modified: bool,
+    initial_changes: ChangeCounters,
     on_script: F,
+    format_script: bool,
+    on_progress: Box<dyn FnMut(MigrationProgress) + 'conn>,
+    progress: MigrationProgress,
     settings: Settings,
 }
 
 impl<'conn, F> TargetTransaction<'conn, F>
 where
-    F: FnMut(String),
+    F: FnMut(ScriptEvent),
 {
     pub fn new(
         target_connection: &'conn mut TargetConnection,
         settings: Settings,
         on_script: F,
+        format_script: bool,
+        on_progress: impl FnMut(MigrationProgress) + 'conn,
     ) -> Result<Self, MigrationError> {
         let transaction = target_connection
             .connection
-            .transaction_with_behavior(TransactionBehavior::Exclusive)
+            .transaction_with_behavior(settings.options.transaction_behavior.into())
             .map_err(MigrationError::TransactionInitializationFailure)?;
+        let mut sql_printer = SqlPrinter::default();
+        let initial_changes =
+            ChangeCounters::read(&transaction, &mut sql_printer).map_err(|e| {
+                MigrationError::QueryFailure("Failed to read change counters".to_owned(), e)
+            })?;
         Ok(Self {
             transaction,
-            sql_printer: SqlPrinter::default(),
-            modified: false,
+            sql_printer,
+            initial_changes,
             on_script,
+            format_script,
+            on_progress: Box::new(on_progress),
+            progress: MigrationProgress {
+                phase: Phase::Tables,
+                completed: 0,
+                total: 0,
+                current_object: None,
+            },
             settings,
         })
     }
 
     pub fn execute(&mut self, sql: &str) -> Result<(), QueryError> {
-        let formatted_sql = self.sql_printer.print(sql);
-        debug!("\n\t{formatted_sql}");
-        (self.on_script)(formatted_sql);
-
-        let normalized = sql.trim().to_uppercase();
-        if normalized.starts_with("DROP")
-            || normalized.starts_with("ALTER")
-            || normalized.starts_with("INSERT")
-            || normalized.starts_with("CREATE")
-        {
-            self.modified = true;
-        }
-
-        if !self.settings.options.dry_run {
-            let rows = self
-                .transaction
-                .execute(sql, [])
-                .map_err(|e| QueryError(sql.to_owned(), e))?;
+        self.execute_with_rows(sql).map(|_| ())
+    }
 
-            if rows > 0 {
-                debug!("Query affected {rows} row(s)");
-            }
+    /// Returns the number of rows changed by the statement, or None for dry runs
+    pub fn execute_with_rows(&mut self, sql: &str) -> Result<Option<usize>, QueryError> {
+        let result = if self.settings.options.dry_run {
+            Ok(None)
+        } else {
+            self.transaction.execute(sql, []).map(Some)
+        };
+        self.emit_script_event(sql, result.as_ref().ok().copied().flatten());
+
+        let rows = result.map_err(|e| QueryError(sql.to_owned(), e))?;
+        if let Some(rows) = rows.filter(|rows| *rows > 0) {
+            let _fields = span!(target: FIELDS_TARGET, Level::DEBUG, "query", rows).entered();
+            debug!("Query affected {rows} row(s)");
         }
 
-        Ok(())
+        Ok(rows)
     }
 
-    pub fn execute_batch(&mut self, statements: &Vec<String>) -> Result<(), QueryError> {
+    pub fn execute_batch(&mut self, statements: &[SqlScript]) -> Result<(), QueryError> {
         for statement in statements {
-            let formatted_sql = self.sql_printer.print(statement);
-            debug!("\n\t{formatted_sql}");
-            (self.on_script)(formatted_sql);
+            self.emit_script(statement.as_ref());
             if !self.settings.options.dry_run {
                 self.transaction
-                    .execute_batch(statement)
-                    .map_err(|e| QueryError(statement.to_string(), e))?;
+                    .execute_batch(statement.as_ref())
+                    .map_err(|e| QueryError(statement.sql.clone(), e))?;
             }
         }
         Ok(())
     }
 
+    pub fn emit_comment(&mut self, comment: &str) {
+        if self.settings.options.script_comments {
+            (self.on_script)(ScriptEvent {
+                sql: format!("-- {comment}"),
+                rows_affected: None,
+            });
+        }
+    }
+
+    pub fn emit_script(&mut self, sql: &str) {
+        self.emit_script_event(sql, None);
+    }
+
+    fn emit_script_event(&mut self, sql: &str, rows_affected: Option<usize>) {
+        let formatted_sql = self.sql_printer.print(sql);
+        debug!("\n\t{formatted_sql}");
+        let sql = if self.format_script {
+            formatted_sql
+        } else {
+            sql.to_owned()
+        };
+        (self.on_script)(ScriptEvent { sql, rows_affected });
+    }
+
     pub fn parse_metadata(&mut self) -> Result<Metadata, QueryError> {
         Metadata::parse(
             &self.transaction,
             Level::DEBUG,
             "",
-            &self.settings.config.ignore,
+            &self.settings.config,
             &mut self.sql_printer,
         )
     }
@@ -188,8 +308,10 @@ where
     }
 
     pub fn get_cols(&mut self, table: &str) -> Result<Vec<String>, QueryError> {
+        let (schema, table) = self.settings.config.split_schema(table);
         get_cols(
             &self.transaction,
+            schema,
             table,
             Level::DEBUG,
             "",
@@ -197,8 +319,74 @@ where
         )
     }
 
-    pub fn modified(&self) -> bool {
-        self.modified
+    pub fn get_triggers(&mut self, table: &str) -> Result<Vec<String>, QueryError> {
+        self.get_table_objects("trigger", table)
+    }
+
+    pub fn get_indexes(&mut self, table: &str) -> Result<Vec<String>, QueryError> {
+        self.get_table_objects("index", table)
+    }
+
+    fn get_table_objects(
+        &mut self,
+        object_type: &str,
+        table: &str,
+    ) -> Result<Vec<String>, QueryError> {
+        let (schema, table) = self.settings.config.split_schema(table);
+        let objects: Vec<String> = query_params(
+            &self.transaction,
+            &format!("SELECT name FROM {schema}.sqlite_master WHERE type = ?1 AND tbl_name = ?2 AND sql IS NOT NULL ORDER BY name"),
+            [object_type, table],
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+            |row| row.get(0),
+        )?;
+        if schema == "main" {
+            Ok(objects)
+        } else {
+            Ok(objects
+                .into_iter()
+                .map(|object| format!("{schema}.{object}"))
+                .collect())
+        }
+    }
+
+    // COUNT(*) has to scan the whole table, so the largest rowid is used as a cheap estimate
+    // instead. Tables without a rowid don't have an estimate.
+    pub fn estimate_rows(&mut self, table: &str) -> Option<i64> {
+        query_single(
+            &self.transaction,
+            &format!("SELECT MAX(rowid) FROM {table}"),
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .ok()
+        .flatten()
+    }
+
+    /// Whether any statement run so far changed the schema or table contents
+    pub fn modified(&mut self) -> Result<bool, QueryError> {
+        let changes = ChangeCounters::read(&self.transaction, &mut self.sql_printer)?;
+        Ok(changes != self.initial_changes)
+    }
+
+    pub fn start_phase(&mut self, phase: Phase, total: usize) {
+        self.progress = MigrationProgress {
+            phase,
+            completed: 0,
+            total,
+            current_object: None,
+        };
+        (self.on_progress)(self.progress.clone());
+    }
+
+    pub fn advance_progress(&mut self, object: &str) {
+        self.progress.completed += 1;
+        self.progress.current_object = Some(object.to_owned());
+        (self.on_progress)(self.progress.clone());
     }
 
     pub fn commit(self) -> Result<(), MigrationError> {
@@ -224,13 +412,39 @@ pub(crate) struct TargetConnection {
 }
 
 impl TargetConnection {
-    pub fn new(connection: Connection, settings: Settings) -> Self {
-        load_extensions(&connection, &settings.config.extensions).unwrap();
-        Self {
+    pub fn new(connection: Connection, settings: Settings) -> Result<Self, InitializationError> {
+        load_extensions(&connection, &settings.config.extensions)?;
+        if let Some(timeout) = settings.options.busy_timeout {
+            connection.busy_timeout(timeout).map_err(|e| {
+                InitializationError::ConnectionFailure(
+                    connection
+                        .path()
+                        .filter(|p| !p.is_empty())
+                        .unwrap_or(":memory:")
+                        .to_owned(),
+                    e,
+                )
+            })?;
+        }
+        attach_databases(
+            &connection,
+            settings
+                .config
+                .attachments
+                .iter()
+                .map(|(alias, path)| (alias.as_str(), path.as_path())),
+        )?;
+        Ok(Self {
             connection,
             sql_printer: SqlPrinter::default(),
             settings,
-        }
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.connection
+            .is_readonly(DatabaseName::Main)
+            .unwrap_or_default()
     }
 
     pub fn execute(&mut self, sql: &str) -> Result<(), QueryError> {
@@ -243,6 +457,7 @@ impl TargetConnection {
                 .map_err(|e| QueryError(sql.to_owned(), e))?;
 
             if rows > 0 {
+                let _fields = span!(target: FIELDS_TARGET, Level::DEBUG, "query", rows).entered();
                 debug!("Query affected {rows} row(s)");
             }
         }
@@ -250,6 +465,37 @@ impl TargetConnection {
         Ok(())
     }
 
+    pub fn set_pragma(&mut self, pragma: &str, value: &str) -> Result<(), QueryError> {
+        // Some pragmas return the updated value, so this can't use execute
+        self.execute_batch(&format!("PRAGMA {pragma} = {value}"))
+    }
+
+    pub fn execute_batch(&mut self, sql: &str) -> Result<(), QueryError> {
+        debug!("\n\t{}", self.sql_printer.print(sql));
+
+        if !self.settings.options.dry_run {
+            self.connection
+                .execute_batch(sql)
+                .map_err(|e| QueryError(sql.to_owned(), e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn backup(&self, path: &Path) -> Result<(), MigrationError> {
+        info!("Backing up database to {path:?}");
+        self.connection
+            .backup(DatabaseName::Main, path, None)
+            .map_err(|e| MigrationError::BackupFailure(path.to_path_buf(), e))
+    }
+
+    pub fn copy_to_memory(&self) -> Result<Connection, rusqlite::Error> {
+        debug!("Copying database into memory");
+        let mut copy = Connection::open_in_memory()?;
+        Backup::new(&self.connection, &mut copy)?.run_to_completion(100, Duration::ZERO, None)?;
+        Ok(copy)
+    }
+
     pub fn vacuum(&mut self) -> Result<(), QueryError> {
         debug!("Optimizing database");
         if !self.settings.options.dry_run {
@@ -258,6 +504,36 @@ impl TargetConnection {
         Ok(())
     }
 
+    pub fn analyze(&mut self) -> Result<(), QueryError> {
+        debug!("Gathering database statistics");
+        if !self.settings.options.dry_run {
+            self.execute("ANALYZE")?;
+        }
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self) -> Result<(), QueryError> {
+        let journal_mode: String = self.get_pragma("journal_mode")?;
+        if !journal_mode.eq_ignore_ascii_case("wal") {
+            debug!("Journal mode is {journal_mode}, skipping checkpoint");
+            return Ok(());
+        }
+        debug!("Checkpointing database");
+        if !self.settings.options.dry_run {
+            let busy: bool = get_pragma(
+                &self.connection,
+                "wal_checkpoint(TRUNCATE)",
+                Level::DEBUG,
+                "",
+                &mut self.sql_printer,
+            )?;
+            if busy {
+                warn!("Checkpoint could not complete because the database is in use");
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_pragma<T: FromSql>(&mut self, pragma: &str) -> Result<T, QueryError> {
         get_pragma(
             &self.connection,
@@ -268,12 +544,60 @@ impl TargetConnection {
         )
     }
 
+    pub fn is_empty(&mut self) -> Result<bool, QueryError> {
+        query_single(
+            &self.connection,
+            "SELECT NOT EXISTS(SELECT 1 FROM sqlite_master)",
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+            |row| row.get(0),
+        )
+    }
+
+    pub fn count_rows(&mut self, table: &str) -> Result<i64, QueryError> {
+        query_single(
+            &self.connection,
+            &format!("SELECT COUNT(*) FROM {table}"),
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+            |row| row.get(0),
+        )
+    }
+
+    pub fn get_cols(&mut self, table: &str) -> Result<Vec<String>, QueryError> {
+        let (schema, table) = self.settings.config.split_schema(table);
+        get_cols(
+            &self.connection,
+            schema,
+            table,
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+        )
+    }
+
+    /// Returns the problems reported by `PRAGMA integrity_check`, which is empty if the database
+    /// is valid
+    pub fn integrity_check(&mut self) -> Result<Vec<String>, QueryError> {
+        let results: Vec<String> = query(
+            &self.connection,
+            "PRAGMA integrity_check",
+            Level::DEBUG,
+            "",
+            &mut self.sql_printer,
+            |row| row.get(0),
+        )?;
+        Ok(results.into_iter().filter(|r| r != "ok").collect())
+    }
+
     pub fn parse_metadata(&mut self) -> Result<Metadata, QueryError> {
         Metadata::parse(
             &self.connection,
             Level::DEBUG,
             "",
-            &self.settings.config.ignore,
+            &self.settings.config,
             &mut self.sql_printer,
         )
     }
@@ -281,17 +605,37 @@ impl TargetConnection {
 
 pub fn load_extensions(
     conn: &Connection,
-    extensions: impl AsRef<[PathBuf]>,
-) -> Result<(), rusqlite::Error> {
+    extensions: impl AsRef<[ExtensionSpec]>,
+) -> Result<(), InitializationError> {
     unsafe {
         let _guard = LoadExtensionGuard::new(conn);
         for extension in extensions.as_ref() {
-            conn.load_extension(extension, None)?;
+            conn.load_extension(extension, None).map_err(|e| {
+                InitializationError::ExtensionLoadFailure(extension.as_ref().to_owned(), e)
+            })?;
         }
     }
     Ok(())
 }
 
+fn attach_databases<'a>(
+    connection: &Connection,
+    attachments: impl IntoIterator<Item = (&'a str, &'a Path)>,
+) -> Result<(), InitializationError> {
+    for (alias, path) in attachments {
+        debug!("Attaching database {path:?} as {alias}");
+        connection
+            .execute(
+                "ATTACH DATABASE ?1 AS ?2",
+                [path.to_string_lossy().as_ref(), alias],
+            )
+            .map_err(|e| {
+                InitializationError::AttachFailure(alias.to_owned(), path.to_owned(), e)
+            })?;
+    }
+    Ok(())
+}
+
 fn replace_sql_params<P>(sql: &str, params: P) -> String
 where
     P: Params + Clone + IntoIterator + Default,
@@ -304,7 +648,7 @@ where
     formatted_sql
 }
 
-fn query_params<T, P, F>(
+pub(crate) fn query_params<T, P, F>(
     connection: &Connection,
     sql: &str,
     params: P,
@@ -334,7 +678,33 @@ where
     results.map_err(|e| QueryError(sql.to_owned(), e))
 }
 
-fn get_pragma<T: FromSql>(
+#[derive(PartialEq, Eq, Debug)]
+struct ChangeCounters {
+    total_changes: i64,
+    schema_version: i64,
+}
+
+impl ChangeCounters {
+    fn read(connection: &Connection, sql_printer: &mut SqlPrinter) -> Result<Self, QueryError> {
+        // total_changes() only counts row modifications, DDL statements bump the schema version
+        let total_changes = query_single(
+            connection,
+            "SELECT total_changes()",
+            Level::TRACE,
+            "",
+            sql_printer,
+            |row| row.get(0),
+        )?;
+        let schema_version =
+            get_pragma(connection, "schema_version", Level::TRACE, "", sql_printer)?;
+        Ok(Self {
+            total_changes,
+            schema_version,
+        })
+    }
+}
+
+pub(crate) fn get_pragma<T: FromSql>(
     connection: &Connection,
     pragma: &str,
     log_level: Level,
@@ -394,6 +764,7 @@ where
 
 fn get_cols(
     connection: &Connection,
+    schema: &str,
     table: &str,
     log_level: Level,
     msg: &str,
@@ -401,8 +772,9 @@ fn get_cols(
 ) -> Result<Vec<String>, QueryError> {
     query_params(
         connection,
-        "SELECT name FROM pragma_table_info(?1)",
-        [table],
+        // Generated columns can't be inserted into, so they're excluded when copying data
+        "SELECT name FROM pragma_table_xinfo(?1, ?2) WHERE hidden NOT IN (2, 3)",
+        [table, schema],
         log_level,
         msg,
         sql_printer,
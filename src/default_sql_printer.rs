@@ -1,9 +1,24 @@
-use crate::Color;
+use crate::{error::ThemeLoadError, Color};
 
 #[derive(Default)]
-pub struct SqlPrinter;
+pub struct PlainSqlPrinter;
+
+impl PlainSqlPrinter {
+    // Themes have no effect without syntax highlighting
+    pub fn with_theme(_name: &str) -> Result<Self, ThemeLoadError> {
+        Ok(Self)
+    }
+
+    pub fn theme_names() -> Vec<String> {
+        Vec::new()
+    }
+
+    pub fn set_default_theme(_name: &str) -> Result<(), ThemeLoadError> {
+        Ok(())
+    }
+
+    pub fn set_color_enabled(_enabled: bool) {}
 
-impl SqlPrinter {
     pub fn print(&mut self, sql: &str) -> String {
         sql.to_owned()
     }
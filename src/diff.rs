@@ -1,31 +1,152 @@
 use std::collections::BTreeMap;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 
 use crate::{error::QueryError, unified_diff_builder::UnifiedDiffBuilder};
-use crate::{MigrationMetadata, Migrator, ObjectType, SqlPrinter};
+use crate::{ChangeKind, Color, Config, MigrationMetadata, Migrator, ObjectType, SqlPrinter};
 use imara_diff::{diff, intern::InternedInput, Algorithm};
 
 impl Migrator {
     pub fn diff(&mut self) -> Result<String, QueryError> {
+        self.diff_with_options(&DiffOptions::default())
+    }
+
+    pub fn diff_with_options(&mut self, options: &DiffOptions) -> Result<String, QueryError> {
         let metadata = self.parse_metadata()?;
 
-        let diffs = diff_metadata(metadata);
-        Ok(diffs
-            .0
-            .values()
-            .flat_map(|d| d.values())
-            .filter_map(|d| {
-                if d.diff_text.is_empty() {
-                    None
+        let diffs = diff_metadata_with_options(metadata, options);
+        let sections: Vec<_> = diffs
+            .iter()
+            .map(|(object_type, objects)| {
+                let changes: Vec<_> = objects
+                    .values()
+                    .filter(|d| !d.diff_text.is_empty())
+                    .map(|d| d.diff_text.clone())
+                    .collect();
+                (object_type, changes)
+            })
+            .collect();
+        if sections.iter().all(|(_, changes)| changes.is_empty()) {
+            return Ok(String::new());
+        }
+
+        // Every object type gets a section so it's clear when a type has no changes
+        Ok(sections
+            .into_iter()
+            .map(|(object_type, changes)| {
+                let header = match object_type {
+                    ObjectType::Table => "-- Tables",
+                    ObjectType::Index => "-- Indexes",
+                    ObjectType::View => "-- Views",
+                    ObjectType::Trigger => "-- Triggers",
+                };
+                if changes.is_empty() {
+                    format!("{header}\nNo changes\n")
                 } else {
-                    Some(d.diff_text.clone())
+                    format!("{header}\n{}", changes.join("\n"))
                 }
             })
             .collect::<Vec<_>>()
             .join("\n"))
     }
+
+    /// Returns the diff hunks for each changed object
+    pub fn diff_objects(&mut self) -> Result<Vec<ObjectDiff>, QueryError> {
+        self.diff_objects_with_options(&DiffOptions::default())
+    }
+
+    pub fn diff_objects_with_options(
+        &mut self,
+        options: &DiffOptions,
+    ) -> Result<Vec<ObjectDiff>, QueryError> {
+        let metadata = self.parse_metadata()?;
+        let diffs = diff_metadata_with_options(metadata, options);
+        Ok(diffs
+            .0
+            .into_iter()
+            .flat_map(|(object_type, objects)| {
+                objects
+                    .into_iter()
+                    .filter(|(_, diff)| !diff.hunks.is_empty())
+                    .map(move |(name, diff)| ObjectDiff {
+                        name,
+                        object_type: object_type.clone(),
+                        hunks: diff.hunks,
+                    })
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    #[default]
+    Histogram,
+    Myers,
+    MyersMinimal,
+}
+
+impl From<DiffAlgorithm> for Algorithm {
+    fn from(algorithm: DiffAlgorithm) -> Self {
+        match algorithm {
+            DiffAlgorithm::Histogram => Algorithm::Histogram,
+            DiffAlgorithm::Myers => Algorithm::Myers,
+            DiffAlgorithm::MyersMinimal => Algorithm::MyersMinimal,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffOptions {
+    pub algorithm: DiffAlgorithm,
+    pub context_lines: u32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: DiffAlgorithm::default(),
+            context_lines: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ObjectChange {
+    pub name: String,
+    pub object_type: ObjectType,
+    pub change_kind: ChangeKind,
+    pub source_sql: Option<String>,
+    pub target_sql: Option<String>,
 }
 
+pub fn object_changes(metadata: &MigrationMetadata, config: &Config) -> Vec<ObjectChange> {
+    metadata
+        .unified_objects()
+        .into_iter()
+        .map(|o| {
+            let source_sql = metadata.source.get(&o.object_type).get(&o.name).cloned();
+            let target_sql = metadata.target.get(&o.object_type).get(&o.name).cloned();
+            let change_kind = match (&source_sql, &target_sql) {
+                (Some(_), None) => ChangeKind::Added,
+                (None, _) => ChangeKind::Removed,
+                (Some(source), Some(target)) if config.sql_changed(target, source) => {
+                    ChangeKind::Modified
+                }
+                (Some(_), Some(_)) => ChangeKind::Unchanged,
+            };
+            ObjectChange {
+                name: o.name,
+                object_type: o.object_type,
+                change_kind,
+                source_sql,
+                target_sql,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct SchemaDiff(BTreeMap<ObjectType, BTreeMap<String, Diff>>);
 
 impl Deref for SchemaDiff {
@@ -36,13 +157,59 @@ impl Deref for SchemaDiff {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct Diff {
     pub diff_text: String,
+    pub hunks: Vec<DiffHunk>,
     pub original_text: String,
     pub new_text: String,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "kind", content = "text", rename_all = "lowercase")
+)]
+pub enum DiffLine {
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+/// A section of a unified diff. Line numbers are 1-based, matching the `@@` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiffHunk {
+    pub before_start: u32,
+    pub before_len: u32,
+    pub after_start: u32,
+    pub after_len: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ObjectDiff {
+    pub name: String,
+    pub object_type: ObjectType,
+    pub hunks: Vec<DiffHunk>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SplitLine {
+    pub source: Option<String>,
+    pub target: Option<String>,
+}
+
 pub fn diff_metadata(metadata: MigrationMetadata) -> SchemaDiff {
+    diff_metadata_with_options(metadata, &DiffOptions::default())
+}
+
+pub fn diff_metadata_with_options(
+    metadata: MigrationMetadata,
+    options: &DiffOptions,
+) -> SchemaDiff {
     let mut map = BTreeMap::<ObjectType, BTreeMap<String, Diff>>::default();
     map.insert(ObjectType::Table, Default::default());
     map.insert(ObjectType::Index, Default::default());
@@ -54,10 +221,11 @@ pub fn diff_metadata(metadata: MigrationMetadata) -> SchemaDiff {
         .map(|o| {
             (
                 o,
-                diff_objects(
+                diff_object(
                     &o.name,
                     metadata.source.get(&o.object_type),
                     metadata.target.get(&o.object_type),
+                    options,
                 ),
             )
         })
@@ -70,25 +238,33 @@ pub fn diff_metadata(metadata: MigrationMetadata) -> SchemaDiff {
     SchemaDiff(diffs)
 }
 
-fn diff_objects(
+fn diff_object(
     name: &str,
     source: &BTreeMap<String, String>,
     target: &BTreeMap<String, String>,
+    options: &DiffOptions,
 ) -> Diff {
-    sql_diff(
+    sql_diff_with_options(
         source.get(name).map(|s| s.as_str()).unwrap_or_default(),
         target.get(name).map(|s| s.as_str()).unwrap_or_default(),
+        options,
     )
 }
 
 pub fn sql_diff(source: &str, target: &str) -> Diff {
+    sql_diff_with_options(source, target, &DiffOptions::default())
+}
+
+pub fn sql_diff_with_options(source: &str, target: &str, options: &DiffOptions) -> Diff {
     let input = InternedInput::new(target, source);
+    let unified_diff = diff(
+        options.algorithm.into(),
+        &input,
+        UnifiedDiffBuilder::new(&input, options.context_lines),
+    );
     Diff {
-        diff_text: diff(
-            Algorithm::Histogram,
-            &input,
-            UnifiedDiffBuilder::new(&input),
-        ),
+        diff_text: unified_diff.text,
+        hunks: unified_diff.hunks,
         original_text: if source.is_empty() {
             String::default()
         } else {
@@ -101,3 +277,58 @@ pub fn sql_diff(source: &str, target: &str) -> Diff {
         },
     }
 }
+
+/// Pairs up the lines of `source` and `target` for a side-by-side view, highlighting the added
+/// and removed lines
+pub fn split_diff(source: &str, target: &str, algorithm: DiffAlgorithm) -> Vec<SplitLine> {
+    let input = InternedInput::new(target, source);
+    let mut printer = SqlPrinter::default();
+    let mut line = |token, color: Option<Color>| {
+        let text = input.interner[token].trim_end_matches('\n');
+        match color {
+            Some(color) => printer.print_on(text, color),
+            None => printer.print(text),
+        }
+    };
+    let mut lines = vec![];
+    let mut pos = 0;
+    let mut after_pos = 0;
+    let mut changes = vec![];
+    diff(
+        algorithm.into(),
+        &input,
+        |before: Range<u32>, after: Range<u32>| changes.push((before, after)),
+    );
+    // "before" is the target and "after" is the source
+    for (before, after) in changes {
+        for (&target, &source) in input.before[pos as usize..before.start as usize]
+            .iter()
+            .zip(&input.after[after_pos as usize..after.start as usize])
+        {
+            lines.push(SplitLine {
+                source: Some(line(source, None)),
+                target: Some(line(target, None)),
+            });
+        }
+        let removed = &input.before[before.start as usize..before.end as usize];
+        let added = &input.after[after.start as usize..after.end as usize];
+        for i in 0..removed.len().max(added.len()) {
+            lines.push(SplitLine {
+                source: added.get(i).map(|&t| line(t, Some(Color::Green))),
+                target: removed.get(i).map(|&t| line(t, Some(Color::Red))),
+            });
+        }
+        pos = before.end;
+        after_pos = after.end;
+    }
+    for (&target, &source) in input.before[pos as usize..]
+        .iter()
+        .zip(&input.after[after_pos as usize..])
+    {
+        lines.push(SplitLine {
+            source: Some(line(source, None)),
+            target: Some(line(target, None)),
+        });
+    }
+    lines
+}
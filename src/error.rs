@@ -1,4 +1,4 @@
-use std::{io, path::PathBuf};
+use std::{fmt, io, path::PathBuf};
 
 #[derive(thiserror::Error, Debug)]
 pub enum InitializationError {
@@ -6,12 +6,34 @@ pub enum InitializationError {
     QueryFailure(String, QueryError),
     #[error("Failed to connect to the database {0}: {1}")]
     ConnectionFailure(String, #[source] rusqlite::Error),
+    #[error("{0} is defined in both {1:?} and {2:?}")]
+    DuplicateDefinition(String, PathBuf, PathBuf),
+    #[error("Failed to read {0:?}: {1}")]
+    ReadFailure(PathBuf, #[source] io::Error),
+    #[error("Failed to attach database {1:?} as {0}: {2}")]
+    AttachFailure(String, PathBuf, #[source] rusqlite::Error),
+    #[error("Failed to load extension {0:?}: {1}")]
+    ExtensionLoadFailure(PathBuf, #[source] rusqlite::Error),
+    #[error("Schema validation failed with {} error(s):\n{}", .0.len(), format_errors(.0))]
+    ValidationFailure(Vec<ValidationError>),
+    #[error("{0} aren't supported when using an existing database as the source")]
+    UnsupportedSourceConfig(String),
+}
+
+fn format_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("- {e}"))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum MigrationError {
     #[error("{0}: {1}")]
     QueryFailure(String, QueryError),
+    #[error("{0}")]
+    InitializationFailure(#[source] InitializationError),
     #[error("Failed to initialize transaction: {0}")]
     TransactionInitializationFailure(#[source] rusqlite::Error),
     #[error("Failed to commit transaction: {0}")]
@@ -20,14 +42,126 @@ pub enum MigrationError {
     TransactionRollbackFailure(#[source] rusqlite::Error),
     #[error("Aborting migration because data loss would occur and allow_deletions is false: {0}")]
     DataLoss(String),
-    #[error("The following foreign keys have constraint violations: {0:?}")]
-    ForeignKeyViolation(Vec<String>),
+    #[error("The target contains objects that aren't in the source: {0}")]
+    ExtraObjects(String),
+    #[error("Failed to back up database to {0:?}: {1}")]
+    BackupFailure(PathBuf, #[source] rusqlite::Error),
+    #[error(
+        "The following foreign keys have constraint violations: {}",
+        .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    ForeignKeyViolation(Vec<ForeignKeyViolation>),
+    #[error("Unable to migrate using a read-only migrator")]
+    ReadOnly,
+    #[error("Migration cancelled")]
+    Cancelled,
+    #[error("The target database failed the integrity check: {}", .0.join(", "))]
+    IntegrityCheckFailed(Vec<String>),
+    #[error("The migrated schema doesn't match the source: {}", .0.join(", "))]
+    VerificationFailed(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    // None for WITHOUT ROWID tables
+    pub rowid: Option<i64>,
+    pub parent: String,
+    pub fkid: i64,
+}
+
+impl fmt::Display for ForeignKeyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rowid {
+            Some(rowid) => write!(f, "{} (rowid {rowid})", self.table)?,
+            None => write!(f, "{}", self.table)?,
+        }
+        write!(f, " references {} (foreign key {})", self.parent, self.fkid)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub struct ValidationError {
+    // None for scripts that weren't read from a file
+    pub path: Option<PathBuf>,
+    #[source]
+    pub error: QueryError,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{path:?}: {}", self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
-#[error("Failed to execute query {0}: {1}")]
 pub struct QueryError(pub(crate) String, #[source] pub(crate) rusqlite::Error);
 
+impl QueryError {
+    pub fn sql(&self) -> &str {
+        &self.0
+    }
+
+    pub fn extended_code(&self) -> Option<i32> {
+        match &self.1 {
+            rusqlite::Error::SqliteFailure(error, _)
+            | rusqlite::Error::SqlInputError { error, .. } => Some(error.extended_code),
+            _ => None,
+        }
+    }
+
+    pub fn offset(&self) -> Option<usize> {
+        match &self.1 {
+            rusqlite::Error::SqlInputError { offset, .. } => usize::try_from(*offset).ok(),
+            _ => None,
+        }
+    }
+
+    fn location(&self) -> Option<(usize, usize, &str)> {
+        let sql = match &self.1 {
+            rusqlite::Error::SqlInputError { sql, .. } => sql,
+            _ => return None,
+        };
+        let offset = self.offset()?;
+        let prefix = sql.get(..offset)?;
+        let line_start = prefix.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = sql[offset..]
+            .find('\n')
+            .map(|i| offset + i)
+            .unwrap_or(sql.len());
+        Some((
+            prefix.matches('\n').count() + 1,
+            prefix[line_start..].chars().count() + 1,
+            &sql[line_start..line_end],
+        ))
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to execute query {}: ", self.0)?;
+        match &self.1 {
+            // The default message repeats the full statement
+            rusqlite::Error::SqlInputError { msg, .. } => write!(f, "{msg}")?,
+            error => write!(f, "{error}")?,
+        }
+        if let Some(code) = self.extended_code() {
+            write!(f, " (extended code {code})")?;
+        }
+        if let Some((line, column, text)) = self.location() {
+            write!(
+                f,
+                "\n  at line {line}, column {column}:\n    {text}\n    {}^",
+                " ".repeat(column - 1)
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum SqlFormatError {
     #[error("Error formatting SQL {0}: {1}")]
@@ -47,3 +181,7 @@ pub enum RefreshError {
 #[derive(thiserror::Error, Debug)]
 #[error("Error loading config file {0}: {1}")]
 pub struct ConfigLoadError(pub(crate) PathBuf, pub(crate) String);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Theme {0} was not found, expected one of: {}", .1.join(", "))]
+pub struct ThemeLoadError(pub(crate) String, pub(crate) Vec<String>);
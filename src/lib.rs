@@ -2,7 +2,6 @@
 mod ansi_sql_printer;
 #[cfg(feature = "pretty-print")]
 pub use ansi_sql_printer::*;
-#[cfg(not(feature = "pretty-print"))]
 mod default_sql_printer;
 #[cfg(feature = "diff")]
 mod diff;
@@ -22,22 +21,39 @@ mod connection;
 pub use connection::*;
 mod metadata;
 pub use metadata::*;
+mod migrator_builder;
+pub use migrator_builder::*;
+mod source_text;
+pub use source_text::*;
 pub mod error;
 pub use rusqlite::Connection;
 
 use crate::connection::TargetTransaction;
+pub use default_sql_printer::PlainSqlPrinter;
 #[cfg(not(feature = "pretty-print"))]
-pub use default_sql_printer::SqlPrinter;
-use error::{InitializationError, MigrationError, QueryError};
+pub use default_sql_printer::PlainSqlPrinter as SqlPrinter;
+use error::{
+    ForeignKeyViolation, InitializationError, MigrationError, QueryError, ValidationError,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use rusqlite::TransactionBehavior;
+use sha2::{Digest, Sha256};
 use std::{
     collections::BTreeMap,
-    fmt::Debug,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    fmt::{self, Debug},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tracing::{debug, info, span, Level};
+use tracing::{debug, info, span, warn, Level};
+
+/// Target of the spans that carry structured fields (table, index, etc.) for each migrated object.
+/// The same names are already part of the log messages, so hierarchical output should disable it.
+pub const FIELDS_TARGET: &str = "slite::fields";
 
 macro_rules! regex {
     ($name: ident, $re: literal $(,) ?) => {
@@ -45,23 +61,429 @@ macro_rules! regex {
     };
 }
 
-regex!(COMMENTS_RE, r"--[^\n]*\n");
+// Comments are matched alongside string literals so quotes inside comments and dashes inside
+// literals are both handled correctly
+regex!(LITERALS_AND_COMMENTS_RE, r"'(?:[^']|'')*'|--[^\n]*(?:\n|$)");
 regex!(WHITESPACE_RE, r"\s+");
 regex!(EXTRA_WHITESPACE_RE, r" *([(),]) *");
-regex!(QUOTES_RE, r#""(\w+)""#);
+regex!(
+    OPERATOR_WHITESPACE_RE,
+    r" *(<=|>=|<>|!=|==|\|\||[=<>+*/%-]) *"
+);
+regex!(COLLATE_RE, r"(?i)\bCOLLATE +(\w+)");
+regex!(CHECK_RE, r"(?i)\bCHECK\(");
+regex!(QUOTES_RE, r#""(\w+)"|`(\w+)`|\[(\w+)\]"#);
+regex!(
+    TABLE_CONSTRAINT_RE,
+    r"(?i)^(CONSTRAINT|PRIMARY\s+KEY|UNIQUE|CHECK|FOREIGN\s+KEY)\b"
+);
+regex!(
+    UNSUPPORTED_ADD_COLUMN_RE,
+    r"(?i)\b(PRIMARY\s+KEY|UNIQUE|GENERATED|CURRENT_TIME|CURRENT_DATE|CURRENT_TIMESTAMP)\b|\bAS\s*\(|\bDEFAULT\s*\("
+);
+regex!(NOT_NULL_RE, r"(?i)\bNOT\s+NULL\b");
+regex!(DEFAULT_RE, r"(?i)\bDEFAULT\s+(\S+)");
+regex!(VIRTUAL_TABLE_RE, r"(?i)^\s*CREATE\s+VIRTUAL\b");
+regex!(AUTOINCREMENT_RE, r"(?i)\bAUTOINCREMENT\b");
+regex!(
+    REFERENCES_RE,
+    r#"(?i)\bREFERENCES\s+(?:["`\[]?(\w+)["`\]]?\s*\.\s*)?["`\[]?(\w+)["`\]]?"#
+);
+regex!(
+    CREATE_OBJECT_RE,
+    r"(?i)^\s*CREATE\s+(?:UNIQUE\s+|TEMP\s+|TEMPORARY\s+|VIRTUAL\s+)?(?:TABLE|INDEX|VIEW|TRIGGER)\s+(?:IF\s+NOT\s+EXISTS\s+)?",
+);
+
+// Tables with at least this many rows get status messages while they're copied during a rebuild
+pub(crate) const LARGE_COPY_ROWS: i64 = 10_000;
+
+// https://www.sqlite.org/lang_keywords.html
+const SQLITE_KEYWORDS: &str =
+    "ABORT ACTION ADD AFTER ALL ALTER ALWAYS ANALYZE AND AS ASC ATTACH AUTOINCREMENT BEFORE \
+    BEGIN BETWEEN BY CASCADE CASE CAST CHECK COLLATE COLUMN COMMIT CONFLICT CONSTRAINT CREATE \
+    CROSS CURRENT CURRENT_DATE CURRENT_TIME CURRENT_TIMESTAMP DATABASE DEFAULT DEFERRABLE \
+    DEFERRED DELETE DESC DETACH DISTINCT DO DROP EACH ELSE END ESCAPE EXCEPT EXCLUDE EXCLUSIVE \
+    EXISTS EXPLAIN FAIL FILTER FIRST FOLLOWING FOR FOREIGN FROM FULL GENERATED GLOB GROUP \
+    GROUPS HAVING IF IGNORE IMMEDIATE IN INDEX INDEXED INITIALLY INNER INSERT INSTEAD \
+    INTERSECT INTO IS ISNULL JOIN KEY LAST LEFT LIKE LIMIT MATCH MATERIALIZED NATURAL NO NOT \
+    NOTHING NOTNULL NULL NULLS OF OFFSET ON OR ORDER OTHERS OUTER OVER PARTITION PLAN PRAGMA \
+    PRECEDING PRIMARY QUERY RAISE RANGE RECURSIVE REFERENCES REGEXP REINDEX RELEASE RENAME \
+    REPLACE RESTRICT RETURNING RIGHT ROLLBACK ROW ROWS SAVEPOINT SELECT SET TABLE TEMP \
+    TEMPORARY THEN TIES TO TRANSACTION TRIGGER UNBOUNDED UNION UNIQUE UPDATE USING VACUUM \
+    VALUES VIEW VIRTUAL WHEN WHERE WINDOW WITH WITHOUT";
 
 #[derive(Debug, Clone, Default)]
 pub struct Options {
     pub allow_deletions: bool,
     pub dry_run: bool,
+    pub backup_path: Option<PathBuf>,
+    pub vacuum: VacuumMode,
+    pub analyze: bool,
+    pub busy_timeout: Option<Duration>,
+    pub on_extra_objects: ExtraPolicy,
+    /// Run `PRAGMA integrity_check` on the target before migrating and abort if it fails
+    pub pre_integrity_check: bool,
+    /// Checkpoint and truncate the WAL after migrating so all changes are in the main database
+    /// file. This has no effect if the target isn't in WAL mode.
+    pub checkpoint: bool,
+    pub transaction_behavior: TransactionBehaviorKind,
+    /// Prefix each section of the generated script with a comment describing the change
+    pub script_comments: bool,
+    /// Don't rebuild tables whose only difference is the order of their columns
+    pub ignore_column_order: bool,
 }
 
-#[derive(Debug, Default, Clone)]
+/// How to handle objects in the target that aren't in the source and aren't ignored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtraPolicy {
+    /// Drop the objects if `allow_deletions` is set, otherwise abort the migration
+    #[default]
+    Drop,
+    /// Abort the migration
+    Error,
+    /// Leave the objects in place
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VacuumMode {
+    Always,
+    Never,
+    #[default]
+    IfModified,
+}
+
+/// Locking behavior of the migration transaction. See
+/// <https://www.sqlite.org/lang_transaction.html> for details.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionBehaviorKind {
+    Deferred,
+    Immediate,
+    #[default]
+    Exclusive,
+}
+
+impl From<TransactionBehaviorKind> for TransactionBehavior {
+    fn from(kind: TransactionBehaviorKind) -> Self {
+        match kind {
+            TransactionBehaviorKind::Deferred => TransactionBehavior::Deferred,
+            TransactionBehaviorKind::Immediate => TransactionBehavior::Immediate,
+            TransactionBehaviorKind::Exclusive => TransactionBehavior::Exclusive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Tables,
+    Indexes,
+    Views,
+    Triggers,
+}
+
+/// Signals a running migration to stop at the next phase boundary and roll back
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationProgress {
+    pub phase: Phase,
+    pub completed: usize,
+    pub total: usize,
+    pub current_object: Option<String>,
+}
+
+/// A statement emitted during a migration. `rows_affected` is only populated for statements that
+/// were actually executed against the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub sql: String,
+    pub rows_affected: Option<usize>,
+}
+
+impl fmt::Display for ScriptEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.sql)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    pub duration: Duration,
+}
+
+/// Time spent in each phase of a migration, in the order the phases completed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationTimings {
+    pub phases: Vec<PhaseTiming>,
+    pub total: Duration,
+}
+
+impl MigrationTimings {
+    pub fn get(&self, phase: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|timing| timing.phase == phase)
+            .map(|timing| timing.duration)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub tables_created: usize,
+    pub tables_dropped: usize,
+    pub tables_modified: usize,
+    pub indexes_changed: usize,
+    pub views_changed: usize,
+    pub triggers_changed: usize,
+    pub columns_dropped: Vec<(String, String)>,
+    pub tables_dropped_names: Vec<String>,
+    /// Tables that will be rebuilt along with the number of rows that need to be copied
+    pub rows_copied: Vec<(String, i64)>,
+}
+
+impl MigrationReport {
+    pub fn has_changes(&self) -> bool {
+        self.tables_created > 0
+            || self.tables_dropped > 0
+            || self.tables_modified > 0
+            || self.indexes_changed > 0
+            || self.views_changed > 0
+            || self.triggers_changed > 0
+    }
+
+    pub fn has_data_loss(&self) -> bool {
+        !self.tables_dropped_names.is_empty() || !self.columns_dropped.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtensionSpec {
+    /// Path to an extension library
+    Path(PathBuf),
+    /// Name of a system-installed extension (e.g. `mod_spatialite`) that's resolved using the
+    /// OS library search path
+    Name(String),
+}
+
+impl From<PathBuf> for ExtensionSpec {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl AsRef<Path> for ExtensionSpec {
+    fn as_ref(&self) -> &Path {
+        match self {
+            Self::Path(path) => path,
+            Self::Name(name) => Path::new(name),
+        }
+    }
+}
+
+/// Custom canonicalization applied to SQL after the built-in normalization
+pub type SqlNormalizer = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Config {
-    pub extensions: Vec<PathBuf>,
-    pub ignore: Option<Regex>,
-    pub before_migration: Vec<String>,
-    pub after_migration: Vec<String>,
+    pub extensions: Vec<ExtensionSpec>,
+    pub ignore: IgnoreRules,
+    /// If set, only objects whose names match are migrated. Objects matching `ignore` are
+    /// still excluded.
+    pub only: Option<Regex>,
+    /// If non-empty, only these objects are migrated along with the indexes and triggers that
+    /// belong to them.
+    pub only_objects: Vec<String>,
+    pub before_migration: Vec<SqlScript>,
+    pub after_migration: Vec<SqlScript>,
+    pub attachments: Vec<(String, PathBuf)>,
+    /// Whether objects created by the before and after migration scripts are migrated along
+    /// with the rest of the schema. If false, they're ignored in both the source and the target.
+    pub treat_hooks_as_schema: bool,
+    /// Pragmas applied to the target after the schema is migrated, such as `journal_mode`.
+    /// Changing `page_size` triggers a VACUUM so the new size takes effect, which isn't possible
+    /// once the target is in WAL mode.
+    pub target_pragmas: Vec<(String, String)>,
+    /// Applied to both versions of an object's SQL before they're compared when deciding whether
+    /// it needs to be migrated. Useful for ignoring differences that are insignificant for a
+    /// particular project, such as `INT` vs `INTEGER`.
+    pub sql_normalizer: Option<SqlNormalizer>,
+}
+
+impl Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("extensions", &self.extensions)
+            .field("ignore", &self.ignore)
+            .field("only", &self.only)
+            .field("only_objects", &self.only_objects)
+            .field("before_migration", &self.before_migration)
+            .field("after_migration", &self.after_migration)
+            .field("attachments", &self.attachments)
+            .field("treat_hooks_as_schema", &self.treat_hooks_as_schema)
+            .field("target_pragmas", &self.target_pragmas)
+            .field(
+                "sql_normalizer",
+                &self.sql_normalizer.as_ref().map(|_| "<normalizer>"),
+            )
+            .finish()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            extensions: Vec::new(),
+            ignore: IgnoreRules::default(),
+            only: None,
+            only_objects: Vec::new(),
+            before_migration: Vec::new(),
+            after_migration: Vec::new(),
+            attachments: Vec::new(),
+            treat_hooks_as_schema: true,
+            target_pragmas: Vec::new(),
+            sql_normalizer: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct IgnoreRules {
+    pub patterns: Vec<Regex>,
+    pub type_patterns: BTreeMap<ObjectType, Vec<Regex>>,
+    pub ignored_types: Vec<ObjectType>,
+}
+
+impl IgnoreRules {
+    pub fn is_ignored(&self, object_type: &ObjectType, name: &str) -> bool {
+        self.ignored_types.contains(object_type)
+            || self.patterns.iter().any(|p| p.is_match(name))
+            || self
+                .type_patterns
+                .get(object_type)
+                .map(|patterns| patterns.iter().any(|p| p.is_match(name)))
+                .unwrap_or(false)
+    }
+}
+
+impl From<Regex> for IgnoreRules {
+    fn from(pattern: Regex) -> Self {
+        Self {
+            patterns: vec![pattern],
+            ..Default::default()
+        }
+    }
+}
+
+/// SQL text along with the file it was read from, if any
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqlScript {
+    pub path: Option<PathBuf>,
+    pub sql: String,
+}
+
+impl SqlScript {
+    fn from_source(source: &impl SqlSource) -> Self {
+        Self {
+            path: source.path().map(|p| p.to_path_buf()),
+            sql: source.as_ref().to_owned(),
+        }
+    }
+}
+
+impl AsRef<str> for SqlScript {
+    fn as_ref(&self) -> &str {
+        &self.sql
+    }
+}
+
+impl From<String> for SqlScript {
+    fn from(sql: String) -> Self {
+        Self { path: None, sql }
+    }
+}
+
+impl From<&str> for SqlScript {
+    fn from(sql: &str) -> Self {
+        sql.to_owned().into()
+    }
+}
+
+/// Schema text that may have been read from a file. The path is included in any errors caused by
+/// the schema.
+pub trait SqlSource: AsRef<str> {
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+impl SqlSource for str {}
+
+impl SqlSource for String {}
+
+impl SqlSource for SqlScript {
+    fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+impl<T: SqlSource + ?Sized> SqlSource for &T {
+    fn path(&self) -> Option<&Path> {
+        (**self).path()
+    }
+}
+
+impl Config {
+    pub(crate) fn is_excluded(&self, object_type: &ObjectType, name: &str, table: &str) -> bool {
+        self.ignore.is_ignored(object_type, name)
+            || self
+                .only
+                .as_ref()
+                .map(|only| !only.is_match(name))
+                .unwrap_or(false)
+            || (!self.only_objects.is_empty()
+                && !self.only_objects.iter().any(|object| {
+                    object.eq_ignore_ascii_case(name) || object.eq_ignore_ascii_case(table)
+                }))
+    }
+
+    pub(crate) fn split_schema<'a>(&self, name: &'a str) -> (&'a str, &'a str) {
+        match name.split_once('.') {
+            Some((schema, object)) if self.attachments.iter().any(|(alias, _)| alias == schema) => {
+                (schema, object)
+            }
+            _ => ("main", name),
+        }
+    }
+
+    /// Normalizes `sql` for comparison, applying the custom normalizer if one is configured
+    pub fn normalize_sql(&self, sql: &str) -> String {
+        let normalized = normalize_sql(sql);
+        match &self.sql_normalizer {
+            Some(sql_normalizer) => sql_normalizer(&normalized),
+            None => normalized,
+        }
+    }
+
+    /// Returns `true` if the two statements differ after normalization
+    pub fn sql_changed(&self, old_sql: &str, new_sql: &str) -> bool {
+        self.normalize_sql(old_sql) != self.normalize_sql(new_sql)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -70,120 +492,799 @@ pub(crate) struct Settings {
     pub(crate) config: Config,
 }
 
+impl Settings {
+    pub(crate) fn table_changed(&self, existing_sql: &str, sql: &str) -> bool {
+        if self.options.ignore_column_order {
+            if let Some(reordered_sql) = reorder_columns(existing_sql, sql) {
+                return self.config.sql_changed(&reordered_sql, sql);
+            }
+        }
+        self.config.sql_changed(existing_sql, sql)
+    }
+}
+
 pub struct Migrator {
     target_connection: Arc<Mutex<TargetConnection>>,
-    pristine: PristineConnection,
+    // Initialized lazily when a cached source is provided
+    pristine: Option<PristineConnection>,
+    pristine_schema: Vec<SqlScript>,
+    schema_hash: String,
+    cached_source: Option<Metadata>,
     settings: Settings,
     foreign_keys_enabled: bool,
+    read_only: bool,
+    cancellation_token: Option<CancellationToken>,
+    source_definitions: BTreeMap<String, String>,
+    timings: MigrationTimings,
 }
 
 impl Migrator {
     pub fn new(
-        schema: &[impl AsRef<str>],
+        schema: &[impl SqlSource],
         target_connection: Connection,
         config: Config,
         options: Options,
     ) -> Result<Self, InitializationError> {
-        let settings = Settings {
-            config: config.clone(),
+        Self::create(
+            schema,
+            target_connection,
+            config,
             options,
-        };
-        let mut target_connection = TargetConnection::new(target_connection, settings.clone());
-        let foreign_keys_enabled = target_connection
-            .get_pragma::<i32>("foreign_keys")
+            false,
+            None,
+            None,
+        )
+    }
+
+    /// Creates a migrator that uses an existing database as the source instead of building one
+    /// from SQL scripts. The source database is only read from, so configs with attached
+    /// databases or before/after migration hooks are rejected with
+    /// [`InitializationError::UnsupportedSourceConfig`].
+    pub fn from_two_connections(
+        source_connection: Connection,
+        target_connection: Connection,
+        config: Config,
+        options: Options,
+    ) -> Result<Self, InitializationError> {
+        // Attached schemas would be replaced with empty in-memory databases and hooks can't be
+        // applied without modifying the source
+        if !config.attachments.is_empty() {
+            return Err(InitializationError::UnsupportedSourceConfig(
+                "Attached databases".to_owned(),
+            ));
+        }
+        if !config.before_migration.is_empty() || !config.after_migration.is_empty() {
+            return Err(InitializationError::UnsupportedSourceConfig(
+                "Migration hooks".to_owned(),
+            ));
+        }
+        let mut source = PristineConnection::from_connection(
+            source_connection,
+            Settings {
+                config: config.clone(),
+                options: options.clone(),
+            },
+        )?;
+        let schema: Vec<SqlScript> = source
+            .parse_metadata()
             .map_err(|e| {
-                InitializationError::QueryFailure(
-                    "Failed to retrieve foreign_keys pragma".to_owned(),
-                    e,
-                )
+                InitializationError::QueryFailure("Failed to parse source metadata".to_owned(), e)
             })?
-            == 1;
-        if foreign_keys_enabled {
-            target_connection
-                .execute("PRAGMA foreign_keys = OFF")
-                .map_err(|e| {
-                    InitializationError::QueryFailure(
-                        "Failed to disable foreign keys".to_owned(),
-                        e,
-                    )
-                })?;
+            .create_statements()
+            .into_iter()
+            .map(SqlScript::from)
+            .collect();
+        Self::create(
+            &schema,
+            target_connection,
+            config,
+            options,
+            false,
+            None,
+            Some(source),
+        )
+    }
+
+    /// Creates a migrator that reuses previously parsed source metadata (see
+    /// [`MigrationMetadata::source`]) instead of loading the schema into an in-memory database.
+    /// The cache is ignored if the schema has changed since it was created. The in-memory database
+    /// is still created on demand if it's needed to run a migration.
+    pub fn new_with_cached_source(
+        schema: &[impl SqlSource],
+        cached: Metadata,
+        target_connection: Connection,
+        config: Config,
+        options: Options,
+    ) -> Result<Self, InitializationError> {
+        Self::create(
+            schema,
+            target_connection,
+            config,
+            options,
+            false,
+            Some(cached),
+            None,
+        )
+    }
+
+    /// Creates a migrator that never modifies the target, only allowing inspection methods such
+    /// as [`Migrator::diff`] and [`Migrator::parse_metadata`]
+    pub fn new_read_only(
+        schema: &[impl SqlSource],
+        target_connection: Connection,
+        config: Config,
+    ) -> Result<Self, InitializationError> {
+        Self::create(
+            schema,
+            target_connection,
+            config,
+            Options {
+                allow_deletions: true,
+                dry_run: true,
+                ..Default::default()
+            },
+            true,
+            None,
+            None,
+        )
+    }
+
+    fn create(
+        schema: &[impl SqlSource],
+        target_connection: Connection,
+        config: Config,
+        options: Options,
+        read_only: bool,
+        cached_source: Option<Metadata>,
+        pristine: Option<PristineConnection>,
+    ) -> Result<Self, InitializationError> {
+        let mut config = config;
+        if !config.treat_hooks_as_schema {
+            for (object_type, name) in hook_objects(schema, &config)? {
+                config
+                    .ignore
+                    .type_patterns
+                    .entry(object_type)
+                    .or_default()
+                    .push(
+                        Regex::new(&format!("^{}$", regex::escape(&name)))
+                            .expect("Regex failed to compile"),
+                    );
+            }
         }
-        let mut pristine = PristineConnection::new(settings.clone())?;
-        pristine.initialize_schema(
-            config
-                .before_migration
-                .iter()
-                .map(|s| s.as_ref())
-                .chain(schema.iter().map(|s| s.as_ref()))
-                .chain(config.after_migration.iter().map(|s| s.as_ref())),
-        )?;
+        let settings = Settings {
+            config: config.clone(),
+            options,
+        };
+        let (target_connection, foreign_keys_enabled) =
+            connect_target(target_connection, &settings, read_only)?;
+        let pristine_schema: Vec<SqlScript> = config
+            .before_migration
+            .iter()
+            .cloned()
+            .chain(schema.iter().map(SqlScript::from_source))
+            .chain(config.after_migration.iter().cloned())
+            .collect();
+        let schema_hash = schema_hash(&pristine_schema);
+        let cached_source = cached_source.filter(|cached| {
+            let is_current = cached.schema_hash() == Some(schema_hash.as_str());
+            if !is_current {
+                debug!("Cached source metadata is out of date, reloading schema");
+            }
+            is_current
+        });
+        let pristine = match pristine {
+            Some(pristine) => Some(pristine),
+            None if cached_source.is_some() => None,
+            None => Some(initialize_pristine(&settings, &pristine_schema)?),
+        };
         Ok(Self {
             target_connection: Arc::new(Mutex::new(target_connection)),
             foreign_keys_enabled,
+            read_only,
+            cancellation_token: None,
+            source_definitions: source_definitions(schema),
+            timings: MigrationTimings::default(),
             pristine,
+            pristine_schema,
+            schema_hash,
+            cached_source,
             settings,
         })
     }
 
+    /// Creates a migrator for another target that reuses this migrator's source database instead
+    /// of loading the schema again. Useful when migrating several databases to the same schema.
+    pub fn for_target(
+        &mut self,
+        target_connection: Connection,
+        options: Options,
+    ) -> Result<Self, InitializationError> {
+        let settings = Settings {
+            config: self.settings.config.clone(),
+            options,
+        };
+        let (target_connection, foreign_keys_enabled) =
+            connect_target(target_connection, &settings, self.read_only)?;
+        if self.pristine.is_none() {
+            self.pristine = Some(initialize_pristine(&self.settings, &self.pristine_schema)?);
+        }
+        let pristine = self
+            .pristine
+            .as_mut()
+            .expect("Pristine connection should be initialized")
+            .try_clone(settings.clone())?;
+        Ok(Self {
+            target_connection: Arc::new(Mutex::new(target_connection)),
+            foreign_keys_enabled,
+            read_only: self.read_only,
+            cancellation_token: None,
+            source_definitions: self.source_definitions.clone(),
+            timings: MigrationTimings::default(),
+            pristine: Some(pristine),
+            pristine_schema: self.pristine_schema.clone(),
+            schema_hash: self.schema_hash.clone(),
+            cached_source: self.cached_source.clone(),
+            settings,
+        })
+    }
+
+    fn pristine(&mut self) -> Result<&mut PristineConnection, MigrationError> {
+        if self.pristine.is_none() {
+            self.pristine = Some(
+                initialize_pristine(&self.settings, &self.pristine_schema)
+                    .map_err(MigrationError::InitializationFailure)?,
+            );
+        }
+        Ok(self
+            .pristine
+            .as_mut()
+            .expect("Pristine connection should be initialized"))
+    }
+
+    /// Returns the original source text for the object, including comments
+    pub fn source_definition(&self, name: &str) -> Option<&str> {
+        self.source_definitions
+            .get(&name.to_lowercase())
+            .map(|s| s.as_str())
+    }
+
+    pub fn with_cancellation_token(self, cancellation_token: CancellationToken) -> Self {
+        Self {
+            cancellation_token: Some(cancellation_token),
+            ..self
+        }
+    }
+
+    pub fn validate(schema: &[SqlScript], config: Config) -> Result<(), InitializationError> {
+        let mut pristine = PristineConnection::new(Settings {
+            config: config.clone(),
+            options: Options::default(),
+        })?;
+        pristine.validate_schema(
+            config
+                .before_migration
+                .iter()
+                .chain(schema)
+                .chain(&config.after_migration),
+        )
+    }
+
+    /// Applies a previously generated migration script (see [`Migrator::migrate_to_sql`]) to the
+    /// target without comparing it to a schema. Statements between `BEGIN` and `COMMIT` (or the
+    /// whole script if there are none) are run in a single transaction using the same foreign key
+    /// handling as a regular migration.
+    pub fn apply_script(
+        script: &str,
+        target_connection: Connection,
+        config: Config,
+        options: Options,
+    ) -> Result<(), MigrationError> {
+        let settings = Settings { config, options };
+        let mut connection = TargetConnection::new(target_connection, settings.clone())
+            .map_err(MigrationError::InitializationFailure)?;
+        if connection.is_read_only() {
+            return Err(MigrationError::ReadOnly);
+        }
+        let foreign_keys_enabled = connection.get_pragma::<i32>("foreign_keys").map_err(|e| {
+            MigrationError::QueryFailure("Failed to retrieve foreign_keys pragma".to_owned(), e)
+        })? == 1;
+        if foreign_keys_enabled {
+            connection
+                .execute("PRAGMA foreign_keys = OFF")
+                .map_err(|e| {
+                    MigrationError::QueryFailure("Failed to disable foreign keys".to_owned(), e)
+                })?;
+        }
+        let result = apply_script_sections(&mut connection, settings, script, foreign_keys_enabled);
+        if foreign_keys_enabled {
+            connection
+                .execute("PRAGMA foreign_keys = ON")
+                .map_err(|e| {
+                    MigrationError::QueryFailure("Failed to re-enable foreign keys".to_owned(), e)
+                })?;
+        }
+        info!("Script applied");
+        result
+    }
+
     pub fn migrate(self) -> Result<(), MigrationError> {
         self.migrate_with_callback(|_| {})
     }
 
     pub fn migrate_with_callback(
-        mut self,
-        on_script: impl FnMut(String),
+        self,
+        on_script: impl FnMut(ScriptEvent),
     ) -> Result<(), MigrationError> {
+        self.migrate_with_callbacks(on_script, |_| {}).map(|_| ())
+    }
+
+    pub fn migrate_with_progress(
+        self,
+        on_progress: impl FnMut(MigrationProgress),
+    ) -> Result<(), MigrationError> {
+        self.migrate_with_callbacks(|_| {}, on_progress).map(|_| ())
+    }
+
+    /// Runs the migration and returns the time spent in each phase
+    pub fn migrate_with_timings(self) -> Result<MigrationTimings, MigrationError> {
+        self.migrate_with_callbacks(|_| {}, |_| {})
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn migrate_async(self) -> Result<(), MigrationError> {
+        match tokio::task::spawn_blocking(move || self.migrate()).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            // The blocking task can only be cancelled if the runtime shuts down
+            Err(_) => Err(MigrationError::Cancelled),
+        }
+    }
+
+    fn migrate_with_callbacks(
+        mut self,
+        on_script: impl FnMut(ScriptEvent),
+        on_progress: impl FnMut(MigrationProgress),
+    ) -> Result<MigrationTimings, MigrationError> {
         let connection_rc = self.target_connection.clone();
         let mut connection = connection_rc.lock().expect("Failed to lock mutex");
-        let mut tx = TargetTransaction::new(&mut connection, self.settings.clone(), on_script)?;
+        let result = self.run_migration(&mut connection, on_script, true, on_progress);
+        if self.foreign_keys_enabled {
+            connection
+                .execute("PRAGMA foreign_keys = ON")
+                .map_err(|e| {
+                    MigrationError::QueryFailure("Failed to re-enable foreign keys".to_owned(), e)
+                })?;
+        }
+        info!("Migration completed");
+        result.map(|_| self.timings)
+    }
+
+    pub fn migrate_to_sql(&mut self) -> Result<String, MigrationError> {
+        let options = self.settings.options.clone();
+        self.settings.options.dry_run = true;
+        let result = self.collect_script();
+        self.settings.options = options;
+        result
+    }
+
+    /// Runs the migration against an in-memory copy of the target and checks that the result
+    /// matches the source schema, returning [`MigrationError::VerificationFailed`] with any
+    /// remaining differences. The target itself is never modified.
+    pub fn verify_after_dry_run(&mut self) -> Result<(), MigrationError> {
+        let copy = self
+            .target_connection
+            .lock()
+            .expect("Failed to lock mutex")
+            .copy_to_memory()
+            .map_err(|e| MigrationError::BackupFailure(PathBuf::from(":memory:"), e))?;
+        let mut settings = self.settings.clone();
+        settings.options.dry_run = false;
+        settings.options.backup_path = None;
+        // Attached databases are created from scratch so the real files are never touched
+        for (_, path) in &mut settings.config.attachments {
+            *path = PathBuf::from(":memory:");
+        }
+        let mut connection = TargetConnection::new(copy, settings.clone())
+            .map_err(MigrationError::InitializationFailure)?;
+        connection
+            .execute("PRAGMA foreign_keys = OFF")
+            .map_err(|e| {
+                MigrationError::QueryFailure("Failed to disable foreign keys".to_owned(), e)
+            })?;
+
+        let original_settings = std::mem::replace(&mut self.settings, settings);
+        let read_only = std::mem::replace(&mut self.read_only, false);
+        let timings = std::mem::take(&mut self.timings);
+        let result = self.run_migration(&mut connection, |_| {}, false, |_| {});
+        self.settings = original_settings;
+        self.read_only = read_only;
+        self.timings = timings;
+        result?;
+
+        let source = self
+            .parse_metadata()
+            .map_err(|e| {
+                MigrationError::QueryFailure("Failed to get database metadata".to_owned(), e)
+            })?
+            .source;
+        let migrated = connection
+            .parse_metadata()
+            .map_err(|e| {
+                MigrationError::QueryFailure("Failed to get database metadata".to_owned(), e)
+            })?
+            .match_name_case(&source);
+        let keep_extra = self.settings.options.on_extra_objects == ExtraPolicy::Keep;
+        let settings = &self.settings;
+        let differences: Vec<String> = source
+            .diff_summary(&migrated, &self.settings.config)
+            .into_iter()
+            .flat_map(|(object_type, changes)| {
+                let source_objects = source.get(&object_type);
+                let migrated_objects = migrated.get(&object_type);
+                let is_table = object_type == ObjectType::Table;
+                changes
+                    .into_iter()
+                    .filter(|(_, change_kind)| !(keep_extra && *change_kind == ChangeKind::Removed))
+                    // Differences the migration itself is configured to ignore aren't failures
+                    .filter(move |(name, change_kind)| {
+                        *change_kind != ChangeKind::Modified
+                            || !is_table
+                            || settings
+                                .table_changed(&migrated_objects[name], &source_objects[name])
+                    })
+                    .map(move |(name, change_kind)| {
+                        format!("{object_type:?} {name}: {change_kind:?}")
+                    })
+            })
+            .collect();
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(MigrationError::VerificationFailed(differences))
+        }
+    }
+
+    pub fn dry_run_report(&mut self) -> Result<MigrationReport, MigrationError> {
+        let metadata = self.parse_metadata().map_err(|e| {
+            MigrationError::QueryFailure("Failed to get database metadata".to_owned(), e)
+        })?;
+        let source_tables = metadata.source.tables();
+        let target_tables = metadata.target.tables();
+        // Objects that only exist in the target are left alone unless the policy drops them
+        let drop_extra = self.settings.options.on_extra_objects == ExtraPolicy::Drop;
+
+        let tables_dropped_names: Vec<String> = target_tables
+            .keys()
+            .filter(|k| !source_tables.contains_key(*k))
+            .filter(|_| drop_extra)
+            .cloned()
+            .collect();
+        let modified_tables: Vec<(&String, &String, &String)> = source_tables
+            .iter()
+            .filter_map(|(name, sql)| {
+                let existing = target_tables.get(name)?;
+                self.settings
+                    .table_changed(existing, sql)
+                    .then_some((name, existing, sql))
+            })
+            .collect();
+
+        let mut columns_dropped = vec![];
+        let mut rows_copied = vec![];
+        for (table, existing_sql, sql) in &modified_tables {
+            let pristine_cols = self.pristine()?.get_cols(table).map_err(|e| {
+                MigrationError::QueryFailure(format!("Error getting columns for table {table}"), e)
+            })?;
+            let mut target_connection =
+                self.target_connection.lock().expect("Failed to lock mutex");
+            let cols = target_connection.get_cols(table).map_err(|e| {
+                MigrationError::QueryFailure(format!("Error getting columns for table {table}"), e)
+            })?;
+            // Virtual tables and added columns don't require copying any data
+            if !is_virtual_table_change(existing_sql, sql)
+                && added_columns(existing_sql, sql, &cols, &pristine_cols).is_none()
+            {
+                let rows = target_connection.count_rows(table).map_err(|e| {
+                    MigrationError::QueryFailure(format!("Error counting rows in table {table}"), e)
+                })?;
+                rows_copied.push((table.to_string(), rows));
+            }
+            columns_dropped.extend(
+                cols.into_iter()
+                    .filter(|c| !pristine_cols.contains(c))
+                    .map(|c| (table.to_string(), c)),
+            );
+        }
+
+        Ok(MigrationReport {
+            tables_created: source_tables
+                .keys()
+                .filter(|k| !target_tables.contains_key(*k))
+                .count(),
+            tables_dropped: tables_dropped_names.len(),
+            tables_modified: modified_tables.len(),
+            indexes_changed: changed_object_count(
+                |existing, sql| self.settings.config.sql_changed(existing, sql),
+                metadata.target.indexes(),
+                metadata.source.indexes(),
+                drop_extra,
+            ),
+            views_changed: changed_object_count(
+                |existing, sql| self.settings.config.sql_changed(existing, sql),
+                metadata.target.views(),
+                metadata.source.views(),
+                drop_extra,
+            ),
+            triggers_changed: changed_object_count(
+                |existing, sql| self.settings.config.sql_changed(existing, sql),
+                metadata.target.triggers(),
+                metadata.source.triggers(),
+                drop_extra,
+            ),
+            columns_dropped,
+            tables_dropped_names,
+            rows_copied,
+        })
+    }
+
+    pub fn rollback_script(&mut self) -> Result<String, MigrationError> {
+        let metadata = self.parse_metadata().map_err(|e| {
+            MigrationError::QueryFailure("Failed to get database metadata".to_owned(), e)
+        })?;
+        let connection = Connection::open_in_memory().map_err(|e| {
+            MigrationError::InitializationFailure(InitializationError::ConnectionFailure(
+                ":memory:".to_owned(),
+                e,
+            ))
+        })?;
+        // The source schema becomes the target of the reverse migration. Hooks aren't replayed
+        // since they've already been applied to the source schema.
+        let config = Config {
+            before_migration: vec![],
+            after_migration: vec![],
+            attachments: self
+                .settings
+                .config
+                .attachments
+                .iter()
+                .map(|(alias, _)| (alias.to_owned(), PathBuf::from(":memory:")))
+                .collect(),
+            ..self.settings.config.clone()
+        };
+        let mut reverse_migrator = Migrator::new(
+            &metadata.target.create_statements(),
+            connection,
+            config,
+            Options {
+                allow_deletions: true,
+                vacuum: VacuumMode::Never,
+                ..Default::default()
+            },
+        )
+        .map_err(MigrationError::InitializationFailure)?;
+        {
+            let mut reverse_target = reverse_migrator
+                .target_connection
+                .lock()
+                .expect("Failed to lock mutex");
+            for statement in metadata.source.create_statements() {
+                reverse_target.execute(&statement).map_err(|e| {
+                    MigrationError::QueryFailure("Error initializing source schema".to_owned(), e)
+                })?;
+            }
+        }
+        // The reverse migration is applied for real since it only touches the in-memory copy.
+        // This keeps the script accurate when tables are rebuilt.
+        reverse_migrator.collect_script()
+    }
+
+    fn collect_script(&mut self) -> Result<String, MigrationError> {
+        let connection_rc = self.target_connection.clone();
+        let mut connection = connection_rc.lock().expect("Failed to lock mutex");
+
+        let mut statements = vec![];
+        self.run_migration(
+            &mut connection,
+            |statement| statements.push(statement.sql.trim().to_owned()),
+            false,
+            |_| {},
+        )?;
+
+        Ok(statements
+            .into_iter()
+            .map(|statement| {
+                if statement.ends_with(';') || statement.starts_with("--") {
+                    statement
+                } else {
+                    statement + ";"
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn run_migration(
+        &mut self,
+        connection: &mut TargetConnection,
+        on_script: impl FnMut(ScriptEvent),
+        format_script: bool,
+        on_progress: impl FnMut(MigrationProgress),
+    ) -> Result<(), MigrationError> {
+        if self.read_only {
+            return Err(MigrationError::ReadOnly);
+        }
+        self.timings = MigrationTimings::default();
+        let start = Instant::now();
+        if self.settings.options.pre_integrity_check {
+            info!("Checking target database integrity");
+            let errors = match connection.integrity_check() {
+                Ok(errors) => errors,
+                // Severe corruption can prevent the check from running at all
+                Err(e) if e.1.sqlite_error_code() == Some(rusqlite::ErrorCode::DatabaseCorrupt) => {
+                    vec![e.1.to_string()]
+                }
+                Err(e) => {
+                    return Err(MigrationError::QueryFailure(
+                        "Failed to check database integrity".to_owned(),
+                        e,
+                    ))
+                }
+            };
+            if !errors.is_empty() {
+                return Err(MigrationError::IntegrityCheckFailed(errors));
+            }
+        }
+        if let Some(backup_path) = &self.settings.options.backup_path {
+            if !self.settings.options.dry_run {
+                connection.backup(&timestamped_path(backup_path))?;
+            }
+        }
+        let target_empty = connection.is_empty().map_err(|e| {
+            MigrationError::QueryFailure("Failed to check if the target is empty".to_owned(), e)
+        })?;
+        if target_empty {
+            info!("Target database is empty, creating the schema from scratch");
+        }
+        let page_size_changed = self.page_size_changed(connection)?;
+        let mut tx = TargetTransaction::new(
+            connection,
+            self.settings.clone(),
+            on_script,
+            format_script,
+            on_progress,
+        )?;
+        // These statements are run outside of the transaction, but they're included in the
+        // script so it can be applied on its own
+        if self.foreign_keys_enabled {
+            tx.emit_script("PRAGMA foreign_keys = OFF");
+        }
+        tx.emit_script("BEGIN");
 
         let migration_span = span!(Level::INFO, "Starting migration");
         let _migration_guard = migration_span.entered();
         let migrate_result = self.migrate_inner(&mut tx);
 
-        let result = match migrate_result {
+        match migrate_result {
             Ok(()) => {
-                let modified = tx.modified();
+                let modified = tx.modified().map_err(|e| {
+                    MigrationError::QueryFailure("Failed to read change counters".to_owned(), e)
+                })?;
+                tx.emit_script("COMMIT");
+                if self.foreign_keys_enabled {
+                    tx.emit_script("PRAGMA foreign_keys = ON");
+                }
+                for (pragma, value) in &self.settings.config.target_pragmas {
+                    tx.emit_script(&format!("PRAGMA {pragma} = {value}"));
+                    if page_size_changed && is_page_size(pragma) {
+                        tx.emit_script("VACUUM");
+                    }
+                }
                 tx.commit()?;
-                if modified {
-                    connection.vacuum().map_err(|e| {
-                        MigrationError::QueryFailure("Failed to vacuum database".to_owned(), e)
+                let vacuumed = self.apply_target_pragmas(connection, page_size_changed)?;
+                match self.settings.options.vacuum {
+                    VacuumMode::Never => {
+                        debug!("Vacuum disabled, not optimizing database");
+                    }
+                    VacuumMode::IfModified if !modified => {
+                        debug!("No changes detected, not optimizing database");
+                    }
+                    _ if vacuumed => {
+                        debug!("Database was vacuumed after changing the page size");
+                    }
+                    // A newly created database has no free pages to reclaim
+                    VacuumMode::IfModified if target_empty => {
+                        debug!("Target was empty, not optimizing database");
+                    }
+                    VacuumMode::Always | VacuumMode::IfModified => {
+                        connection.vacuum().map_err(|e| {
+                            MigrationError::QueryFailure("Failed to vacuum database".to_owned(), e)
+                        })?;
+                    }
+                }
+                if self.settings.options.analyze {
+                    connection.analyze().map_err(|e| {
+                        MigrationError::QueryFailure("Failed to analyze database".to_owned(), e)
+                    })?;
+                }
+                if self.settings.options.checkpoint {
+                    connection.checkpoint().map_err(|e| {
+                        MigrationError::QueryFailure("Failed to checkpoint database".to_owned(), e)
                     })?;
-                } else {
-                    debug!("No changes detected, not optimizing database");
                 }
+                self.timings.total = start.elapsed();
+                debug!(
+                    duration_ms = self.timings.total.as_millis() as u64,
+                    "Finished migration"
+                );
                 Ok(())
             }
             Err(e) => {
                 tx.rollback()?;
                 Err(e)
             }
+        }
+    }
+
+    fn page_size_changed(&self, connection: &mut TargetConnection) -> Result<bool, MigrationError> {
+        let Some((_, page_size)) = self
+            .settings
+            .config
+            .target_pragmas
+            .iter()
+            .find(|(pragma, _)| is_page_size(pragma))
+        else {
+            return Ok(false);
         };
-        if self.foreign_keys_enabled {
-            connection
-                .execute("PRAGMA foreign_keys = ON")
-                .map_err(|e| {
-                    MigrationError::QueryFailure("Failed to re-enable foreign keys".to_owned(), e)
+        let current_page_size: i64 = connection.get_pragma("page_size").map_err(|e| {
+            MigrationError::QueryFailure("Failed to get the current page size".to_owned(), e)
+        })?;
+        Ok(page_size.trim().parse::<i64>().ok() != Some(current_page_size))
+    }
+
+    fn apply_target_pragmas(
+        &self,
+        connection: &mut TargetConnection,
+        page_size_changed: bool,
+    ) -> Result<bool, MigrationError> {
+        // The target connection's settings aren't updated when generating a script
+        if self.settings.options.dry_run {
+            return Ok(false);
+        }
+        let mut vacuumed = false;
+        for (pragma, value) in &self.settings.config.target_pragmas {
+            info!("Setting {pragma} to {value}");
+            connection.set_pragma(pragma, value).map_err(|e| {
+                MigrationError::QueryFailure(format!("Failed to set pragma {pragma}"), e)
+            })?;
+            // The new page size isn't applied until the database is rebuilt
+            if page_size_changed && is_page_size(pragma) {
+                connection.vacuum().map_err(|e| {
+                    MigrationError::QueryFailure("Failed to vacuum database".to_owned(), e)
                 })?;
+                vacuumed = true;
+            }
         }
-        info!("Migration completed");
-        result
+        Ok(vacuumed)
     }
 
     fn migrate_inner<F>(&mut self, tx: &mut TargetTransaction<F>) -> Result<(), MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
-        if self.foreign_keys_enabled {
-            tx.execute("PRAGMA defer_foreign_keys = TRUE")
-                .map_err(|e| {
-                    MigrationError::QueryFailure("Error enabling defer_foreign_keys".to_owned(), e)
-                })?;
+        self.check_cancelled()?;
+        // Enforcement follows the source schema so a target with foreign keys disabled is still
+        // validated
+        let enforce_foreign_keys = self.source_foreign_keys_enabled()?;
+        if enforce_foreign_keys {
+            defer_foreign_keys(tx)?;
         }
 
         if !self.settings.config.before_migration.is_empty() {
             let object_span = span!(Level::INFO, "Executing pre-migration scripts");
             let _object_guard = object_span.entered();
+            let start = Instant::now();
+            tx.emit_comment("Run pre-migration scripts");
             tx.execute_batch(&self.settings.config.before_migration)
                 .map_err(|e| {
                     MigrationError::QueryFailure(
@@ -191,62 +1292,95 @@ impl Migrator {
                         e,
                     )
                 })?;
+            self.record_timing("Executing pre-migration scripts", start);
         }
 
-        let pristine_metadata = self.pristine.parse_metadata().map_err(|e| {
+        let pristine_metadata = self.pristine()?.parse_metadata().map_err(|e| {
             MigrationError::QueryFailure(
                 "Failed to get metadata from pristine database".to_owned(),
                 e,
             )
         })?;
 
-        self.migrate_tables(tx, &pristine_metadata)?;
+        self.check_cancelled()?;
+        let dropped_objects = self.migrate_tables(tx, &pristine_metadata)?;
 
-        let metadata = tx.parse_metadata().map_err(|e| {
-            MigrationError::QueryFailure(
-                "Failed to get metadata from current database".to_owned(),
-                e,
-            )
-        })?;
+        let mut metadata = tx
+            .parse_metadata()
+            .map_err(|e| {
+                MigrationError::QueryFailure(
+                    "Failed to get metadata from current database".to_owned(),
+                    e,
+                )
+            })?
+            .match_name_case(&pristine_metadata);
+        // Dry runs don't actually drop the rebuilt tables, so their objects are still in the target
+        for (object_type, name) in &dropped_objects {
+            metadata.remove(object_type, name);
+        }
 
+        self.check_cancelled()?;
         {
             let object_span = span!(Level::INFO, "Migrating indexes");
             let _object_guard = object_span.entered();
+            let start = Instant::now();
             self.migrate_objects(
                 tx,
                 metadata.indexes(),
                 pristine_metadata.indexes(),
                 "index",
                 "indexes",
+                Phase::Indexes,
             )?;
+            self.record_timing("Migrating indexes", start);
         }
 
+        self.check_cancelled()?;
         {
             let object_span = span!(Level::INFO, "Migrating views");
             let _object_guard = object_span.entered();
+            let start = Instant::now();
             self.migrate_objects(
                 tx,
                 metadata.views(),
                 pristine_metadata.views(),
                 "view",
                 "views",
+                Phase::Views,
             )?;
+            self.record_timing("Migrating views", start);
         }
 
+        self.check_cancelled()?;
         {
             let object_span = span!(Level::INFO, "Migrating triggers");
             let _object_guard = object_span.entered();
+            let start = Instant::now();
             self.migrate_objects(
                 tx,
                 metadata.triggers(),
                 pristine_metadata.triggers(),
                 "trigger",
                 "triggers",
+                Phase::Triggers,
             )?;
+            self.record_timing("Migrating triggers", start);
+        }
+
+        self.check_cancelled()?;
+        if metadata.user_version() != pristine_metadata.user_version() {
+            let user_version = pristine_metadata.user_version();
+            info!("Setting user_version to {user_version}");
+            tx.execute(&format!("PRAGMA user_version = {user_version}"))
+                .map_err(|e| {
+                    MigrationError::QueryFailure("Failed to set user_version".to_owned(), e)
+                })?;
         }
         if !self.settings.config.after_migration.is_empty() {
             let object_span = span!(Level::INFO, "Executing post-migration scripts");
             let _object_guard = object_span.entered();
+            let start = Instant::now();
+            tx.emit_comment("Run post-migration scripts");
             tx.execute_batch(&self.settings.config.after_migration)
                 .map_err(|e| {
                     MigrationError::QueryFailure(
@@ -254,10 +1388,18 @@ impl Migrator {
                         e,
                     )
                 })?;
+            self.record_timing("Executing post-migration scripts", start);
         }
 
-        if self
-            .pristine
+        if enforce_foreign_keys {
+            check_foreign_keys(tx)?;
+        }
+        self.check_cancelled()
+    }
+
+    fn source_foreign_keys_enabled(&mut self) -> Result<bool, MigrationError> {
+        Ok(self
+            .pristine()?
             .get_pragma::<i32>("foreign_keys")
             .map_err(|e| {
                 MigrationError::QueryFailure(
@@ -265,43 +1407,68 @@ impl Migrator {
                     e,
                 )
             })?
-            == 1
-        {
-            let foreign_key_violations: Vec<String> = tx
-                .query("PRAGMA foreign_key_check", |row| row.get(0))
-                .map_err(|e| {
-                    MigrationError::QueryFailure("Error executing foreign key check".to_owned(), e)
-                })?;
-            if !foreign_key_violations.is_empty() {
-                return Err(MigrationError::ForeignKeyViolation(foreign_key_violations));
+            == 1)
+    }
+
+    fn record_timing(&mut self, phase: &'static str, start: Instant) {
+        let duration = start.elapsed();
+        debug!(
+            phase,
+            duration_ms = duration.as_millis() as u64,
+            "Finished phase"
+        );
+        self.timings.phases.push(PhaseTiming { phase, duration });
+    }
+
+    fn check_cancelled(&self) -> Result<(), MigrationError> {
+        match &self.cancellation_token {
+            Some(token) if token.is_cancelled() => {
+                warn!("Migration cancelled, rolling back");
+                Err(MigrationError::Cancelled)
             }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
+    // Returns the indexes and triggers that were dropped along with rebuilt tables and still need
+    // to be created
     fn migrate_tables<F>(
         &mut self,
         tx: &mut TargetTransaction<F>,
         pristine_metadata: &Metadata,
-    ) -> Result<(), MigrationError>
+    ) -> Result<Vec<(ObjectType, String)>, MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
         let table_span = span!(Level::INFO, "Migrating tables");
         let _table_guard = table_span.entered();
+        let start = Instant::now();
 
-        let metadata = tx.parse_metadata().map_err(|e| {
-            MigrationError::QueryFailure(
-                "Failed to get metadata from current database".to_owned(),
-                e,
-            )
-        })?;
+        let metadata = tx
+            .parse_metadata()
+            .map_err(|e| {
+                MigrationError::QueryFailure(
+                    "Failed to get metadata from current database".to_owned(),
+                    e,
+                )
+            })?
+            .match_name_case(pristine_metadata);
 
+        tx.start_phase(
+            Phase::Tables,
+            changed_object_count(
+                |existing, sql| self.settings.table_changed(existing, sql),
+                metadata.tables(),
+                pristine_metadata.tables(),
+                self.settings.options.on_extra_objects == ExtraPolicy::Drop,
+            ),
+        );
         self.create_new_tables(tx, pristine_metadata, &metadata)?;
         self.drop_old_tables(tx, pristine_metadata, &metadata)?;
-        self.update_tables(tx, pristine_metadata, &metadata)?;
+        let dropped_objects = self.update_tables(tx, pristine_metadata, &metadata)?;
+        self.record_timing("Migrating tables", start);
 
-        Ok(())
+        Ok(dropped_objects)
     }
 
     fn create_new_tables<F>(
@@ -311,14 +1478,14 @@ impl Migrator {
         metadata: &Metadata,
     ) -> Result<(), MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
         let create_table_span = span!(Level::INFO, "Creating tables");
         let _create_table_guard = create_table_span.entered();
+        let start = Instant::now();
 
-        let new_tables: BTreeMap<&String, &String> = pristine_metadata
-            .tables()
-            .iter()
+        let new_tables: Vec<(&String, &String)> = sort_by_dependencies(pristine_metadata.tables())
+            .into_iter()
             .filter(|(k, _)| !metadata.tables().contains_key(*k))
             .collect();
 
@@ -326,11 +1493,16 @@ impl Migrator {
             info!("No tables to create");
         }
         for (new_table, new_table_sql) in new_tables {
+            let _fields =
+                span!(target: FIELDS_TARGET, Level::INFO, "table", table = %new_table).entered();
             info!("Creating table {new_table}");
+            tx.emit_comment(&format!("Create table {new_table}"));
             tx.execute(new_table_sql).map_err(|e| {
                 MigrationError::QueryFailure(format!("Error creating table {new_table}"), e)
             })?;
+            tx.advance_progress(new_table);
         }
+        self.record_timing("Creating tables", start);
         Ok(())
     }
 
@@ -341,103 +1513,232 @@ impl Migrator {
         metadata: &Metadata,
     ) -> Result<(), MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
         let drop_table_span = span!(Level::INFO, "Dropping tables");
         let _drop_table_guard = drop_table_span.entered();
+        let start = Instant::now();
 
         let removed_tables: Vec<&String> = metadata
             .tables()
             .keys()
             .filter(|k| !pristine_metadata.tables().contains_key(*k))
             .collect();
-
-        if !removed_tables.is_empty() && !self.settings.options.allow_deletions {
-            let removed_table_list = removed_tables
-                .into_iter()
-                .map(|t| t.to_owned())
-                .collect::<Vec<_>>()
-                .join(",");
-            return Err(MigrationError::DataLoss(format!(
-                "The following tables would be removed: {removed_table_list}"
-            )));
-        }
+        let removed_tables = self.extra_objects(removed_tables, "tables")?;
 
         if removed_tables.is_empty() {
             info!("No tables to drop");
         }
         for removed_table in removed_tables {
+            let _fields =
+                span!(target: FIELDS_TARGET, Level::INFO, "table", table = %removed_table)
+                    .entered();
             info!("Dropping table {removed_table}");
+            tx.emit_comment(&format!("Drop table {removed_table}"));
             tx.execute(&format!("DROP TABLE {removed_table}"))
                 .map_err(|e| {
                     MigrationError::QueryFailure(format!("Error dropping table {removed_table}"), e)
                 })?;
+            tx.advance_progress(removed_table);
         }
+        self.record_timing("Dropping tables", start);
         Ok(())
     }
 
+    /// Applies the extra object policy to objects that only exist in the target, returning the
+    /// ones that should be dropped
+    fn extra_objects<'a>(
+        &self,
+        objects: Vec<&'a String>,
+        object_name_plural: &str,
+    ) -> Result<Vec<&'a String>, MigrationError> {
+        if objects.is_empty() {
+            return Ok(objects);
+        }
+        let object_list = objects
+            .iter()
+            .map(|o| o.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.settings.options.on_extra_objects {
+            ExtraPolicy::Drop if !self.settings.options.allow_deletions => {
+                Err(MigrationError::DataLoss(format!(
+                    "The following {object_name_plural} would be removed: {object_list}"
+                )))
+            }
+            ExtraPolicy::Drop => Ok(objects),
+            ExtraPolicy::Error => Err(MigrationError::ExtraObjects(format!(
+                "{object_name_plural}: {object_list}"
+            ))),
+            ExtraPolicy::Keep => {
+                info!("Keeping {object_name_plural} not in the source: {object_list}");
+                Ok(vec![])
+            }
+        }
+    }
+
     fn update_tables<F>(
         &mut self,
         tx: &mut TargetTransaction<F>,
         pristine_metadata: &Metadata,
         metadata: &Metadata,
-    ) -> Result<(), MigrationError>
+    ) -> Result<Vec<(ObjectType, String)>, MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
         let modify_table_span = span!(Level::INFO, "Modifying tables");
         let _modify_table_guard = modify_table_span.entered();
+        let start = Instant::now();
 
-        let modified_tables: BTreeMap<&String, &String> = pristine_metadata
-            .tables()
-            .iter()
-            .filter(|(name, sql)| {
-                if let Some(existing) = metadata.tables().get(*name) {
-                    normalize_sql(existing) != normalize_sql(sql)
-                } else {
-                    false
-                }
-            })
-            .collect();
+        let modified_tables: Vec<(&String, &String, &String)> =
+            sort_by_dependencies(pristine_metadata.tables())
+                .into_iter()
+                .filter_map(|(name, sql)| {
+                    let existing = metadata.tables().get(name)?;
+                    self.settings
+                        .table_changed(existing, sql)
+                        .then_some((name, existing, sql))
+                })
+                .collect();
 
         if modified_tables.is_empty() {
             info!("No tables to modify");
         }
-        for (modified_table, modified_table_sql) in modified_tables {
-            self.update_table(tx, modified_table, modified_table_sql)?;
+        let mut dropped_objects = vec![];
+        for (modified_table, existing_sql, modified_table_sql) in modified_tables {
+            self.check_cancelled()?;
+            dropped_objects.extend(self.update_table(
+                tx,
+                pristine_metadata,
+                metadata,
+                modified_table,
+                existing_sql,
+                modified_table_sql,
+            )?);
+            tx.advance_progress(modified_table);
         }
-        Ok(())
+        self.record_timing("Modifying tables", start);
+        Ok(dropped_objects)
+    }
+
+    // Dropping a table also drops its indexes and triggers, so the unchanged ones are restored right
+    // away. New and changed ones are skipped since they're created when migrating their object type.
+    // Returns the objects that were dropped without being restored.
+    fn restore_table_objects<F>(
+        &mut self,
+        tx: &mut TargetTransaction<F>,
+        pristine_metadata: &Metadata,
+        metadata: &Metadata,
+        object_type: ObjectType,
+        object_name: &str,
+        objects: Vec<String>,
+    ) -> Result<Vec<(ObjectType, String)>, MigrationError>
+    where
+        F: FnMut(ScriptEvent),
+    {
+        let mut dropped = vec![];
+        for object in objects {
+            // Target metadata names are matched to the case used by the source
+            let Some((object, existing_sql)) = metadata
+                .get(&object_type)
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&object))
+            else {
+                continue;
+            };
+            match pristine_metadata.get(&object_type).get(object) {
+                Some(sql) if !self.settings.config.sql_changed(existing_sql, sql) => {
+                    let _fields = span!(
+                        target: FIELDS_TARGET,
+                        Level::INFO,
+                        "object",
+                        object_type = %object_name,
+                        object = %object
+                    )
+                    .entered();
+                    info!("Restoring {object_name} {object}");
+                    tx.execute(sql).map_err(|e| {
+                        MigrationError::QueryFailure(
+                            format!("Error creating {object_name} {object}"),
+                            e,
+                        )
+                    })?;
+                }
+                _ => dropped.push((object_type.clone(), object.to_owned())),
+            }
+        }
+        Ok(dropped)
     }
 
     fn update_table<F>(
         &mut self,
         tx: &mut TargetTransaction<F>,
+        pristine_metadata: &Metadata,
+        metadata: &Metadata,
         modified_table: &str,
+        existing_sql: &str,
         modified_table_sql: &str,
-    ) -> Result<(), MigrationError>
+    ) -> Result<Vec<(ObjectType, String)>, MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
+        let _fields =
+            span!(target: FIELDS_TARGET, Level::INFO, "table", table = %modified_table).entered();
         info!("Modifying table {modified_table}");
-        let temp_table = format!("{modified_table}_migration_new");
-        let create_table_regex = Regex::new(&format!(r"\b{}\b", regex::escape(modified_table)))
-            .expect("Regex failed to compile");
-        let create_temp_table_sql = create_table_regex.replace_all(modified_table_sql, &temp_table);
-        tx.execute(&create_temp_table_sql).map_err(|e| {
-            MigrationError::QueryFailure(format!("Error creating temp table {temp_table}"), e)
-        })?;
+        if is_virtual_table_change(existing_sql, modified_table_sql) {
+            self.recreate_virtual_table(tx, modified_table, modified_table_sql)?;
+            return Ok(vec![]);
+        }
         let cols = tx.get_cols(modified_table).map_err(|e| {
             MigrationError::QueryFailure(
                 format!("Error getting columns for table {modified_table}"),
                 e,
             )
         })?;
-        let pristine_cols = self.pristine.get_cols(modified_table).map_err(|e| {
+        let pristine_cols = self.pristine()?.get_cols(modified_table).map_err(|e| {
             MigrationError::QueryFailure(
                 format!("Error getting columns for table {modified_table}"),
                 e,
             )
         })?;
+
+        if let Some(new_columns) =
+            added_columns(existing_sql, modified_table_sql, &cols, &pristine_cols)
+        {
+            tx.emit_comment(&format!(
+                "Add {} to table {modified_table}",
+                pluralize(new_columns.len(), "column")
+            ));
+            for column in new_columns {
+                let _fields =
+                    span!(target: FIELDS_TARGET, Level::INFO, "column", column = %column).entered();
+                info!("Adding column to table {modified_table}: {column}");
+                tx.execute(&format!("ALTER TABLE {modified_table} ADD COLUMN {column}"))
+                    .map_err(|e| {
+                        MigrationError::QueryFailure(
+                            format!("Error adding column to table {modified_table}"),
+                            e,
+                        )
+                    })?;
+            }
+            return Ok(vec![]);
+        }
+
+        tx.emit_comment(&format!(
+            "Rebuild table {modified_table} ({} added, {} removed)",
+            pluralize(
+                pristine_cols.iter().filter(|c| !cols.contains(c)).count(),
+                "column"
+            ),
+            cols.iter().filter(|c| !pristine_cols.contains(c)).count()
+        ));
+        let temp_table = format!("{modified_table}_migration_new");
+        let create_table_regex = Regex::new(&format!(r"\b{}\b", regex::escape(modified_table)))
+            .expect("Regex failed to compile");
+        let create_temp_table_sql = create_table_regex.replace_all(modified_table_sql, &temp_table);
+        tx.execute(&create_temp_table_sql).map_err(|e| {
+            MigrationError::QueryFailure(format!("Error creating temp table {temp_table}"), e)
+        })?;
         let removed_cols: Vec<&String> =
             cols.iter().filter(|c| !pristine_cols.contains(c)).collect();
         if !self.settings.options.allow_deletions && !removed_cols.is_empty() {
@@ -455,12 +1756,45 @@ impl Migrator {
             .filter(|c| pristine_cols.contains(c))
             .collect::<Vec<_>>()
             .join(",");
-        tx.execute(&format!(
-            "INSERT INTO {temp_table} ({common_cols}) SELECT {common_cols} FROM {modified_table}"
-        ))
-        .map_err(|e| {
+        // Copying a large table can take a while, so let the user know it's still working
+        let large_copy = !self.settings.options.dry_run
+            && tx
+                .estimate_rows(modified_table)
+                .is_some_and(|rows| rows >= LARGE_COPY_ROWS);
+        if large_copy {
+            info!("Copying rows into {temp_table}, this may take a while");
+        }
+        let copy_start = Instant::now();
+        let rows = tx
+            .execute_with_rows(&format!(
+                "INSERT INTO {temp_table} ({common_cols}) SELECT {common_cols} FROM {modified_table}"
+            ))
+            .map_err(|e| {
+                MigrationError::QueryFailure(
+                    format!("Error migrating data into table {modified_table}"),
+                    e,
+                )
+            })?;
+        if let Some(rows) = rows.filter(|rows| large_copy || *rows as i64 >= LARGE_COPY_ROWS) {
+            let _fields = span!(target: FIELDS_TARGET, Level::INFO, "copy", rows).entered();
+            info!(
+                "Copied {rows} rows into {temp_table} in {:.2?}",
+                copy_start.elapsed()
+            );
+        }
+        let (schema, unqualified_table) = self.settings.config.split_schema(modified_table);
+        if AUTOINCREMENT_RE.is_match(modified_table_sql) {
+            self.preserve_sequence(tx, schema, unqualified_table, &temp_table)?;
+        }
+        let indexes = tx.get_indexes(modified_table).map_err(|e| {
+            MigrationError::QueryFailure(
+                format!("Error getting indexes for table {modified_table}"),
+                e,
+            )
+        })?;
+        let triggers = tx.get_triggers(modified_table).map_err(|e| {
             MigrationError::QueryFailure(
-                format!("Error migrating data into table {modified_table}"),
+                format!("Error getting triggers for table {modified_table}"),
                 e,
             )
         })?;
@@ -468,14 +1802,101 @@ impl Migrator {
             .map_err(|e| {
                 MigrationError::QueryFailure(format!("Error dropping table {modified_table}"), e)
             })?;
+        // SQLite validates every trigger and view while renaming a table, which fails if one of
+        // them references a table that's been dropped to be rebuilt. The legacy behavior skips
+        // that check and nothing needs to be rewritten since the table keeps its original name.
+        let set_legacy_alter_table = |tx: &mut TargetTransaction<F>, enabled: bool| {
+            tx.execute(&format!(
+                "PRAGMA legacy_alter_table = {}",
+                if enabled { "ON" } else { "OFF" }
+            ))
+            .map_err(|e| {
+                MigrationError::QueryFailure("Failed to set legacy_alter_table".to_owned(), e)
+            })
+        };
+        set_legacy_alter_table(tx, true)?;
+        let renamed = tx
+            .execute(&format!(
+                "ALTER TABLE {temp_table} RENAME TO {unqualified_table}"
+            ))
+            .map_err(|e| {
+                MigrationError::QueryFailure(
+                    format!("Error renaming {temp_table} to {modified_table}"),
+                    e,
+                )
+            });
+        set_legacy_alter_table(tx, false)?;
+        renamed?;
+
+        let mut dropped = self.restore_table_objects(
+            tx,
+            pristine_metadata,
+            metadata,
+            ObjectType::Index,
+            "index",
+            indexes,
+        )?;
+        dropped.extend(self.restore_table_objects(
+            tx,
+            pristine_metadata,
+            metadata,
+            ObjectType::Trigger,
+            "trigger",
+            triggers,
+        )?);
+        Ok(dropped)
+    }
+
+    // Copying the rows only moves the sequence up to the largest remaining rowid, so the old
+    // high-water mark is carried over to keep ids of deleted rows from being reused. The renamed
+    // table inherits the sequence entry of the temp table.
+    fn preserve_sequence<F>(
+        &mut self,
+        tx: &mut TargetTransaction<F>,
+        schema: &str,
+        table: &str,
+        temp_table: &str,
+    ) -> Result<(), MigrationError>
+    where
+        F: FnMut(ScriptEvent),
+    {
+        let (_, unqualified_temp_table) = self.settings.config.split_schema(temp_table);
+        // The names are used as string literals here so any quotes need to be escaped
+        let table_literal = table.replace('\'', "''");
+        let temp_table_literal = unqualified_temp_table.replace('\'', "''");
         tx.execute(&format!(
-            "ALTER TABLE {temp_table} RENAME TO {modified_table}"
+            "DELETE FROM {schema}.sqlite_sequence WHERE name = '{temp_table_literal}' AND EXISTS (SELECT 1 FROM {schema}.sqlite_sequence WHERE name = '{table_literal}')"
         ))
+        .and_then(|_| {
+            tx.execute(&format!(
+                "INSERT INTO {schema}.sqlite_sequence(name, seq) SELECT '{temp_table_literal}', seq FROM {schema}.sqlite_sequence WHERE name = '{table_literal}'"
+            ))
+        })
         .map_err(|e| {
-            MigrationError::QueryFailure(
-                format!("Error renaming {temp_table} to {modified_table}"),
-                e,
-            )
+            MigrationError::QueryFailure(format!("Error preserving the sequence for table {table}"), e)
+        })
+    }
+
+    fn recreate_virtual_table<F>(
+        &mut self,
+        tx: &mut TargetTransaction<F>,
+        modified_table: &str,
+        modified_table_sql: &str,
+    ) -> Result<(), MigrationError>
+    where
+        F: FnMut(ScriptEvent),
+    {
+        // Virtual tables manage their own storage so their data can't be copied into a new table.
+        // Modules like fts5 with external content tables can be repopulated with a 'rebuild'
+        // command after the migration.
+        warn!("Recreating virtual table {modified_table}, its contents will not be preserved");
+        tx.emit_comment(&format!("Recreate virtual table {modified_table}"));
+        tx.execute(&format!("DROP TABLE {modified_table}"))
+            .map_err(|e| {
+                MigrationError::QueryFailure(format!("Error dropping table {modified_table}"), e)
+            })?;
+        tx.execute(modified_table_sql).map_err(|e| {
+            MigrationError::QueryFailure(format!("Error creating table {modified_table}"), e)
         })?;
         Ok(())
     }
@@ -487,21 +1908,34 @@ impl Migrator {
         pristine_metadata: &BTreeMap<String, String>,
         object_name: &str,
         object_name_plural: &str,
+        phase: Phase,
     ) -> Result<(), MigrationError>
     where
-        F: FnMut(String),
+        F: FnMut(ScriptEvent),
     {
+        tx.start_phase(
+            phase,
+            changed_object_count(
+                |existing, sql| self.settings.config.sql_changed(existing, sql),
+                target_metadata,
+                pristine_metadata,
+                self.settings.options.on_extra_objects == ExtraPolicy::Drop,
+            ),
+        );
         let old_objects: Vec<_> = target_metadata
             .keys()
             .filter(|k| !pristine_metadata.contains_key(*k))
             .collect();
+        let old_objects = self.extra_objects(old_objects, object_name_plural)?;
 
         if old_objects.is_empty() {
             info!("No {object_name_plural} to drop");
         }
 
         for object in old_objects {
+            let _fields = span!(target: FIELDS_TARGET, Level::INFO, "object", object_type = %object_name, object = %object).entered();
             info!("Dropping {object_name} {object}");
+            tx.emit_comment(&format!("Drop {object_name} {object}"));
             tx.execute(&format!("DROP {} {object}", object_name.to_uppercase()))
                 .map_err(|e| {
                     MigrationError::QueryFailure(
@@ -509,14 +1943,17 @@ impl Migrator {
                         e,
                     )
                 })?;
+            tx.advance_progress(object);
         }
         let mut object_updated = false;
         let mut object_created = false;
         for (object, sql) in pristine_metadata {
             match target_metadata.get(object) {
-                Some(old_object) if normalize_sql(sql) != normalize_sql(old_object) => {
+                Some(old_object) if self.settings.config.sql_changed(old_object, sql) => {
                     object_updated = true;
+                    let _fields = span!(target: FIELDS_TARGET, Level::INFO, "object", object_type = %object_name, object = %object).entered();
                     info!("Updating {object_name} {object}");
+                    tx.emit_comment(&format!("Update {object_name} {object}"));
                     tx.execute(&format!("DROP {} {object}", object_name.to_uppercase()))
                         .map_err(|e| {
                             MigrationError::QueryFailure(
@@ -530,16 +1967,20 @@ impl Migrator {
                             e,
                         )
                     })?;
+                    tx.advance_progress(object);
                 }
                 None => {
                     object_created = true;
+                    let _fields = span!(target: FIELDS_TARGET, Level::INFO, "object", object_type = %object_name, object = %object).entered();
                     info!("Creating {object_name} {object}");
+                    tx.emit_comment(&format!("Create {object_name} {object}"));
                     tx.execute(sql).map_err(|e| {
                         MigrationError::QueryFailure(
                             format!("Error creating {object_name} {object}"),
                             e,
                         )
                     })?;
+                    tx.advance_progress(object);
                 }
                 _ => {}
             }
@@ -555,14 +1996,22 @@ impl Migrator {
     }
 
     pub fn parse_metadata(&mut self) -> Result<MigrationMetadata, QueryError> {
-        Ok(MigrationMetadata {
-            source: self.pristine.parse_metadata()?,
-            target: self
-                .target_connection
-                .lock()
-                .expect("Failed to lock mutex")
-                .parse_metadata()?,
-        })
+        let source = match (&self.cached_source, &mut self.pristine) {
+            (Some(cached), _) => cached.clone(),
+            (None, Some(pristine)) => {
+                let mut source = pristine.parse_metadata()?;
+                source.schema_hash = Some(self.schema_hash.clone());
+                source
+            }
+            (None, None) => unreachable!("Pristine connection is initialized without a cache"),
+        };
+        let target = self
+            .target_connection
+            .lock()
+            .expect("Failed to lock mutex")
+            .parse_metadata()?
+            .match_name_case(&source);
+        Ok(MigrationMetadata { source, target })
     }
 }
 
@@ -576,6 +2025,14 @@ impl MigrationMetadata {
     pub fn unified_objects(&self) -> Vec<Object> {
         self.source.unified_objects(&self.target)
     }
+
+    pub fn source_fingerprint(&self) -> String {
+        self.source.fingerprint()
+    }
+
+    pub fn target_fingerprint(&self) -> String {
+        self.target.fingerprint()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -603,42 +2060,284 @@ impl Ord for Object {
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Objects(BTreeMap<ObjectType, Vec<String>>);
 
 impl Objects {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self(
+            [
+                ObjectType::Table,
+                ObjectType::Index,
+                ObjectType::View,
+                ObjectType::Trigger,
+            ]
+            .into_iter()
+            .map(|object_type| {
+                let names = metadata.get(&object_type).keys().cloned().collect();
+                (object_type, names)
+            })
+            .collect(),
+        )
+    }
+
+    pub fn get(&self, object_type: &ObjectType) -> &[String] {
+        self.0
+            .get(object_type)
+            .map(|o| o.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn tables(&self) -> &[String] {
+        self.get(&ObjectType::Table)
+    }
+
+    pub fn indexes(&self) -> &[String] {
+        self.get(&ObjectType::Index)
+    }
+
+    pub fn views(&self) -> &[String] {
+        self.get(&ObjectType::View)
+    }
+
+    pub fn triggers(&self) -> &[String] {
+        self.get(&ObjectType::Trigger)
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
         self.0.insert(
             ObjectType::Table,
-            sorted_merge(
-                self.0.get(&ObjectType::Table).unwrap(),
-                other.0.get(&ObjectType::Table).unwrap(),
-            ),
+            sorted_merge(self.get(&ObjectType::Table), other.get(&ObjectType::Table)),
         );
         self.0.insert(
             ObjectType::Index,
-            sorted_merge(
-                self.0.get(&ObjectType::Index).unwrap(),
-                other.0.get(&ObjectType::Index).unwrap(),
-            ),
+            sorted_merge(self.get(&ObjectType::Index), other.get(&ObjectType::Index)),
         );
         self.0.insert(
             ObjectType::View,
-            sorted_merge(
-                self.0.get(&ObjectType::View).unwrap(),
-                other.0.get(&ObjectType::View).unwrap(),
-            ),
+            sorted_merge(self.get(&ObjectType::View), other.get(&ObjectType::View)),
         );
         self.0.insert(
             ObjectType::Trigger,
             sorted_merge(
-                self.0.get(&ObjectType::Trigger).unwrap(),
-                other.0.get(&ObjectType::Trigger).unwrap(),
+                self.get(&ObjectType::Trigger),
+                other.get(&ObjectType::Trigger),
             ),
         );
         self
     }
 }
 
+fn apply_script_sections(
+    connection: &mut TargetConnection,
+    settings: Settings,
+    script: &str,
+    foreign_keys_enabled: bool,
+) -> Result<(), MigrationError> {
+    let statements: Vec<(String, String)> = split_statements(script)
+        .into_iter()
+        .map(|statement| {
+            let keyword = WHITESPACE_RE
+                .replace_all(statement.code.trim(), " ")
+                .to_uppercase();
+            (keyword, statement.text)
+        })
+        // Foreign keys are managed here, so the script's own toggles are skipped
+        .filter(|(keyword, _)| !keyword.is_empty() && !keyword.starts_with("PRAGMA FOREIGN_KEYS"))
+        .collect();
+    let begin = statements
+        .iter()
+        .position(|(keyword, _)| matches!(keyword.as_str(), "BEGIN" | "BEGIN TRANSACTION"));
+    let commit = statements.iter().rposition(|(keyword, _)| {
+        matches!(
+            keyword.as_str(),
+            "COMMIT" | "COMMIT TRANSACTION" | "END" | "END TRANSACTION"
+        )
+    });
+    let (before, body, after) = match (begin, commit) {
+        (Some(begin), Some(commit)) if begin < commit => (
+            &statements[..begin],
+            &statements[begin + 1..commit],
+            &statements[commit + 1..],
+        ),
+        _ => (&statements[..0], &statements[..], &statements[..0]),
+    };
+
+    for (_, sql) in before {
+        connection
+            .execute_batch(sql)
+            .map_err(|e| MigrationError::QueryFailure("Error applying script".to_owned(), e))?;
+    }
+    let body: Vec<SqlScript> = body.iter().map(|(_, sql)| sql.as_str().into()).collect();
+    let mut tx = TargetTransaction::new(connection, settings, |_| {}, false, |_| {})?;
+    match apply_script_transaction(&mut tx, &body, foreign_keys_enabled) {
+        Ok(()) => tx.commit()?,
+        Err(e) => {
+            tx.rollback()?;
+            return Err(e);
+        }
+    }
+    for (_, sql) in after {
+        connection
+            .execute_batch(sql)
+            .map_err(|e| MigrationError::QueryFailure("Error applying script".to_owned(), e))?;
+    }
+    Ok(())
+}
+
+fn apply_script_transaction<F>(
+    tx: &mut TargetTransaction<F>,
+    body: &[SqlScript],
+    foreign_keys_enabled: bool,
+) -> Result<(), MigrationError>
+where
+    F: FnMut(ScriptEvent),
+{
+    if foreign_keys_enabled {
+        defer_foreign_keys(tx)?;
+    }
+    tx.execute_batch(body)
+        .map_err(|e| MigrationError::QueryFailure("Error applying script".to_owned(), e))?;
+    if foreign_keys_enabled {
+        check_foreign_keys(tx)?;
+    }
+    Ok(())
+}
+
+fn defer_foreign_keys<F>(tx: &mut TargetTransaction<F>) -> Result<(), MigrationError>
+where
+    F: FnMut(ScriptEvent),
+{
+    if !supports_defer_foreign_keys(rusqlite::version_number()) {
+        // Foreign keys are disabled for the whole migration anyway, so the final foreign key
+        // check is still enough to catch violations
+        debug!(
+            "SQLite {} doesn't support defer_foreign_keys, skipping",
+            rusqlite::version()
+        );
+        return Ok(());
+    }
+    tx.execute("PRAGMA defer_foreign_keys = TRUE").map_err(|e| {
+        MigrationError::QueryFailure("Error enabling defer_foreign_keys".to_owned(), e)
+    })
+}
+
+fn supports_defer_foreign_keys(version_number: i32) -> bool {
+    // defer_foreign_keys was added in SQLite 3.8.0
+    version_number >= 3_008_000
+}
+
+fn check_foreign_keys<F>(tx: &mut TargetTransaction<F>) -> Result<(), MigrationError>
+where
+    F: FnMut(ScriptEvent),
+{
+    let foreign_key_violations: Vec<ForeignKeyViolation> = tx
+        .query("PRAGMA foreign_key_check", |row| {
+            Ok(ForeignKeyViolation {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+                fkid: row.get(3)?,
+            })
+        })
+        .map_err(|e| {
+            MigrationError::QueryFailure("Error executing foreign key check".to_owned(), e)
+        })?;
+    if !foreign_key_violations.is_empty() {
+        return Err(MigrationError::ForeignKeyViolation(foreign_key_violations));
+    }
+    Ok(())
+}
+
+// Foreign keys are disabled on the target while migrating. Returns whether they were enabled so
+// they can be turned back on afterwards.
+fn connect_target(
+    target_connection: Connection,
+    settings: &Settings,
+    read_only: bool,
+) -> Result<(TargetConnection, bool), InitializationError> {
+    let mut target_connection = TargetConnection::new(target_connection, settings.clone())?;
+    // Read-only targets can only be inspected, so there's no need to touch their settings
+    let foreign_keys_enabled = !read_only
+        && !target_connection.is_read_only()
+        && target_connection
+            .get_pragma::<i32>("foreign_keys")
+            .map_err(|e| {
+                InitializationError::QueryFailure(
+                    "Failed to retrieve foreign_keys pragma".to_owned(),
+                    e,
+                )
+            })?
+            == 1;
+    if foreign_keys_enabled {
+        target_connection
+            .execute("PRAGMA foreign_keys = OFF")
+            .map_err(|e| {
+                InitializationError::QueryFailure("Failed to disable foreign keys".to_owned(), e)
+            })?;
+    }
+    Ok((target_connection, foreign_keys_enabled))
+}
+
+fn initialize_pristine(
+    settings: &Settings,
+    schema: &[SqlScript],
+) -> Result<PristineConnection, InitializationError> {
+    let mut pristine = PristineConnection::new(settings.clone())?;
+    pristine.initialize_schema(schema)?;
+    Ok(pristine)
+}
+
+pub(crate) fn schema_hash(schemas: &[impl AsRef<str>]) -> String {
+    let mut hasher = Sha256::new();
+    for schema in schemas {
+        hasher.update(schema.as_ref());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Finds the objects that are created by the before and after migration scripts rather than the
+// schema itself
+fn hook_objects(
+    schema: &[impl SqlSource],
+    config: &Config,
+) -> Result<Vec<(ObjectType, String)>, InitializationError> {
+    let mut pristine = PristineConnection::new(Settings {
+        config: config.clone(),
+        options: Options::default(),
+    })?;
+    let object_names = |pristine: &mut PristineConnection| {
+        pristine
+            .parse_metadata()
+            .map(|metadata| {
+                metadata
+                    .all_objects()
+                    .into_iter()
+                    .map(|o| (o.object_type, o.name))
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|e| {
+                InitializationError::QueryFailure(
+                    "Failed to get metadata from hook scripts".to_owned(),
+                    e,
+                )
+            })
+    };
+
+    pristine.initialize_schema(&config.before_migration)?;
+    let mut objects = object_names(&mut pristine)?;
+    pristine.initialize_schema(schema)?;
+    let schema_objects = object_names(&mut pristine)?;
+    pristine.initialize_schema(&config.after_migration)?;
+    objects.extend(
+        object_names(&mut pristine)?
+            .into_iter()
+            .filter(|o| !schema_objects.contains(o)),
+    );
+    Ok(objects)
+}
+
 fn sorted_merge(a: &[String], b: &[String]) -> Vec<String> {
     let mut merged: Vec<_> = a.iter().chain(b.iter()).map(|m| m.to_owned()).collect();
     merged.sort();
@@ -647,6 +2346,11 @@ fn sorted_merge(a: &[String], b: &[String]) -> Vec<String> {
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum ObjectType {
     Table,
     Index,
@@ -654,12 +2358,315 @@ pub enum ObjectType {
     Trigger,
 }
 
+fn sort_by_dependencies(tables: &BTreeMap<String, String>) -> Vec<(&String, &String)> {
+    let mut dependencies: BTreeMap<&String, Vec<&String>> = tables
+        .iter()
+        .map(|(name, sql)| {
+            // Foreign keys can only reference tables in the same schema
+            let schema = split_schema(name).0;
+            let references = REFERENCES_RE
+                .captures_iter(sql)
+                .filter_map(|c| {
+                    let referenced = &c[2];
+                    tables.keys().find(|key| {
+                        let (key_schema, key_name) = split_schema(key);
+                        key_schema.eq_ignore_ascii_case(schema)
+                            && key_name.eq_ignore_ascii_case(referenced)
+                    })
+                })
+                .filter(|reference| *reference != name)
+                .collect();
+            (name, references)
+        })
+        .collect();
+
+    let mut sorted = Vec::with_capacity(tables.len());
+    while !dependencies.is_empty() {
+        let ready: Vec<&String> = dependencies
+            .iter()
+            .filter(|(_, references)| references.is_empty())
+            .map(|(name, _)| *name)
+            .collect();
+        if ready.is_empty() {
+            let cyclic = dependencies
+                .keys()
+                .map(|k| k.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!("Tables have cyclic foreign key references, using default ordering: {cyclic}");
+            return tables.iter().collect();
+        }
+        for name in ready {
+            dependencies.remove(name);
+            for references in dependencies.values_mut() {
+                references.retain(|r| *r != name);
+            }
+            sorted.push((name, &tables[name]));
+        }
+    }
+    sorted
+}
+
+fn changed_object_count(
+    changed: impl Fn(&str, &str) -> bool,
+    target_metadata: &BTreeMap<String, String>,
+    pristine_metadata: &BTreeMap<String, String>,
+    include_removed: bool,
+) -> usize {
+    let removed = target_metadata
+        .keys()
+        .filter(|k| include_removed && !pristine_metadata.contains_key(*k))
+        .count();
+    let created_or_updated = pristine_metadata
+        .iter()
+        .filter(|(name, sql)| match target_metadata.get(*name) {
+            Some(existing) => changed(existing, sql),
+            None => true,
+        })
+        .count();
+    removed + created_or_updated
+}
+
+// Virtual tables can't be copied into a temp table so they have to be dropped and recreated
+fn is_virtual_table_change(existing_sql: &str, sql: &str) -> bool {
+    VIRTUAL_TABLE_RE.is_match(existing_sql) || VIRTUAL_TABLE_RE.is_match(sql)
+}
+
+// Returns the column definitions to add if the only change to a table is new columns appended to
+// the end that ALTER TABLE ... ADD COLUMN supports
+fn added_columns(
+    existing_sql: &str,
+    pristine_sql: &str,
+    cols: &[String],
+    pristine_cols: &[String],
+) -> Option<Vec<String>> {
+    if pristine_cols.len() <= cols.len() || pristine_cols[..cols.len()] != *cols {
+        return None;
+    }
+    let definitions = column_definitions(pristine_sql)?;
+    // New columns are inserted after the last existing column definition
+    let mut insert_at = column_definitions(existing_sql)?.last()?.2;
+    let mut expected_sql = existing_sql.to_owned();
+    let mut new_columns = vec![];
+    for col in &pristine_cols[cols.len()..] {
+        let (_, definition, _) = definitions
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(col))?;
+        let has_default = DEFAULT_RE
+            .captures(definition)
+            .is_some_and(|c| !c[1].eq_ignore_ascii_case("NULL"));
+        if UNSUPPORTED_ADD_COLUMN_RE.is_match(definition)
+            || (NOT_NULL_RE.is_match(definition) && !has_default)
+        {
+            return None;
+        }
+        let column = format!(", {definition}");
+        expected_sql.insert_str(insert_at, &column);
+        insert_at += column.len();
+        new_columns.push(definition.to_string());
+    }
+    // Anything else that changed still requires a rebuild
+    (normalize_sql(&expected_sql) == normalize_sql(pristine_sql)).then_some(new_columns)
+}
+
+// Splits the column definitions out of a CREATE TABLE statement, returning the name, definition,
+// and end offset of each column. Table constraints are skipped.
+fn column_definitions(sql: &str) -> Option<Vec<(String, &str, usize)>> {
+    if sql.contains("--") || sql.contains("/*") || VIRTUAL_TABLE_RE.is_match(sql) {
+        return None;
+    }
+    let start = sql.find('(')? + 1;
+    let mut definitions = vec![];
+    let mut segment_start = start;
+    let mut depth = 1;
+    let mut quote = None;
+    for (i, c) in sql[start..].char_indices() {
+        let i = start + i;
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')' | ',') if depth == 1 => {
+                let definition = sql[segment_start..i].trim();
+                if !TABLE_CONSTRAINT_RE.is_match(definition) {
+                    let name = definition.split_whitespace().next()?;
+                    definitions.push((
+                        name.trim_matches(|c| matches!(c, '"' | '`' | '[' | ']'))
+                            .to_owned(),
+                        definition,
+                        segment_start + sql[segment_start..i].trim_end().len(),
+                    ));
+                }
+                if c == ')' {
+                    return Some(definitions);
+                }
+                segment_start = i + 1;
+            }
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Rewrites a CREATE TABLE statement with its columns in the same order as the reference
+// statement. Returns None if the tables don't have the same columns.
+fn reorder_columns(sql: &str, reference_sql: &str) -> Option<String> {
+    let columns = column_definitions(sql)?;
+    let reference_columns = column_definitions(reference_sql)?;
+    if columns.len() != reference_columns.len() {
+        return None;
+    }
+    let mut reordered = String::with_capacity(sql.len());
+    let mut last = 0;
+    for ((_, definition, end), (reference_name, _, _)) in columns.iter().zip(&reference_columns) {
+        let (_, replacement, _) = columns
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(reference_name))?;
+        reordered += &sql[last..end - definition.len()];
+        reordered += replacement;
+        last = *end;
+    }
+    reordered += &sql[last..];
+    Some(reordered)
+}
+
+fn pluralize(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("{count} {word}")
+    } else {
+        format!("{count} {word}s")
+    }
+}
+
+fn timestamped_path(path: &Path) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{timestamp}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{timestamp}"),
+    };
+    path.with_file_name(file_name)
+}
+
+fn is_page_size(pragma: &str) -> bool {
+    pragma.trim().eq_ignore_ascii_case("page_size")
+}
+
+// Splits an object key into its schema and name. Keys in the main schema aren't qualified.
+fn split_schema(key: &str) -> (&str, &str) {
+    key.split_once('.').unwrap_or(("main", key))
+}
+
+pub(crate) fn qualify_sql(sql: &str, schema: &str) -> String {
+    match CREATE_OBJECT_RE.find(sql) {
+        Some(create) => format!("{}{schema}.{}", create.as_str(), &sql[create.end()..]),
+        None => sql.to_owned(),
+    }
+}
+
 fn normalize_sql(sql: &str) -> String {
-    let sql = COMMENTS_RE.replace_all(sql, "");
-    let sql = WHITESPACE_RE.replace_all(&sql, " ");
+    let mut normalized = String::with_capacity(sql.len());
+    let mut code = String::new();
+    let mut last = 0;
+    for token in LITERALS_AND_COMMENTS_RE.find_iter(sql) {
+        code += &sql[last..token.start()];
+        last = token.end();
+        if token.as_str().starts_with('\'') {
+            // String literals are kept verbatim since their whitespace is meaningful
+            normalized += &normalize_code(&code);
+            normalized += token.as_str();
+            code.clear();
+        } else {
+            code.push(' ');
+        }
+    }
+    code += &sql[last..];
+    normalized += &normalize_code(&code);
+    normalize_check_operators(normalized.trim())
+}
+
+// SQLite stores CHECK constraints exactly as written, so spacing around operators is only
+// normalized inside them. Everything else keeps its operator spacing.
+fn normalize_check_operators(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut check_depth = 0;
+    let mut last = 0;
+    for literal in LITERALS_AND_COMMENTS_RE.find_iter(sql) {
+        normalized += &normalize_check_code(&sql[last..literal.start()], &mut check_depth);
+        normalized += literal.as_str();
+        last = literal.end();
+    }
+    normalized += &normalize_check_code(&sql[last..], &mut check_depth);
+    normalized
+}
+
+fn normalize_check_code(code: &str, check_depth: &mut usize) -> String {
+    let mut normalized = String::with_capacity(code.len());
+    let mut rest = code;
+    while !rest.is_empty() {
+        if *check_depth == 0 {
+            let Some(check) = CHECK_RE.find(rest) else {
+                normalized += rest;
+                break;
+            };
+            normalized += &rest[..check.end()];
+            rest = &rest[check.end()..];
+            *check_depth = 1;
+        } else {
+            let end = rest
+                .char_indices()
+                .find_map(|(i, c)| {
+                    match c {
+                        '(' => *check_depth += 1,
+                        ')' => *check_depth -= 1,
+                        _ => {}
+                    }
+                    (*check_depth == 0).then_some(i)
+                })
+                .unwrap_or(rest.len());
+            normalized += &OPERATOR_WHITESPACE_RE.replace_all(&rest[..end], r"$1");
+            rest = &rest[end..];
+        }
+    }
+    normalized
+}
+
+fn normalize_code(sql: &str) -> String {
+    let sql = WHITESPACE_RE.replace_all(sql, " ");
     let sql = EXTRA_WHITESPACE_RE.replace_all(&sql, r"$1");
-    let sql = QUOTES_RE.replace_all(&sql, r"$1");
-    sql.trim().to_owned()
+    // Collation names and the CHECK keyword are case-insensitive
+    let sql = COLLATE_RE.replace_all(&sql, |captures: &regex::Captures| {
+        format!("COLLATE {}", captures[1].to_uppercase())
+    });
+    let sql = CHECK_RE.replace_all(&sql, "CHECK(");
+    QUOTES_RE
+        .replace_all(&sql, |captures: &regex::Captures| {
+            let identifier = captures
+                .iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .expect("Identifier should be captured")
+                .as_str();
+            // Keywords and names starting with a digit still need quotes, so they're
+            // canonicalized to double quotes instead
+            if identifier.starts_with(|c: char| c.is_ascii_digit())
+                || SQLITE_KEYWORDS
+                    .split_whitespace()
+                    .any(|keyword| keyword.eq_ignore_ascii_case(identifier))
+            {
+                format!("\"{identifier}\"")
+            } else {
+                identifier.to_owned()
+            }
+        })
+        .into_owned()
 }
 #[cfg(test)]
 #[path = "./lib_test.rs"]
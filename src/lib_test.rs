@@ -1,6 +1,21 @@
-use crate::{normalize_sql, MigrationError, Migrator, Options};
+use crate::{
+    added_columns,
+    error::{ForeignKeyViolation, InitializationError},
+    normalize_sql, sort_by_dependencies, sql_diff_with_options, supports_defer_foreign_keys,
+    CancellationToken, ColumnDef, Config, DiffAlgorithm, DiffOptions, ExtraPolicy, IgnoreRules,
+    MigrationError, MigrationReport, Migrator, ObjectType, Objects, Options, Phase,
+    TransactionBehaviorKind, VacuumMode, LARGE_COPY_ROWS,
+};
+use regex::Regex;
 use rstest::rstest;
 use rusqlite::{Connection, OpenFlags};
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::Level;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct SqliteMetadata {
@@ -189,6 +204,3008 @@ fn test_data_migration() {
     assert_eq!((1, 100), rows.get(1).unwrap().clone());
 }
 
+#[rstest]
+fn test_backup() {
+    let schemas = schemas();
+    let tempdir = tempfile::tempdir().unwrap();
+    let connection = get_connection("backup");
+    let connection2 = get_connection("backup");
+    connection.execute_batch(schemas[1]).unwrap();
+
+    let migrator = Migrator::new(
+        &[schemas[2]],
+        connection,
+        crate::Config::default(),
+        Options {
+            backup_path: Some(tempdir.path().join("backup.db")),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    assert_schema_equal(&connection2, schemas[2]);
+
+    let backups: Vec<_> = std::fs::read_dir(tempdir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(1, backups.len());
+    let backup_name = backups[0].file_name().unwrap().to_string_lossy();
+    assert!(backup_name.starts_with("backup-") && backup_name.ends_with(".db"));
+    assert_schema_equal(&Connection::open(&backups[0]).unwrap(), schemas[1]);
+}
+
+#[rstest]
+fn test_rebuild_with_generated_columns() {
+    let connection = get_connection("generated");
+    let connection2 = get_connection("generated");
+    connection
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, price INTEGER NOT NULL, doubled INTEGER GENERATED ALWAYS AS (price * 2) STORED);
+            INSERT INTO Item(id, price) VALUES (1, 10);",
+        )
+        .unwrap();
+
+    let new_schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, price INTEGER NOT NULL, doubled INTEGER GENERATED ALWAYS AS (price * 2) STORED, tripled INTEGER AS (price * 3), name TEXT);";
+    let migrator = Migrator::new(
+        &[new_schema],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    assert_schema_equal(&connection2, new_schema);
+
+    let (doubled, tripled): (i64, i64) = connection2
+        .query_row(
+            "SELECT doubled, tripled FROM Item WHERE id = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((20, 30), (doubled, tripled));
+}
+
+#[rstest]
+#[case(ExtraPolicy::Drop, Some(vec!["Item"]))]
+#[case(ExtraPolicy::Keep, Some(vec!["Extra", "ExtraView", "Extra_idx", "Item"]))]
+#[case(ExtraPolicy::Error, None)]
+fn test_extra_objects(#[case] policy: ExtraPolicy, #[case] expected: Option<Vec<&str>>) {
+    let name = format!("extra_objects_{policy:?}");
+    let target = get_connection(&name);
+    let connection = get_connection(&name);
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY);
+            CREATE TABLE Extra(id INTEGER PRIMARY KEY, name TEXT);
+            CREATE INDEX Extra_idx ON Extra(name);
+            CREATE VIEW ExtraView AS SELECT name FROM Extra;",
+        )
+        .unwrap();
+    let migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY);"],
+        target,
+        crate::Config::default(),
+        Options {
+            allow_deletions: true,
+            on_extra_objects: policy,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let result = migrator.migrate();
+    match expected {
+        Some(expected) => {
+            result.unwrap();
+            let mut names: Vec<_> = dump_sqlite_master(&connection)
+                .into_iter()
+                .map(|m| m.name)
+                .collect();
+            names.sort();
+            assert_eq!(expected, names);
+        }
+        None => assert!(
+            matches!(result, Err(MigrationError::ExtraObjects(_))),
+            "{result:?}"
+        ),
+    }
+}
+
+#[rstest]
+#[case("CREATE INDEX Item_idx ON Item(name);")]
+#[case("CREATE VIEW ItemView AS SELECT name FROM Item;")]
+#[case("CREATE TRIGGER Item_trigger AFTER INSERT ON Item BEGIN SELECT 1; END;")]
+fn test_drop_extra_object_requires_allow_deletions(
+    #[case] extra_object: &str,
+    #[values(true, false)] allow_deletions: bool,
+) {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);";
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(&format!("{schema}{extra_object}"))
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options {
+            allow_deletions,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let result = migrator.migrate_to_sql();
+    if allow_deletions {
+        assert!(result.unwrap().contains("DROP"));
+    } else {
+        assert!(
+            matches!(result, Err(MigrationError::DataLoss(_))),
+            "{result:?}"
+        );
+    }
+}
+
+#[rstest]
+fn test_pre_integrity_check() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Item_name ON Item(name);";
+    let options = Options {
+        pre_integrity_check: true,
+        ..Default::default()
+    };
+    let target = Connection::open(&target_path).unwrap();
+    target
+        .execute_batch(&format!(
+            "{schema} INSERT INTO Item(name) VALUES ('a'), ('b'), ('c');"
+        ))
+        .unwrap();
+    Migrator::new(
+        &[schema],
+        Connection::open(&target_path).unwrap(),
+        crate::Config::default(),
+        options.clone(),
+    )
+    .unwrap()
+    .migrate()
+    .unwrap();
+
+    // Change the indexed column so the existing index entries no longer match
+    target
+        .execute_batch(
+            "PRAGMA writable_schema = ON;
+            UPDATE sqlite_master SET sql = 'CREATE INDEX Item_name ON Item(id)'
+            WHERE name = 'Item_name';",
+        )
+        .unwrap();
+    drop(target);
+    let result = Migrator::new(
+        &[schema],
+        Connection::open(&target_path).unwrap(),
+        crate::Config::default(),
+        options,
+    )
+    .unwrap()
+    .migrate();
+    assert!(
+        matches!(&result, Err(MigrationError::IntegrityCheckFailed(errors)) if !errors.is_empty()),
+        "{result:?}"
+    );
+}
+
+#[rstest]
+fn test_empty_target() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("new.db");
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Item_name ON Item(name);";
+    let migrator = Migrator::new(
+        &[schema],
+        Connection::open(&target_path).unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    assert_schema_equal(&Connection::open(&target_path).unwrap(), schema);
+}
+
+const UNMODIFIED_SCHEMA: &str = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);";
+const MODIFIED_SCHEMA: &str =
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT); CREATE INDEX Item_name ON Item(name);";
+
+#[rstest]
+#[case(VacuumMode::IfModified, UNMODIFIED_SCHEMA, false)]
+#[case(VacuumMode::IfModified, MODIFIED_SCHEMA, true)]
+#[case(VacuumMode::Never, MODIFIED_SCHEMA, false)]
+#[case(VacuumMode::Always, UNMODIFIED_SCHEMA, true)]
+fn test_vacuum_mode(#[case] vacuum: VacuumMode, #[case] schema: &str, #[case] vacuumed: bool) {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let target = Connection::open(&target_path).unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+            WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+            INSERT INTO Item(name) SELECT randomblob(1000) FROM n;
+            DELETE FROM Item;",
+        )
+        .unwrap();
+    let free_pages_before: i64 = target
+        .pragma_query_value(None, "freelist_count", |row| row.get(0))
+        .unwrap();
+    assert!(free_pages_before > 0);
+    let migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options {
+            vacuum,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+
+    let free_pages: i64 = Connection::open(&target_path)
+        .unwrap()
+        .pragma_query_value(None, "freelist_count", |row| row.get(0))
+        .unwrap();
+    assert_eq!(vacuumed, free_pages == 0);
+}
+
+#[rstest]
+fn test_checkpoint(#[values("WAL", "DELETE")] journal_mode: &str) {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let target = Connection::open(&target_path).unwrap();
+    target
+        .pragma_update(None, "journal_mode", journal_mode)
+        .unwrap();
+    // Keeping this connection open prevents the WAL from being checkpointed when the migrator's
+    // connection closes
+    target
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    let migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        Connection::open(&target_path).unwrap(),
+        crate::Config::default(),
+        Options {
+            checkpoint: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+
+    let wal_path = tempdir.path().join("target.db-wal");
+    assert_eq!(0, std::fs::metadata(wal_path).map(|m| m.len()).unwrap_or(0));
+}
+
+#[rstest]
+#[case(Some("^reporting_"), None, vec!["reporting_a", "reporting_b"])]
+#[case(None, Some("_b$"), vec!["other", "reporting_a"])]
+#[case(Some("^reporting_"), Some("_b$"), vec!["reporting_a"])]
+fn test_only_objects(
+    #[case] only: Option<&str>,
+    #[case] ignore: Option<&str>,
+    #[case] expected: Vec<&str>,
+) {
+    let schema = "CREATE TABLE reporting_a(id INTEGER PRIMARY KEY);
+        CREATE TABLE reporting_b(id INTEGER PRIMARY KEY);
+        CREATE TABLE other(id INTEGER PRIMARY KEY);";
+    let target = Connection::open_in_memory().unwrap();
+    target.execute_batch(schema).unwrap();
+    let config = crate::Config {
+        only: only.map(|only| Regex::new(only).unwrap()),
+        ignore: ignore
+            .map(|ignore| IgnoreRules::from(Regex::new(ignore).unwrap()))
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let mut migrator = Migrator::new(&[schema], target, config, Options::default()).unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        expected,
+        metadata.source.tables().keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        expected,
+        metadata.target.tables().keys().collect::<Vec<_>>()
+    );
+}
+
+#[rstest]
+fn test_to_create_script() {
+    let schema = "PRAGMA user_version = 2;
+        CREATE TABLE Child(id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES Parent(id));
+        CREATE TABLE Parent(id INTEGER PRIMARY KEY);
+        CREATE TRIGGER Parent_insert AFTER INSERT ON Parent BEGIN SELECT 1; END;
+        CREATE VIEW ChildView AS SELECT * FROM Child;
+        CREATE INDEX Child_parent ON Child(parent_id);";
+    let mut migrator = Migrator::new(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.parse_metadata().unwrap().source.to_create_script();
+    let statements: Vec<_> = script.lines().map(|l| &l[..l.len().min(20)]).collect();
+    assert_eq!(
+        vec![
+            "CREATE TABLE Parent(",
+            "CREATE TABLE Child(i",
+            "CREATE INDEX Child_p",
+            "CREATE VIEW ChildVie",
+            "CREATE TRIGGER Paren",
+            "PRAGMA user_version ",
+        ],
+        statements
+    );
+
+    let connection = Connection::open_in_memory().unwrap();
+    connection.execute_batch(&script).unwrap();
+    assert_schema_equal(&connection, schema);
+}
+
+#[rstest]
+fn test_from_two_connections() {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Item_name ON Item(name);
+        CREATE VIEW ItemNames AS SELECT name FROM Item;
+        PRAGMA user_version = 3;";
+    let source = Connection::open_in_memory().unwrap();
+    source.execute_batch(schema).unwrap();
+    let target = get_connection("two_connections");
+    let target2 = get_connection("two_connections");
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY); CREATE TABLE Obsolete(id INTEGER);",
+        )
+        .unwrap();
+
+    let mut migrator = Migrator::from_two_connections(
+        source,
+        target,
+        crate::Config::default(),
+        Options {
+            allow_deletions: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(3, metadata.source.user_version());
+    assert_eq!(
+        vec!["Item"],
+        metadata.source.tables().keys().collect::<Vec<_>>()
+    );
+    assert!(!metadata
+        .source
+        .structurally_equal(&metadata.target, &Config::default()));
+
+    migrator.migrate().unwrap();
+    assert_schema_equal(&target2, schema);
+}
+
+#[rstest]
+#[case::attachments(
+    crate::Config {
+        attachments: vec![("aux".to_owned(), ":memory:".into())],
+        ..Default::default()
+    },
+    "Attached databases"
+)]
+#[case::before_migration(
+    crate::Config {
+        before_migration: vec!["CREATE TABLE IF NOT EXISTS Hook(id INTEGER);".into()],
+        ..Default::default()
+    },
+    "Migration hooks"
+)]
+#[case::after_migration(
+    crate::Config {
+        after_migration: vec!["CREATE TABLE IF NOT EXISTS Hook(id INTEGER);".into()],
+        ..Default::default()
+    },
+    "Migration hooks"
+)]
+fn test_from_two_connections_unsupported_config(
+    #[case] config: crate::Config,
+    #[case] expected: &str,
+) {
+    let source = Connection::open_in_memory().unwrap();
+    source
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    let result = Migrator::from_two_connections(
+        source,
+        Connection::open_in_memory().unwrap(),
+        config,
+        Options::default(),
+    );
+    match result {
+        Err(InitializationError::UnsupportedSourceConfig(config)) => assert_eq!(expected, config),
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[rstest]
+fn test_for_target() {
+    let schema = ["PRAGMA foreign_keys = ON;
+        CREATE TABLE Node(id INTEGER PRIMARY KEY);
+        CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER REFERENCES Node(id));"];
+    let config = crate::Config {
+        before_migration: vec!["CREATE TABLE IF NOT EXISTS Hook(id INTEGER PRIMARY KEY);".into()],
+        ..Default::default()
+    };
+    let options = Options {
+        allow_deletions: true,
+        ..Default::default()
+    };
+    let mut migrator = Migrator::new(
+        &schema,
+        Connection::open_in_memory().unwrap(),
+        config,
+        options.clone(),
+    )
+    .unwrap();
+
+    let target = get_connection("for_target1");
+    let target2 = get_connection("for_target1");
+    migrator
+        .for_target(target, options.clone())
+        .unwrap()
+        .migrate()
+        .unwrap();
+    // Objects created by hooks are part of the source so they aren't dropped as extra objects
+    assert_schema_equal(
+        &target2,
+        &format!("CREATE TABLE Hook(id INTEGER PRIMARY KEY); {}", schema[0]),
+    );
+
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Node(id INTEGER PRIMARY KEY);
+            CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER);
+            INSERT INTO Job(id, node_id) VALUES(3, 1);",
+        )
+        .unwrap();
+    // The source's foreign_keys setting carries over to the copy so violations are still checked
+    let result = migrator.for_target(target, options).unwrap().migrate();
+    assert!(
+        matches!(result, Err(MigrationError::ForeignKeyViolation(_))),
+        "{result:?}"
+    );
+}
+
+#[rstest]
+fn test_migrator_builder() {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY, legacy TEXT);")
+        .unwrap();
+    let mut migrator = Migrator::builder()
+        .schema(["CREATE TABLE Item(id INTEGER PRIMARY KEY);"])
+        .before_migration(["CREATE TABLE IF NOT EXISTS Hook(id INTEGER PRIMARY KEY);"])
+        .treat_hooks_as_schema(false)
+        .allow_deletions(true)
+        .script_comments(true)
+        .build(target)
+        .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(script.contains("-- Rebuild table Item"));
+    assert!(!script.contains("DROP TABLE Hook"));
+}
+
+#[rstest]
+fn test_only_named_objects() {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Node(id INTEGER PRIMARY KEY);
+            CREATE TABLE Other(id INTEGER PRIMARY KEY);",
+        )
+        .unwrap();
+    let config = crate::Config {
+        only_objects: vec!["node".to_owned()],
+        ..Default::default()
+    };
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Node(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Node_name ON Node(name);
+        CREATE TRIGGER Node_insert AFTER INSERT ON Node BEGIN SELECT 1; END;
+        CREATE TABLE Other(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Other_name ON Other(name);"],
+        target,
+        config,
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        vec!["Node"],
+        metadata.source.tables().keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["Node_name"],
+        metadata.source.indexes().keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["Node_insert"],
+        metadata.source.triggers().keys().collect::<Vec<_>>()
+    );
+
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(script.contains("Node_name"));
+    assert!(!script.contains("Other"));
+}
+
+#[rstest]
+#[case(TransactionBehaviorKind::Deferred, true)]
+#[case(TransactionBehaviorKind::Immediate, true)]
+#[case(TransactionBehaviorKind::Exclusive, false)]
+fn test_transaction_behavior(#[case] behavior: TransactionBehaviorKind, #[case] readable: bool) {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let target = Connection::open(&target_path).unwrap();
+    target
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    target.busy_timeout(Duration::ZERO).unwrap();
+    let migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        Connection::open(&target_path).unwrap(),
+        crate::Config::default(),
+        Options {
+            transaction_behavior: behavior,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut read_results = vec![];
+    migrator
+        .migrate_with_progress(|progress| {
+            // Progress is only reported once the transaction has started
+            if progress.phase == Phase::Tables && progress.completed == 0 {
+                read_results.push(
+                    target
+                        .query_row("SELECT COUNT(*) FROM Item", [], |row| row.get::<_, i64>(0))
+                        .is_ok(),
+                );
+            }
+        })
+        .unwrap();
+    assert_eq!(vec![readable], read_results);
+}
+
+#[rstest]
+#[case(false, true)]
+#[case(true, false)]
+fn test_sql_normalizer(#[case] use_normalizer: bool, #[case] has_changes: bool) {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, quantity INTEGER);
+            CREATE INDEX Item_quantity ON Item(quantity);",
+        )
+        .unwrap();
+    let int_re = Regex::new(r"(?i)\bINTEGER\b").unwrap();
+    let config = crate::Config {
+        sql_normalizer: use_normalizer.then(|| {
+            std::sync::Arc::new(move |sql: &str| int_re.replace_all(sql, "INT").into_owned())
+                as crate::SqlNormalizer
+        }),
+        ..Default::default()
+    };
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, quantity INT);
+        CREATE INDEX Item_quantity ON Item(quantity);"],
+        target,
+        config.clone(),
+        Options::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        has_changes,
+        migrator.dry_run_report().unwrap().has_changes()
+    );
+
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        has_changes,
+        !metadata
+            .source
+            .structurally_equal(&metadata.target, &config)
+    );
+    assert_eq!(
+        has_changes,
+        crate::object_changes(&metadata, &config)
+            .iter()
+            .any(|c| c.change_kind != crate::ChangeKind::Unchanged)
+    );
+
+    let mut statements = vec![];
+    migrator
+        .migrate_with_callback(|event| statements.push(strip_ansi(&event.sql)))
+        .unwrap();
+    assert_eq!(
+        has_changes,
+        statements.iter().any(|s| s.contains("CREATE TABLE"))
+    );
+}
+
+#[rstest]
+fn test_target_pragmas() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let target = Connection::open(&target_path).unwrap();
+    target
+        .execute_batch("PRAGMA page_size = 4096; CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    // The journal mode can't be changed while other connections are open
+    drop(target);
+    let config = crate::Config {
+        target_pragmas: vec![
+            ("page_size".to_owned(), "8192".to_owned()),
+            ("journal_mode".to_owned(), "WAL".to_owned()),
+        ],
+        ..Default::default()
+    };
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        Connection::open(&target_path).unwrap(),
+        config,
+        Options {
+            vacuum: crate::VacuumMode::Never,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(
+        script.ends_with("PRAGMA page_size = 8192;\nVACUUM;\nPRAGMA journal_mode = WAL;"),
+        "{script}"
+    );
+    migrator.migrate().unwrap();
+
+    let target = Connection::open(&target_path).unwrap();
+    let page_size: i64 = target
+        .query_row("PRAGMA page_size", [], |row| row.get(0))
+        .unwrap();
+    let journal_mode: String = target
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!((8192, "wal"), (page_size, journal_mode.as_str()));
+}
+
+#[rstest]
+fn test_attached_database() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let attached_path = tempdir.path().join("attached.db");
+    let attached = Connection::open(&attached_path).unwrap();
+    attached
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT); INSERT INTO Item(id, name) VALUES (1, 'item');")
+        .unwrap();
+
+    let config = crate::Config {
+        attachments: vec![("aux".to_owned(), attached_path.clone())],
+        ..Default::default()
+    };
+    let migrator = Migrator::new(
+        &[r#"
+        CREATE TABLE Node(id INTEGER PRIMARY KEY);
+        CREATE TABLE aux.Item(id INTEGER PRIMARY KEY, name TEXT, active BOOLEAN NOT NULL DEFAULT(1));
+        CREATE INDEX aux.Item_name ON Item(name);
+        "#],
+        Connection::open(&target_path).unwrap(),
+        config,
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+
+    let attached_schema = dump_sqlite_master(&attached);
+    assert_eq!(
+        vec!["Item_name", "Item"],
+        attached_schema
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    let name: String = attached
+        .query_row(
+            "SELECT name FROM Item WHERE id = 1 AND active = 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!("item", name);
+    assert_eq!(
+        vec!["Node"],
+        dump_sqlite_master(&Connection::open(&target_path).unwrap())
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[rstest]
+fn test_rebuild_preserves_triggers() {
+    let before = r#"
+        CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE item_log(name TEXT NOT NULL);
+        CREATE TRIGGER item_insert AFTER INSERT ON item BEGIN
+            INSERT INTO item_log(name) VALUES(new.name);
+        END;
+        CREATE TRIGGER item_update AFTER UPDATE ON item BEGIN SELECT 1; END;
+        "#;
+    // item_log is rebuilt after item, so item_insert references a dropped table at that point
+    let after = r#"
+        CREATE TABLE item(id INTEGER PRIMARY KEY, price INTEGER, name TEXT NOT NULL);
+        CREATE TABLE item_log(name TEXT);
+        CREATE TRIGGER item_insert AFTER INSERT ON item BEGIN
+            INSERT INTO item_log(name) VALUES(new.name);
+        END;
+        CREATE TRIGGER item_update AFTER UPDATE ON item BEGIN SELECT 2; END;
+        "#;
+    let connection = get_connection("triggers");
+    let connection2 = get_connection("triggers");
+    connection.execute_batch(before).unwrap();
+
+    let mut statements = vec![];
+    let migrator = Migrator::new(
+        &[after],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator
+        .migrate_with_callback(|statement| statements.push(strip_ansi(&statement.sql)))
+        .unwrap();
+    assert_schema_equal(&connection2, after);
+
+    let position = |sql: &str| statements.iter().position(|s| s.contains(sql)).unwrap();
+    let count = |sql: &str| statements.iter().filter(|s| s.contains(sql)).count();
+    // Unchanged triggers are restored as soon as the table is renamed
+    let rename_index = position("item_migration_new RENAME TO item");
+    assert!(statements[rename_index + 1].contains("PRAGMA legacy_alter_table = OFF"));
+    assert!(statements[rename_index + 2].contains("CREATE TRIGGER item_insert"));
+    assert_eq!(1, count("CREATE TRIGGER item_insert"));
+    // Changed triggers are only created when migrating triggers
+    assert!(position("CREATE TRIGGER item_update") > position("RENAME TO item_log"));
+    assert_eq!(1, count("CREATE TRIGGER item_update"));
+    assert_eq!(0, count("DROP TRIGGER"));
+
+    connection2
+        .execute("INSERT INTO item(name, price) VALUES('book', 10)", [])
+        .unwrap();
+    let logged: String = connection2
+        .query_row("SELECT name FROM item_log", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!("book", logged);
+
+    // Scripts from dry runs don't try to drop the triggers that were dropped with the table
+    let connection = get_connection("triggers_script");
+    let connection2 = get_connection("triggers_script");
+    connection.execute_batch(before).unwrap();
+    let mut migrator = Migrator::new(
+        &[after],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    connection2.execute_batch(&script).unwrap();
+    assert_schema_equal(&connection2, after);
+}
+
+#[rstest]
+#[case(false, Some(2))]
+#[case(true, None)]
+fn test_script_rows_affected(#[case] dry_run: bool, #[case] expected_rows: Option<usize>) {
+    let connection = Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            INSERT INTO item(name) VALUES('book'), ('pen');",
+        )
+        .unwrap();
+
+    let mut events = vec![];
+    let migrator = Migrator::new(
+        &["CREATE TABLE item(id INTEGER PRIMARY KEY, price INTEGER, name TEXT NOT NULL);"],
+        connection,
+        crate::Config::default(),
+        Options {
+            dry_run,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator
+        .migrate_with_callback(|event| events.push(event))
+        .unwrap();
+
+    let copy = events
+        .iter()
+        .find(|e| strip_ansi(&e.sql).trim().starts_with("INSERT INTO"))
+        .unwrap();
+    assert_eq!(expected_rows, copy.rows_affected);
+    let begin = events
+        .iter()
+        .find(|e| strip_ansi(&e.sql).trim().starts_with("BEGIN"))
+        .unwrap();
+    assert_eq!(None, begin.rows_affected);
+}
+
+#[rstest]
+#[case(
+    "CREATE TABLE item(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL);",
+    4
+)]
+#[case("CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);", 2)]
+fn test_rebuild_preserves_sequence(#[case] before: &str, #[case] expected_id: i64) {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let connection = Connection::open(&target_path).unwrap();
+    connection.execute_batch(before).unwrap();
+    connection
+        .execute_batch(
+            "INSERT INTO item(name) VALUES ('a'), ('b'), ('c');
+            DELETE FROM item WHERE id > 1;",
+        )
+        .unwrap();
+    let migrator = Migrator::new(
+        &["CREATE TABLE item(id INTEGER PRIMARY KEY AUTOINCREMENT, price INTEGER, name TEXT NOT NULL);"],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+
+    let id = Connection::open(&target_path)
+        .unwrap()
+        .query_row(
+            "INSERT INTO item(name) VALUES ('d') RETURNING id",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap();
+    assert_eq!(expected_id, id);
+}
+
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[rstest]
+#[case(LARGE_COPY_ROWS, true)]
+#[case(LARGE_COPY_ROWS - 1, false)]
+#[case(10, false)]
+fn test_large_copy_message(#[case] rows: i64, #[case] logged: bool) {
+    let connection = Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            WITH RECURSIVE seq(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM seq WHERE n < {rows})
+            INSERT INTO item(name) SELECT 'item' || n FROM seq;"
+        ))
+        .unwrap();
+    let migrator = Migrator::new(
+        &["CREATE TABLE item(id INTEGER PRIMARY KEY, price INTEGER, name TEXT NOT NULL);"],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+
+    let buffer = LogBuffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || migrator.migrate().unwrap());
+
+    let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(logged, logs.contains("this may take a while"), "{logs}");
+    assert_eq!(
+        logged,
+        logs.contains(&format!("Copied {rows} rows into")),
+        "{logs}"
+    );
+}
+
+#[rstest]
+#[case(
+    "CREATE TABLE item(name TEXT NOT NULL, id INTEGER PRIMARY KEY);",
+    true,
+    false
+)]
+#[case(
+    "CREATE TABLE item(name TEXT NOT NULL, id INTEGER PRIMARY KEY);",
+    false,
+    true
+)]
+#[case("CREATE TABLE item(name TEXT, id INTEGER PRIMARY KEY);", true, true)]
+#[case(
+    "CREATE TABLE item(name TEXT NOT NULL, id INTEGER PRIMARY KEY, UNIQUE(name));",
+    true,
+    true
+)]
+fn test_ignore_column_order(
+    #[case] after: &str,
+    #[case] ignore_column_order: bool,
+    #[case] rebuilt: bool,
+) {
+    let connection = Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch("CREATE TABLE item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);")
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[after],
+        connection,
+        crate::Config::default(),
+        Options {
+            ignore_column_order,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        rebuilt as usize,
+        migrator.dry_run_report().unwrap().tables_modified
+    );
+    assert_eq!(
+        rebuilt,
+        migrator
+            .migrate_to_sql()
+            .unwrap()
+            .contains("_migration_new")
+    );
+}
+
+#[rstest]
+fn test_user_version() {
+    let schemas = schemas();
+    let connection = get_connection("user_version");
+    let connection2 = get_connection("user_version");
+    connection.execute_batch(schemas[3]).unwrap();
+
+    let migrator = Migrator::new(
+        &[schemas[4]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    let user_version: i64 = connection2
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(6, user_version);
+
+    let migrator = Migrator::new(
+        &[schemas[3]],
+        get_connection("user_version"),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    let user_version: i64 = connection2
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(0, user_version);
+}
+
+#[cfg(feature = "tokio")]
+#[rstest]
+fn test_migrate_async() {
+    let schemas = schemas();
+    let connection = get_connection("migrate_async");
+    let connection2 = get_connection("migrate_async");
+    connection.execute_batch(schemas[0]).unwrap();
+
+    let migrator = Migrator::new(
+        &[schemas[1]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(migrator.migrate_async())
+        .unwrap();
+    assert_schema_equal(&connection2, schemas[1]);
+}
+
+#[rstest]
+fn test_migrate_with_progress() {
+    let schemas = schemas();
+    let connection = get_connection("progress");
+    let connection2 = get_connection("progress");
+    connection.execute_batch(schemas[0]).unwrap();
+
+    let migrator = Migrator::new(
+        &[schemas[1]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let mut progress = vec![];
+    migrator
+        .migrate_with_progress(|p| progress.push(p))
+        .unwrap();
+    assert_schema_equal(&connection2, schemas[1]);
+
+    let phases: Vec<_> = progress
+        .iter()
+        .filter(|p| p.current_object.is_none())
+        .map(|p| p.phase)
+        .collect();
+    assert_eq!(
+        vec![Phase::Tables, Phase::Indexes, Phase::Views, Phase::Triggers],
+        phases
+    );
+    for phase in phases {
+        let updates: Vec<_> = progress.iter().filter(|p| p.phase == phase).collect();
+        let last = updates.last().unwrap();
+        assert_eq!(last.total, last.completed);
+        assert_eq!(last.total, updates.len() - 1);
+    }
+    assert!(progress
+        .iter()
+        .any(|p| p.phase == Phase::Tables && p.total > 0));
+}
+
+#[rstest]
+fn test_fingerprint() {
+    let schemas = schemas();
+    let connection = get_connection("fingerprint");
+    connection.execute_batch(schemas[1]).unwrap();
+
+    let reformatted = schemas[1].replace('(', " ( ").replace(',', " ,\n");
+    let mut migrator = Migrator::new(
+        &[reformatted],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(64, metadata.source_fingerprint().len());
+    assert_eq!(metadata.source_fingerprint(), metadata.target_fingerprint());
+
+    let mut migrator = Migrator::new(
+        &[schemas[2]],
+        get_connection("fingerprint"),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_ne!(metadata.source_fingerprint(), metadata.target_fingerprint());
+}
+
+#[rstest]
+fn test_ignore_rules() {
+    let connection = get_connection("ignore_rules");
+    let connection2 = get_connection("ignore_rules");
+    let target_schema = r#"
+        CREATE TABLE tmp_table(id INTEGER PRIMARY KEY);
+        CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE VIEW ItemView AS SELECT * FROM Item;
+        CREATE TRIGGER tmp_trigger AFTER INSERT ON Item BEGIN SELECT 1; END;
+        CREATE TRIGGER Item_trigger AFTER INSERT ON Item BEGIN SELECT 1; END;
+        "#;
+    connection.execute_batch(target_schema).unwrap();
+
+    let config = crate::Config {
+        ignore: IgnoreRules {
+            type_patterns: BTreeMap::from([(
+                ObjectType::Trigger,
+                vec![Regex::new("^tmp_").unwrap()],
+            )]),
+            ignored_types: vec![ObjectType::View],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        connection,
+        config,
+        Options {
+            allow_deletions: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    assert_schema_equal(
+        &connection2,
+        r#"
+        CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE VIEW ItemView AS SELECT * FROM Item;
+        CREATE TRIGGER tmp_trigger AFTER INSERT ON Item BEGIN SELECT 1; END;
+        "#,
+    );
+}
+
+#[rstest]
+fn test_migrate_to_sql() {
+    let schemas = schemas();
+    let connection = get_connection("migrate_to_sql");
+    let connection2 = get_connection("migrate_to_sql");
+    connection.execute_batch(schemas[0]).unwrap();
+
+    let mut migrator = Migrator::new(
+        &[schemas[1]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(script.starts_with("PRAGMA foreign_keys = OFF;\nBEGIN;\n"));
+    // The source schema disables foreign keys, so they aren't deferred or checked
+    assert!(!script.contains("defer_foreign_keys"));
+    assert!(script.ends_with("\nCOMMIT;\nPRAGMA foreign_keys = ON;"));
+    assert!(!script.contains('\u{1b}'));
+    assert_schema_equal(&connection2, schemas[0]);
+
+    connection2.execute_batch(&script).unwrap();
+    assert_schema_equal(&connection2, schemas[1]);
+}
+
+#[rstest]
+#[case(true)]
+#[case(false)]
+fn test_script_comments(#[case] script_comments: bool) {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, legacy TEXT);
+            CREATE TABLE Obsolete(id INTEGER PRIMARY KEY);
+            CREATE TABLE Tag(id INTEGER PRIMARY KEY);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, price INTEGER);
+        CREATE TABLE Tag(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Item_name ON Item(name);",
+        ],
+        target,
+        crate::Config::default(),
+        Options {
+            allow_deletions: true,
+            script_comments,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    let comments: Vec<_> = script.lines().filter(|l| l.starts_with("--")).collect();
+    if script_comments {
+        assert_eq!(
+            vec![
+                "-- Drop table Obsolete",
+                "-- Rebuild table Item (2 columns added, 1 removed)",
+                "-- Add 1 column to table Tag",
+                "-- Create index Item_name",
+            ],
+            comments
+        );
+        assert!(script.contains("-- Drop table Obsolete\nDROP TABLE Obsolete;"));
+    } else {
+        assert!(comments.is_empty(), "{script}");
+    }
+}
+
+#[rstest]
+fn test_cached_source() {
+    let schema = "CREATE TABLE item(id integer primary key, name text);";
+    let mut migrator = Migrator::new(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let cached = migrator.parse_metadata().unwrap().source;
+    assert!(cached.schema_hash().is_some());
+
+    let connection = get_connection("cached_source");
+    let connection2 = get_connection("cached_source");
+    let mut migrator = Migrator::new_with_cached_source(
+        &[schema],
+        cached.clone(),
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(migrator.pristine.is_none());
+    assert_eq!(
+        cached.fingerprint(),
+        migrator.parse_metadata().unwrap().source_fingerprint()
+    );
+    assert!(migrator.pristine.is_none());
+    migrator.migrate().unwrap();
+    assert_schema_equal(&connection2, schema);
+
+    let new_schema = "CREATE TABLE item(id integer primary key, name text, price real);";
+    let mut migrator = Migrator::new_with_cached_source(
+        &[new_schema],
+        cached,
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(migrator.pristine.is_some());
+    assert!(migrator
+        .parse_metadata()
+        .unwrap()
+        .source
+        .tables()
+        .get("item")
+        .unwrap()
+        .contains("price"));
+}
+
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+#[rstest]
+fn test_serialize_metadata() {
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE item(id integer primary key, name text); CREATE INDEX item_name ON item(name);"],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let source = migrator.parse_metadata().unwrap().source;
+    let json = serde_json::to_string(&source).unwrap();
+    let deserialized: crate::Metadata = serde_json::from_str(&json).unwrap();
+    assert_eq!(source.fingerprint(), deserialized.fingerprint());
+    assert_eq!(source.schema_hash(), deserialized.schema_hash());
+    assert_eq!(
+        source.table_columns("item"),
+        deserialized.table_columns("item")
+    );
+}
+
+#[rstest]
+fn test_rebuilt_table_indexes() {
+    let old_schema = "CREATE TABLE item(id integer primary key, name text, price text);
+        CREATE INDEX item_name ON item(name);
+        CREATE INDEX item_price ON item(price);";
+    let new_schema = "CREATE TABLE item(id integer primary key, name text, price real);
+        CREATE INDEX item_name ON item(name);
+        CREATE INDEX item_price ON item(price, name);";
+
+    let connection = get_connection("rebuilt_indexes");
+    let connection2 = get_connection("rebuilt_indexes");
+    connection.execute_batch(old_schema).unwrap();
+    let mut migrator = Migrator::new(
+        &[new_schema],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    connection2.execute_batch(&script).unwrap();
+    assert_schema_equal(&connection2, new_schema);
+
+    let connection = get_connection("rebuilt_indexes2");
+    let connection2 = get_connection("rebuilt_indexes2");
+    connection.execute_batch(old_schema).unwrap();
+    let migrator = Migrator::new(
+        &[new_schema],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    assert_schema_equal(&connection2, new_schema);
+}
+
+#[rstest]
+fn test_rollback_script(#[values(0, 1, 2, 3, 4)] from: usize, #[values(0, 1, 2, 3, 4)] to: usize) {
+    let schemas = schemas();
+    let connection = get_connection(&format!("rollback{from}{to}"));
+    connection.execute_batch(schemas[from]).unwrap();
+
+    let mut migrator = Migrator::new(
+        &[schemas[to]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.rollback_script().unwrap();
+
+    let migrated = Connection::open_in_memory().unwrap();
+    migrated.execute_batch(schemas[to]).unwrap();
+    migrated.execute_batch(&script).unwrap();
+    assert_schema_equal(&migrated, schemas[from]);
+}
+
+#[cfg(feature = "diff")]
+#[rstest]
+fn test_object_changes() {
+    use crate::{object_changes, ChangeKind};
+
+    let schemas = schemas();
+    let connection = get_connection("changes");
+    connection.execute_batch(schemas[3]).unwrap();
+    let mut migrator = Migrator::new(
+        &[schemas[4]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let changes: Vec<_> = object_changes(&migrator.parse_metadata().unwrap(), &Config::default())
+        .into_iter()
+        .map(|c| (c.object_type, c.name, c.change_kind))
+        .collect();
+    assert_eq!(
+        vec![
+            (ObjectType::Table, "Job".to_owned(), ChangeKind::Unchanged),
+            (ObjectType::Table, "Node".to_owned(), ChangeKind::Unchanged),
+            (
+                ObjectType::Index,
+                "Job_node_oid".to_owned(),
+                ChangeKind::Unchanged
+            ),
+            (
+                ObjectType::Index,
+                "Node_node_id".to_owned(),
+                ChangeKind::Modified
+            ),
+        ],
+        changes
+    );
+
+    let connection = get_connection("changes2");
+    connection.execute_batch(schemas[1]).unwrap();
+    let mut migrator = Migrator::new(
+        &[schemas[0]],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let changes = object_changes(&migrator.parse_metadata().unwrap(), &Config::default());
+    assert!(changes
+        .iter()
+        .all(|c| c.change_kind == ChangeKind::Removed && c.source_sql.is_none()));
+}
+
+#[cfg(feature = "read-files")]
+#[rstest]
+fn test_read_extension_dir() {
+    use crate::read_extension_dir;
+    use std::env::consts::{DLL_EXTENSION, OS};
+
+    let dir = tempfile::tempdir().unwrap();
+    let os_dir = dir.path().join(OS);
+    std::fs::create_dir(&os_dir).unwrap();
+    let library = os_dir.join(format!("ext.{DLL_EXTENSION}"));
+    std::fs::write(&library, [0u8, 1, 2]).unwrap();
+    std::fs::write(os_dir.join("data.bin"), [0u8, 1, 2]).unwrap();
+    std::fs::write(os_dir.join("readme.txt"), "extension docs").unwrap();
+    assert_eq!(vec![library], read_extension_dir(dir.path()).unwrap());
+
+    let dir = tempfile::tempdir().unwrap();
+    let blob = dir.path().join("ext");
+    std::fs::write(&blob, [0u8, 1, 2]).unwrap();
+    std::fs::write(dir.path().join("readme.txt"), "extension docs").unwrap();
+    assert_eq!(vec![blob], read_extension_dir(dir.path()).unwrap());
+}
+
+#[cfg(feature = "read-files")]
+#[rstest]
+fn test_read_sql_dirs() {
+    use crate::read_sql_dirs;
+
+    let core = tempfile::tempdir().unwrap();
+    let plugins = tempfile::tempdir().unwrap();
+    std::fs::write(
+        core.path().join("10-node.sql"),
+        "CREATE TABLE Node(node_oid INTEGER PRIMARY KEY);",
+    )
+    .unwrap();
+    std::fs::write(
+        core.path().join("30-node_view.sql"),
+        "CREATE VIEW node_view AS SELECT * FROM Node JOIN Job USING(node_oid);",
+    )
+    .unwrap();
+    std::fs::write(
+        plugins.path().join("20-job.sql"),
+        "CREATE TABLE Job(node_oid INTEGER REFERENCES Node(node_oid));",
+    )
+    .unwrap();
+
+    let schema = read_sql_dirs(&[core.path(), plugins.path()]).unwrap();
+    assert_eq!(3, schema.len());
+    assert!(schema[0].sql.contains("TABLE Node"));
+    assert!(schema[1].sql.contains("TABLE Job"));
+    assert!(schema[2].sql.contains("VIEW node_view"));
+
+    std::fs::write(
+        plugins.path().join("40-node.sql"),
+        "CREATE TABLE IF NOT EXISTS node(node_oid INTEGER PRIMARY KEY);",
+    )
+    .unwrap();
+    let result = read_sql_dirs(&[core.path(), plugins.path()]);
+    assert!(matches!(
+        result,
+        Err(InitializationError::DuplicateDefinition(name, _, _)) if name == "node"
+    ));
+
+    std::fs::write(
+        plugins.path().join("40-node.sql"),
+        "-- CREATE TABLE node(node_oid INTEGER PRIMARY KEY);\n/* CREATE VIEW node_view AS SELECT 1; */",
+    )
+    .unwrap();
+    assert_eq!(
+        4,
+        read_sql_dirs(&[core.path(), plugins.path()]).unwrap().len()
+    );
+
+    let unreadable = plugins.path().join("50-binary.sql");
+    std::fs::write(&unreadable, [0xff, 0xfe, 0xfd]).unwrap();
+    let result = read_sql_dirs(&[core.path(), plugins.path()]);
+    assert!(matches!(
+        result,
+        Err(InitializationError::ReadFailure(path, _)) if path == unreadable
+    ));
+}
+
+#[cfg(feature = "read-files")]
+#[rstest]
+fn test_schema_error_path() {
+    use crate::read_sql_dirs;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("10-node.sql"),
+        "CREATE TABLE Node(id INTEGER PRIMARY KEY);",
+    )
+    .unwrap();
+    let broken = dir.path().join("20-job.sql");
+    std::fs::write(&broken, "CREATE TABLE Job(id INTEGER PRIMARY KEY,);").unwrap();
+
+    let schema = read_sql_dirs(&[dir.path()]).unwrap();
+    assert_eq!(Some(broken.clone()), schema[1].path);
+    let result = Migrator::new(
+        &schema,
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    );
+    let Err(err) = result else {
+        panic!("Expected an initialization error");
+    };
+    assert!(err.to_string().contains(&format!("{broken:?}")), "{err}");
+}
+
+#[rstest]
+fn test_query_error_location() {
+    let result = Migrator::new(
+        &["CREATE TABLE Item(\n    id INTEGER PRIMARY KEY,\n    name TEXT NOT NUL\n);"],
+        get_connection("query_error"),
+        crate::Config::default(),
+        Options::default(),
+    );
+    let Err(InitializationError::QueryFailure(_, error)) = result else {
+        panic!("expected query failure");
+    };
+    assert_eq!(Some(1), error.extended_code());
+    assert!(error.offset().is_some());
+    let message = error.to_string();
+    assert!(
+        message
+            .ends_with("at line 3, column 19:\n        name TEXT NOT NUL\n                      ^"),
+        "{message}"
+    );
+}
+
+#[rstest]
+fn test_validate() {
+    let schema = [
+        crate::SqlScript {
+            path: Some("item.sql".into()),
+            sql: "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT NOT NUL);".to_owned(),
+        },
+        "CREATE TABLE Tag(id INTEGER PRIMARY KEY);".into(),
+        "CREATE INDEX tag_idx ON Tag(missing);".into(),
+    ];
+    let Err(err @ InitializationError::ValidationFailure(_)) =
+        Migrator::validate(&schema, crate::Config::default())
+    else {
+        panic!("expected validation failure");
+    };
+    assert!(err.to_string().contains("- \"item.sql\": "), "{err}");
+    let InitializationError::ValidationFailure(errors) = err else {
+        unreachable!();
+    };
+    assert_eq!(2, errors.len());
+    assert_eq!(schema[0].path, errors[0].path);
+    assert_eq!(schema[0].sql, errors[0].error.sql());
+    assert_eq!(None, errors[1].path);
+    assert_eq!(schema[2].sql, errors[1].error.sql());
+
+    Migrator::validate(&schema[1..2], crate::Config::default()).unwrap();
+}
+
+#[rstest]
+fn test_dry_run_report() {
+    let target = get_connection("dry_run_report");
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, legacy TEXT);
+            CREATE TABLE Obsolete(id INTEGER PRIMARY KEY);
+            CREATE INDEX item_name_idx ON Item(name);
+            INSERT INTO Item(name) VALUES ('a'), ('b');",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);",
+            "CREATE TABLE Tag(id INTEGER PRIMARY KEY);",
+            "CREATE VIEW ItemView AS SELECT name FROM Item;",
+        ],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let report = migrator.dry_run_report().unwrap();
+    assert_eq!(
+        MigrationReport {
+            tables_created: 1,
+            tables_dropped: 1,
+            tables_modified: 1,
+            indexes_changed: 1,
+            views_changed: 1,
+            triggers_changed: 0,
+            columns_dropped: vec![("Item".to_owned(), "legacy".to_owned())],
+            tables_dropped_names: vec!["Obsolete".to_owned()],
+            rows_copied: vec![("Item".to_owned(), 2)],
+        },
+        report
+    );
+    assert!(report.has_changes());
+    assert!(report.has_data_loss());
+    let metadata = migrator.parse_metadata().unwrap();
+    assert!(metadata.target.tables().contains_key("Obsolete"));
+}
+
+#[rstest]
+fn test_dry_run_report_keep_extra_objects(
+    #[values(ExtraPolicy::Keep, ExtraPolicy::Error)] on_extra_objects: ExtraPolicy,
+) {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE Obsolete(id INTEGER PRIMARY KEY);
+            CREATE INDEX item_name_idx ON Item(name);
+            CREATE VIEW ObsoleteView AS SELECT id FROM Obsolete;
+            CREATE TRIGGER item_insert AFTER INSERT ON Item BEGIN DELETE FROM Obsolete; END;",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);",
+            "CREATE VIEW ItemView AS SELECT name FROM Item;",
+        ],
+        target,
+        crate::Config::default(),
+        Options {
+            on_extra_objects,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let report = migrator.dry_run_report().unwrap();
+    assert_eq!(0, report.tables_dropped);
+    assert_eq!(0, report.indexes_changed);
+    assert_eq!(1, report.views_changed);
+    assert_eq!(0, report.triggers_changed);
+}
+
+#[rstest]
+#[case(
+    "CREATE TABLE  \"Item\" ( id INTEGER )",
+    "CREATE TABLE Item(id INTEGER)"
+)]
+#[case("SELECT 'two  spaces' , 'a''  b'", "SELECT 'two  spaces','a''  b'")]
+#[case("SELECT 'it''s' -- don't\n  FROM x", "SELECT 'it''s' FROM x")]
+#[case("SELECT '-- not a comment'  -- comment", "SELECT '-- not a comment'")]
+#[case("SELECT '\"quoted\"'", "SELECT '\"quoted\"'")]
+#[case("CHECK( x>0 )", "CHECK(x>0)")]
+#[case("check (x > 0 AND y <= 10)", "CHECK(x>0 AND y<=10)")]
+#[case("CHECK ((x + 1) > 0)", "CHECK((x+1)>0)")]
+#[case("CHECK (x = 'a = b' OR y > 0)", "CHECK(x='a = b' OR y>0)")]
+#[case(
+    "x INTEGER CHECK (x > 0) DEFAULT (1 + 2)",
+    "x INTEGER CHECK(x>0)DEFAULT(1 + 2)"
+)]
+#[case("x INTEGER DEFAULT (1 + 2)", "x INTEGER DEFAULT(1 + 2)")]
+#[case(
+    "CREATE VIEW Total AS SELECT a + b FROM Item WHERE a = 1",
+    "CREATE VIEW Total AS SELECT a + b FROM Item WHERE a = 1"
+)]
+#[case("SELECT 'CHECK(a = 1)', b = 1", "SELECT 'CHECK(a = 1)',b = 1")]
+#[case("name TEXT collate  nocase", "name TEXT COLLATE NOCASE")]
+#[case("SELECT 'a = b'", "SELECT 'a = b'")]
+#[case("CREATE TABLE `Item`(`id` INTEGER)", "CREATE TABLE Item(id INTEGER)")]
+#[case("CREATE TABLE [Item]([id] INTEGER)", "CREATE TABLE Item(id INTEGER)")]
+#[case(
+    "CREATE TABLE Item(\"order\" INTEGER)",
+    "CREATE TABLE Item(\"order\" INTEGER)"
+)]
+#[case(
+    "CREATE TABLE Item([order] INTEGER)",
+    "CREATE TABLE Item(\"order\" INTEGER)"
+)]
+#[case(
+    "CREATE TABLE Item(`Group` INTEGER)",
+    "CREATE TABLE Item(\"Group\" INTEGER)"
+)]
+#[case(
+    "CREATE TABLE Item(\"1st\" INTEGER)",
+    "CREATE TABLE Item(\"1st\" INTEGER)"
+)]
+#[case(
+    "CREATE TABLE Item([first name] TEXT)",
+    "CREATE TABLE Item([first name] TEXT)"
+)]
+fn test_normalize_sql(#[case] sql: &str, #[case] expected: &str) {
+    assert_eq!(expected, normalize_sql(sql));
+}
+
+#[rstest]
+fn test_quoting_style_changes() {
+    let target = get_connection("quoting_style_changes");
+    target
+        .execute_batch(
+            "CREATE TABLE [Item]([id] INTEGER PRIMARY KEY, `name` TEXT, [order] INTEGER);
+            CREATE INDEX \"Item_name\" ON `Item`(\"name\");",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, \"order\" INTEGER);
+        CREATE INDEX Item_name ON Item(name);",
+        ],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+}
+
+#[rstest]
+fn test_cosmetic_constraint_changes() {
+    let target = get_connection("cosmetic_constraint_changes");
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, qty INTEGER CHECK( qty>0 ), name TEXT collate nocase);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, qty INTEGER CHECK (qty > 0), name TEXT COLLATE NOCASE);"],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+}
+
+#[rstest]
+fn test_trigger_literal_whitespace() {
+    let schema = [
+        "CREATE TABLE Item(id INTEGER PRIMARY KEY);",
+        "CREATE TABLE Log(message TEXT);",
+        "CREATE TRIGGER item_insert AFTER INSERT ON Item BEGIN INSERT INTO Log VALUES ('two  spaces'); END;",
+    ];
+    let target = get_connection("trigger_literal_whitespace");
+    target
+        .execute_batch(&schema.join("\n").replace("two  spaces", "two spaces"))
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &schema,
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert_eq!(1, migrator.dry_run_report().unwrap().triggers_changed);
+}
+
+#[rstest]
+fn test_ignore_internal_tables() {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT);
+        CREATE INDEX item_name ON Item(name);";
+    let target = get_connection("ignore_internal_tables");
+    target.execute_batch(schema).unwrap();
+    target
+        .execute_batch("INSERT INTO Item(name) VALUES('item'); ANALYZE;")
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        vec!["Item"],
+        metadata.target.tables().keys().collect::<Vec<_>>()
+    );
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+}
+
+#[rstest]
+fn test_analyze(#[values(true, false)] dry_run: bool) {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX item_name ON Item(name);";
+    let name = format!("analyze{dry_run}");
+    let target = get_connection(&name);
+    let connection2 = get_connection(&name);
+    target
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT); INSERT INTO Item(name) VALUES('item');")
+        .unwrap();
+    let migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options {
+            analyze: true,
+            dry_run,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.migrate().unwrap();
+    let stats: i64 = connection2
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name = 'sqlite_stat1'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(if dry_run { 0 } else { 1 }, stats);
+}
+
+#[rstest]
+#[case(3_007_017, false)]
+#[case(3_008_000, true)]
+#[case(3_045_001, true)]
+fn test_supports_defer_foreign_keys(#[case] version_number: i32, #[case] expected: bool) {
+    assert_eq!(expected, supports_defer_foreign_keys(version_number));
+}
+
+#[rstest]
+fn test_foreign_key_violation() {
+    let target = get_connection("foreign_key_violation");
+    target
+        .execute_batch(
+            "PRAGMA foreign_keys = ON;
+            CREATE TABLE Node(id INTEGER PRIMARY KEY);
+            CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER);
+            INSERT INTO Job(id, node_id) VALUES(3, 1);",
+        )
+        .unwrap();
+    let migrator = Migrator::new(
+        &["PRAGMA foreign_keys = ON;
+        CREATE TABLE Node(id INTEGER PRIMARY KEY);
+        CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER REFERENCES Node(id));"],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let Err(MigrationError::ForeignKeyViolation(violations)) = migrator.migrate() else {
+        panic!("expected foreign key violation");
+    };
+    assert_eq!(
+        vec![ForeignKeyViolation {
+            table: "Job".to_owned(),
+            rowid: Some(3),
+            parent: "Node".to_owned(),
+            fkid: 0,
+        }],
+        violations
+    );
+}
+
+#[rstest]
+#[case(1, None)]
+#[case(
+    2,
+    Some(ForeignKeyViolation {
+        table: "Job".to_owned(),
+        rowid: Some(3),
+        parent: "Node".to_owned(),
+        fkid: 0,
+    })
+)]
+fn test_apply_script(#[case] node_id: i64, #[case] violation: Option<ForeignKeyViolation>) {
+    let initial = "CREATE TABLE Node(id INTEGER PRIMARY KEY);
+        CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER);";
+    let schema = "CREATE TABLE Node(id INTEGER PRIMARY KEY);
+        CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER REFERENCES Node(id));";
+    let source = Connection::open_in_memory().unwrap();
+    source
+        .execute_batch(&format!("PRAGMA foreign_keys = ON; {initial}"))
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[format!("PRAGMA foreign_keys = ON; {schema}")],
+        source,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(
+        script.starts_with("PRAGMA foreign_keys = OFF;\nBEGIN;"),
+        "{script}"
+    );
+
+    let target = get_connection(&format!("apply_script{node_id}"));
+    let target2 = get_connection(&format!("apply_script{node_id}"));
+    target
+        .execute_batch(&format!(
+            "PRAGMA foreign_keys = ON; {initial}
+            INSERT INTO Node(id) VALUES(1);
+            INSERT INTO Job(id, node_id) VALUES(3, {node_id});"
+        ))
+        .unwrap();
+    let result = Migrator::apply_script(
+        &script,
+        target,
+        crate::Config::default(),
+        Options::default(),
+    );
+    match violation {
+        Some(violation) => {
+            let Err(MigrationError::ForeignKeyViolation(violations)) = result else {
+                panic!("expected foreign key violation, got {result:?}");
+            };
+            assert_eq!(vec![violation], violations);
+            assert_schema_equal(&target2, initial);
+        }
+        None => {
+            result.unwrap();
+            let job_sql: String = target2
+                .query_row(
+                    "SELECT sql FROM sqlite_master WHERE name = 'Job'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(job_sql.contains("REFERENCES Node(id)"), "{job_sql}");
+        }
+    }
+}
+
+#[rstest]
+#[case("PRAGMA foreign_keys = ON;", true)]
+#[case("PRAGMA foreign_keys = OFF;", false)]
+fn test_foreign_key_check_follows_source(#[case] source_pragma: &str, #[case] checked: bool) {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "PRAGMA foreign_keys = OFF;
+            CREATE TABLE Node(id INTEGER PRIMARY KEY);
+            CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER);
+            INSERT INTO Job(id, node_id) VALUES(3, 1);",
+        )
+        .unwrap();
+    let migrator = Migrator::new(
+        &[format!(
+            "{source_pragma}
+            CREATE TABLE Node(id INTEGER PRIMARY KEY);
+            CREATE TABLE Job(id INTEGER PRIMARY KEY, node_id INTEGER REFERENCES Node(id));"
+        )],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let result = migrator.migrate();
+    assert_eq!(
+        checked,
+        matches!(result, Err(MigrationError::ForeignKeyViolation(_))),
+        "{result:?}"
+    );
+}
+
+#[rstest]
+fn test_diff_sections() {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE VIEW ItemNames AS SELECT name FROM Item;";
+    let target = get_connection("diff_sections");
+    target
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);")
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let diff = strip_ansi(&migrator.diff().unwrap());
+    let headers: Vec<_> = diff.lines().filter(|l| l.starts_with("-- ")).collect();
+    assert_eq!(
+        vec!["-- Tables", "-- Indexes", "-- Views", "-- Triggers"],
+        headers
+    );
+    let views = &diff[diff.find("-- Views").unwrap()..diff.find("-- Triggers").unwrap()];
+    assert!(views.contains("CREATE VIEW ItemNames"), "{diff}");
+    assert!(diff[..diff.find("-- Indexes").unwrap()].contains("No changes"));
+
+    let target = get_connection("diff_sections_unchanged");
+    target.execute_batch(schema).unwrap();
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(migrator.diff().unwrap().is_empty());
+}
+
+#[rstest]
+#[case(1, 2)]
+#[case(3, 1)]
+fn test_diff_context_lines(#[case] context_lines: u32, #[case] hunks: usize) {
+    let source = (0..12)
+        .map(|i| format!("line {i}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let target = source
+        .replace("line 2\n", "changed 2\n")
+        .replace("line 9\n", "changed 9\n");
+    let options = DiffOptions {
+        algorithm: DiffAlgorithm::Myers,
+        context_lines,
+    };
+    let diff = strip_ansi(&sql_diff_with_options(&source, &target, &options).diff_text);
+    assert_eq!(hunks, diff.matches("@@ -").count(), "{diff}");
+}
+
+#[rstest]
+fn test_case_insensitive_names() {
+    let target = get_connection("case_insensitive_names");
+    let connection = get_connection("case_insensitive_names");
+    target
+        .execute_batch(
+            "CREATE TABLE node(id integer primary key, name text);
+            CREATE INDEX node_name ON node(name);
+            INSERT INTO node(name) VALUES('test');",
+        )
+        .unwrap();
+    let schema = "CREATE TABLE Node(id integer primary key, name text);
+        CREATE INDEX Node_Name ON Node(name);";
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        vec!["Node"],
+        metadata.target.tables().keys().collect::<Vec<_>>()
+    );
+    migrator.migrate().unwrap();
+
+    let name: String = connection
+        .query_row("SELECT name FROM Node", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!("test", name);
+    let mut migrator = Migrator::new(
+        &[schema],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    let summary = metadata
+        .source
+        .diff_summary(&metadata.target, &Config::default());
+    assert!(
+        summary.values().all(|changes| changes.is_empty()),
+        "{summary:?}"
+    );
+}
+
+#[rstest]
+fn test_diff_objects() {
+    use crate::DiffLine;
+
+    let target = get_connection("diff_objects");
+    target
+        .execute_batch(
+            "CREATE TABLE kept(id integer primary key);
+            CREATE TABLE changed(id integer primary key);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE kept(id integer primary key);
+        CREATE TABLE changed(id integer primary key, name text);"],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let diffs = migrator.diff_objects().unwrap();
+    assert_eq!(1, diffs.len());
+    assert_eq!("changed", diffs[0].name);
+    assert_eq!(ObjectType::Table, diffs[0].object_type);
+    let hunk = &diffs[0].hunks[0];
+    assert_eq!(1, hunk.before_start);
+    assert_eq!(1, hunk.after_start);
+    assert!(
+        hunk.lines
+            .iter()
+            .any(|l| matches!(l, DiffLine::Removed(line) if !line.contains("name"))),
+        "{hunk:?}"
+    );
+    assert!(
+        hunk.lines
+            .iter()
+            .any(|l| matches!(l, DiffLine::Added(line) if line.contains("name"))),
+        "{hunk:?}"
+    );
+}
+
+#[rstest]
+fn test_busy_timeout() {
+    let migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY);"],
+        get_connection("busy_timeout"),
+        crate::Config::default(),
+        Options {
+            busy_timeout: Some(Duration::from_millis(250)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let busy_timeout: i64 = migrator
+        .target_connection
+        .lock()
+        .unwrap()
+        .get_pragma("busy_timeout")
+        .unwrap();
+    assert_eq!(250, busy_timeout);
+}
+
+#[rstest]
+fn test_read_only_target() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let target_path = tempdir.path().join("target.db");
+    let connection = Connection::open(&target_path).unwrap();
+    connection
+        .execute_batch("PRAGMA foreign_keys = ON; CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    drop(connection);
+
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        Connection::open_with_flags(
+            &target_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .unwrap(),
+        crate::Config::default(),
+        Options {
+            dry_run: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(!migrator.diff().unwrap().is_empty());
+    migrator.migrate().unwrap();
+
+    let connection = Connection::open(&target_path).unwrap();
+    let sql: String = connection
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'Item'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!("CREATE TABLE Item(id INTEGER PRIMARY KEY)", sql);
+}
+
+#[rstest]
+fn test_new_read_only() {
+    let connection = get_connection("new_read_only");
+    connection
+        .execute_batch("PRAGMA foreign_keys = ON; CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+
+    let mut migrator = Migrator::new_read_only(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);"],
+        connection,
+        crate::Config::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(1, metadata.target.tables().len());
+    assert!(!migrator.diff().unwrap().is_empty());
+    assert!(matches!(
+        migrator.migrate_to_sql(),
+        Err(MigrationError::ReadOnly)
+    ));
+
+    let foreign_keys: i32 = migrator
+        .target_connection
+        .lock()
+        .unwrap()
+        .get_pragma("foreign_keys")
+        .unwrap();
+    assert_eq!(1, foreign_keys);
+    assert!(matches!(migrator.migrate(), Err(MigrationError::ReadOnly)));
+}
+
+#[rstest]
+#[case("b TEXT", Some(vec!["b TEXT"]))]
+#[case("b INTEGER NOT NULL DEFAULT 1", Some(vec!["b INTEGER NOT NULL DEFAULT 1"]))]
+#[case("b INTEGER NOT NULL", None)]
+#[case("b INTEGER NOT NULL DEFAULT NULL", None)]
+#[case("b TEXT DEFAULT CURRENT_TIMESTAMP", None)]
+#[case("b TEXT PRIMARY KEY", None)]
+fn test_added_columns(#[case] column: &str, #[case] expected: Option<Vec<&str>>) {
+    assert_eq!(
+        expected.map(|e| e.into_iter().map(|c| c.to_owned()).collect::<Vec<_>>()),
+        added_columns(
+            "CREATE TABLE t(a INTEGER)",
+            &format!("CREATE TABLE t(a INTEGER, {column})"),
+            &["a".to_owned()],
+            &["a".to_owned(), "b".to_owned()],
+        )
+    );
+}
+
+#[rstest]
+#[case(
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT)",
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, description TEXT, qty INTEGER NOT NULL DEFAULT 0)",
+    true
+)]
+#[case(
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, UNIQUE(name))",
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, description TEXT, UNIQUE(name))",
+    true
+)]
+#[case(
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT)",
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, code TEXT GENERATED ALWAYS AS (name || id) STORED)",
+    false
+)]
+#[case(
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT)",
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, code TEXT UNIQUE, name TEXT)",
+    false
+)]
+#[case(
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT)",
+    "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT NOT NULL, description TEXT)",
+    false
+)]
+fn test_add_columns(#[case] from: &str, #[case] to: &str, #[case] altered: bool) {
+    let name = format!("add_columns{}", to.len());
+    let connection = get_connection(&name);
+    connection.execute_batch(from).unwrap();
+    connection
+        .execute("INSERT INTO Item(id, name) VALUES(5, 'item')", [])
+        .unwrap();
+
+    let mut migrator = Migrator::new(
+        &[to],
+        get_connection(&name),
+        crate::Config::default(),
+        Options {
+            allow_deletions: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let report = migrator.dry_run_report().unwrap();
+    assert_eq!(altered, report.rows_copied.is_empty());
+    let script = migrator.migrate_to_sql().unwrap();
+    assert_eq!(altered, script.contains("ADD COLUMN"), "{script}");
+    assert_eq!(!altered, script.contains("Item_migration_new"), "{script}");
+    migrator.migrate().unwrap();
+
+    let mut migrator = Migrator::new(
+        &[to],
+        get_connection(&name),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+    let name: String = connection
+        .query_row("SELECT name FROM Item WHERE id = 5", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!("item", name);
+}
+
+#[rstest]
+#[case(
+    "CREATE VIRTUAL TABLE Search USING fts5(title)",
+    "CREATE VIRTUAL TABLE Search USING fts5(title, body)"
+)]
+#[case(
+    "CREATE VIRTUAL TABLE Search USING fts5(title)",
+    "CREATE VIRTUAL TABLE Search USING fts5(title, tokenize = 'porter')"
+)]
+#[case(
+    "CREATE VIRTUAL TABLE Search USING rtree(id, minX, maxX)",
+    "CREATE VIRTUAL TABLE Search USING rtree(id, minX, maxX, minY, maxY)"
+)]
+fn test_virtual_tables(#[case] from: &str, #[case] to: &str) {
+    let name = format!("virtual_tables{}", to.len());
+    let connection = get_connection(&name);
+    connection.execute_batch(from).unwrap();
+
+    let mut migrator = Migrator::new(
+        &[to],
+        get_connection(&name),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let script = migrator.migrate_to_sql().unwrap();
+    assert!(!script.contains("Search_migration_new"), "{script}");
+    migrator.migrate().unwrap();
+
+    let mut migrator = Migrator::new(
+        &[to],
+        get_connection(&name),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+    let sql: String = connection
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'Search'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(to, sql);
+}
+
+#[rstest]
+fn test_objects_merge() {
+    let connection = get_connection("objects_merge");
+    connection
+        .execute_batch(
+            "CREATE TABLE Album(id INTEGER PRIMARY KEY); CREATE TABLE Track(id INTEGER PRIMARY KEY);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Artist(id INTEGER PRIMARY KEY);",
+            "CREATE TABLE Track(id INTEGER PRIMARY KEY, name TEXT);",
+            "CREATE INDEX track_name ON Track(name);",
+        ],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    let source = Objects::from_metadata(&metadata.source);
+    assert_eq!(vec!["Artist", "Track"], source.tables());
+    assert_eq!(vec!["track_name"], source.indexes());
+
+    let merged = source.merge(Objects::from_metadata(&metadata.target));
+    assert_eq!(vec!["Album", "Artist", "Track"], merged.tables());
+    assert_eq!(vec!["track_name"], merged.indexes());
+    assert!(merged.views().is_empty());
+    assert!(merged.triggers().is_empty());
+}
+
+#[rstest]
+fn test_cancellation() {
+    let connection = get_connection("cancellation");
+    connection
+        .execute_batch("CREATE TABLE Item(id INTEGER PRIMARY KEY);")
+        .unwrap();
+    let schema = [
+        "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);",
+        "CREATE TABLE Tag(id INTEGER PRIMARY KEY);",
+        "CREATE INDEX item_name ON Item(name);",
+    ];
+
+    let token = CancellationToken::new();
+    let progress_token = token.clone();
+    let result = Migrator::new(
+        &schema,
+        get_connection("cancellation"),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap()
+    .with_cancellation_token(token)
+    .migrate_with_progress(|progress| {
+        if progress.phase == Phase::Tables {
+            progress_token.cancel();
+        }
+    });
+    assert!(matches!(result, Err(MigrationError::Cancelled)));
+    assert_eq!(1, dump_sqlite_master(&connection).len());
+
+    let token = CancellationToken::new();
+    Migrator::new(
+        &schema,
+        get_connection("cancellation"),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap()
+    .with_cancellation_token(token.clone())
+    .migrate()
+    .unwrap();
+    assert!(!token.is_cancelled());
+    assert_eq!(3, dump_sqlite_master(&connection).len());
+}
+
+#[rstest]
+fn test_table_columns() {
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'none', price REAL);"],
+        get_connection("table_columns"),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        Some(
+            &[
+                ColumnDef {
+                    name: "id".to_owned(),
+                    column_type: "INTEGER".to_owned(),
+                    notnull: false,
+                    default: None,
+                    pk: 1,
+                },
+                ColumnDef {
+                    name: "name".to_owned(),
+                    column_type: "TEXT".to_owned(),
+                    notnull: true,
+                    default: Some("'none'".to_owned()),
+                    pk: 0,
+                },
+                ColumnDef {
+                    name: "price".to_owned(),
+                    column_type: "REAL".to_owned(),
+                    notnull: false,
+                    default: None,
+                    pk: 0,
+                },
+            ][..]
+        ),
+        metadata.source.table_columns("Item")
+    );
+    assert_eq!(None, metadata.target.table_columns("Item"));
+}
+
+#[rstest]
+fn test_treat_hooks_as_schema(#[values(true, false)] treat_hooks_as_schema: bool) {
+    let name = format!("hooks_as_schema{treat_hooks_as_schema}");
+    let connection = get_connection(&name);
+    connection
+        .execute_batch("CREATE TABLE Audit(id INTEGER PRIMARY KEY, note TEXT);")
+        .unwrap();
+    let config = crate::Config {
+        before_migration: vec!["CREATE TABLE IF NOT EXISTS Audit(id INTEGER PRIMARY KEY);".into()],
+        after_migration: vec!["CREATE VIEW IF NOT EXISTS ItemView AS SELECT * FROM Item;".into()],
+        treat_hooks_as_schema,
+        ..Default::default()
+    };
+    let schema = ["CREATE TABLE Item(id INTEGER PRIMARY KEY);"];
+
+    let mut migrator = Migrator::new(
+        &schema,
+        get_connection(&name),
+        config.clone(),
+        Options {
+            allow_deletions: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        treat_hooks_as_schema,
+        metadata.source.tables().contains_key("Audit")
+    );
+    assert_eq!(
+        treat_hooks_as_schema,
+        metadata.source.views().contains_key("ItemView")
+    );
+    migrator.migrate().unwrap();
+
+    let audit_sql: String = connection
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'Audit'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    // The existing definition is only replaced when the hook's version is part of the schema
+    assert_eq!(
+        !treat_hooks_as_schema,
+        audit_sql.contains("note"),
+        "{audit_sql}"
+    );
+
+    let mut migrator =
+        Migrator::new(&schema, get_connection(&name), config, Options::default()).unwrap();
+    assert!(!migrator.dry_run_report().unwrap().has_changes());
+}
+
+#[rstest]
+fn test_load_extension_by_name() {
+    use crate::{load_extensions, ExtensionSpec};
+    use std::path::{Path, PathBuf};
+
+    let name = ExtensionSpec::Name("mod_missing".to_owned());
+    assert_eq!(Path::new("mod_missing"), name.as_ref());
+    assert_eq!(
+        ExtensionSpec::Path(PathBuf::from("ext/mod.so")),
+        PathBuf::from("ext/mod.so").into()
+    );
+    let connection = Connection::open_in_memory().unwrap();
+    assert!(load_extensions(&connection, [name]).is_err());
+}
+
+#[rstest]
+fn test_extension_load_failure() {
+    use std::path::PathBuf;
+
+    let config = crate::Config {
+        extensions: vec![PathBuf::from("missing/mod_missing.so").into()],
+        ..Default::default()
+    };
+    let result = Migrator::new(
+        &["CREATE TABLE Item(id INTEGER PRIMARY KEY);"],
+        Connection::open_in_memory().unwrap(),
+        config,
+        Options::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(InitializationError::ExtensionLoadFailure(path, _))
+            if path == PathBuf::from("missing/mod_missing.so")
+    ));
+}
+
+#[rstest]
+fn test_unified_objects() {
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE a(id INTEGER PRIMARY KEY);
+            CREATE TABLE b(id INTEGER PRIMARY KEY);
+            CREATE TABLE c(id INTEGER PRIMARY KEY);
+            CREATE INDEX b_idx ON b(id);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE a(id INTEGER PRIMARY KEY);
+        CREATE TABLE b(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE d(id INTEGER PRIMARY KEY);
+        CREATE INDEX b_idx ON b(id);"],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let objects = migrator.parse_metadata().unwrap().unified_objects();
+    assert_eq!(
+        vec![
+            (ObjectType::Table, "a"),
+            (ObjectType::Table, "b"),
+            (ObjectType::Table, "c"),
+            (ObjectType::Table, "d"),
+            (ObjectType::Index, "b_idx"),
+        ],
+        objects
+            .iter()
+            .map(|o| (o.object_type.clone(), o.name.as_str()))
+            .collect::<Vec<_>>()
+    );
+    // Modified objects use the source definition
+    assert!(objects[1].sql.contains("name TEXT"));
+}
+
+#[rstest]
+fn test_metadata_dump() {
+    let schema = "CREATE VIEW b_view AS SELECT * FROM b;
+        CREATE TABLE b(id INTEGER PRIMARY KEY, a_id INTEGER REFERENCES a(id));
+        CREATE TABLE a(id INTEGER PRIMARY KEY);
+        CREATE INDEX b_idx ON b(a_id);";
+    let mut migrator = Migrator::new(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert_eq!(
+        "CREATE TABLE a(id INTEGER PRIMARY KEY);
+
+CREATE TABLE b(id INTEGER PRIMARY KEY, a_id INTEGER REFERENCES a(id));
+
+CREATE INDEX b_idx ON b(a_id);
+
+CREATE VIEW b_view AS SELECT * FROM b;
+",
+        metadata.source.dump()
+    );
+    assert_eq!("", metadata.target.dump());
+}
+
+#[rstest]
+fn test_metadata_structurally_equal() {
+    let schema = "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT);
+        CREATE INDEX Item_name ON Item(name);";
+    let target = Connection::open_in_memory().unwrap();
+    target
+        .execute_batch(
+            "CREATE TABLE Item(
+                id INTEGER PRIMARY KEY,
+                -- The item's name
+                name TEXT
+            );
+            CREATE INDEX Item_name ON Item(name);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[schema],
+        target,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert!(metadata
+        .source
+        .structurally_equal(&metadata.target, &Config::default()));
+    assert!(!metadata.source.is_empty());
+
+    let mut migrator = Migrator::new(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    assert!(!metadata
+        .source
+        .structurally_equal(&metadata.target, &Config::default()));
+    assert!(!metadata
+        .target
+        .structurally_equal(&metadata.source, &Config::default()));
+    assert!(metadata.target.is_empty());
+}
+
+#[rstest]
+#[case(ExtraPolicy::Drop)]
+#[case(ExtraPolicy::Keep)]
+fn test_verify_after_dry_run(#[case] on_extra_objects: ExtraPolicy) {
+    let target = get_connection(&format!("verify_after_dry_run{on_extra_objects:?}"));
+    let target2 = get_connection(&format!("verify_after_dry_run{on_extra_objects:?}"));
+    target
+        .execute_batch(
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, price INTEGER);
+            CREATE TABLE Extra(id INTEGER PRIMARY KEY);
+            INSERT INTO Item(id, name, price) VALUES(1, 'item', 5);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &[
+            "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE INDEX Item_name ON Item(name);
+        CREATE VIEW ItemNames AS SELECT name FROM Item;",
+        ],
+        target,
+        crate::Config::default(),
+        Options {
+            allow_deletions: true,
+            on_extra_objects,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    migrator.verify_after_dry_run().unwrap();
+    assert_schema_equal(
+        &target2,
+        "CREATE TABLE Item(id INTEGER PRIMARY KEY, name TEXT, price INTEGER);
+        CREATE TABLE Extra(id INTEGER PRIMARY KEY);",
+    );
+}
+
+#[rstest]
+fn test_diff_summary() {
+    use crate::ChangeKind;
+
+    let connection = get_connection("diff_summary");
+    connection
+        .execute_batch(
+            "CREATE TABLE kept(id int primary key);
+            CREATE TABLE changed(id int primary key);
+            CREATE TABLE removed(id int primary key);
+            CREATE INDEX kept_idx ON kept(id);",
+        )
+        .unwrap();
+    let mut migrator = Migrator::new(
+        &["CREATE TABLE   kept(id int primary key);
+        CREATE TABLE changed(id int primary key, name text);
+        CREATE TABLE added(id int primary key);
+        CREATE INDEX kept_idx ON kept(id);"],
+        connection,
+        crate::Config::default(),
+        Options::default(),
+    )
+    .unwrap();
+    let metadata = migrator.parse_metadata().unwrap();
+    let summary = metadata
+        .source
+        .diff_summary(&metadata.target, &Config::default());
+    assert_eq!(
+        vec![
+            ("added".to_owned(), ChangeKind::Added),
+            ("changed".to_owned(), ChangeKind::Modified),
+            ("removed".to_owned(), ChangeKind::Removed),
+        ],
+        summary[&ObjectType::Table]
+    );
+    assert!(summary[&ObjectType::Index].is_empty());
+}
+
+#[rstest]
+fn test_migration_timings() {
+    let schema = "CREATE TABLE test(id int primary key); CREATE INDEX test_idx ON test(id);";
+    let config = crate::Config {
+        before_migration: vec!["SELECT 1;".into()],
+        ..Default::default()
+    };
+    let migrator = Migrator::new(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        config,
+        Options::default(),
+    )
+    .unwrap();
+    let timings = migrator.migrate_with_timings().unwrap();
+    let phases: Vec<_> = timings.phases.iter().map(|t| t.phase).collect();
+    assert_eq!(
+        vec![
+            "Executing pre-migration scripts",
+            "Creating tables",
+            "Dropping tables",
+            "Modifying tables",
+            "Migrating tables",
+            "Migrating indexes",
+            "Migrating views",
+            "Migrating triggers",
+        ],
+        phases
+    );
+    assert!(timings.get("Migrating tables").unwrap() >= timings.get("Creating tables").unwrap());
+    assert!(timings.total >= timings.get("Migrating tables").unwrap());
+    assert_eq!(None, timings.get("Executing post-migration scripts"));
+}
+
+#[rstest]
+fn test_source_definitions() {
+    let schema = r#"
+-- Stores users
+CREATE TABLE user(
+    id int primary key, -- the id
+    name text default 'a;b' /* name; optional */
+);
+
+/* Audit log */
+CREATE TABLE log(id int primary key, message text);
+
+-- Logs inserts
+CREATE TRIGGER user_insert AFTER INSERT ON user
+BEGIN
+    INSERT INTO log(message) VALUES ('inserted');
+    INSERT INTO log(message) VALUES ('done');
+END;
+"#;
+    let migrator = Migrator::new_read_only(
+        &[schema],
+        Connection::open_in_memory().unwrap(),
+        crate::Config::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        Some(
+            "-- Stores users\nCREATE TABLE user(\n    id int primary key, -- the id\n    name text default 'a;b' /* name; optional */\n);"
+        ),
+        migrator.source_definition("user")
+    );
+    assert_eq!(
+        Some("/* Audit log */\nCREATE TABLE log(id int primary key, message text);"),
+        migrator.source_definition("LOG")
+    );
+    assert!(migrator
+        .source_definition("user_insert")
+        .unwrap()
+        .ends_with("VALUES ('done');\nEND;"));
+    assert_eq!(None, migrator.source_definition("missing"));
+}
+
+#[cfg(feature = "pretty-print")]
+#[rstest]
+fn test_plain_sql_printer() {
+    let sql = "CREATE TABLE test(id int primary key);";
+    let mut printer = crate::SqlPrinter::plain();
+    assert!(printer.is_plain());
+    assert_eq!(sql, printer.print(sql));
+    assert_eq!(sql, printer.print_on(sql, crate::Color::Green));
+    assert_ne!(
+        sql,
+        crate::SqlPrinter::with_theme("ansi").unwrap().print(sql)
+    );
+}
+
+#[cfg(feature = "pretty-print")]
+#[rstest]
+fn test_sql_printer_theme() {
+    let themes = crate::SqlPrinter::theme_names();
+    assert!(themes.contains(&"ansi".to_owned()));
+    for theme in &themes {
+        assert!(crate::SqlPrinter::with_theme(theme).is_ok());
+    }
+    let err = crate::SqlPrinter::with_theme("missing").err().unwrap();
+    assert!(err.to_string().starts_with("Theme missing was not found"));
+    assert!(crate::SqlPrinter::set_default_theme("missing").is_err());
+}
+
+#[rstest]
+fn test_sort_by_dependencies() {
+    let tables = BTreeMap::from_iter([
+        (
+            "Album".to_owned(),
+            "CREATE TABLE Album(id INTEGER PRIMARY KEY, artist_id INTEGER REFERENCES Artist(id))"
+                .to_owned(),
+        ),
+        (
+            "Artist".to_owned(),
+            "CREATE TABLE Artist(id INTEGER PRIMARY KEY, label_id INTEGER REFERENCES Label(id))"
+                .to_owned(),
+        ),
+        (
+            "Label".to_owned(),
+            "CREATE TABLE Label(id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES Label(id))"
+                .to_owned(),
+        ),
+        (
+            "Genre".to_owned(),
+            "CREATE TABLE Genre(id INTEGER PRIMARY KEY)".to_owned(),
+        ),
+    ]);
+    let sorted: Vec<_> = sort_by_dependencies(&tables)
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(vec!["Genre", "Label", "Artist", "Album"], sorted);
+
+    let tables = BTreeMap::from_iter([
+        (
+            "Album".to_owned(),
+            "CREATE TABLE Album(id INTEGER PRIMARY KEY, artist_id INTEGER REFERENCES \"ARTIST\"(id))"
+                .to_owned(),
+        ),
+        (
+            "Artist".to_owned(),
+            "CREATE TABLE Artist(id INTEGER PRIMARY KEY, label_id INTEGER REFERENCES main.[label](id))"
+                .to_owned(),
+        ),
+        (
+            "Label".to_owned(),
+            "CREATE TABLE Label(id INTEGER PRIMARY KEY)".to_owned(),
+        ),
+        (
+            "aux.Track".to_owned(),
+            "CREATE TABLE aux.Track(id INTEGER PRIMARY KEY, album_id INTEGER REFERENCES album(id))"
+                .to_owned(),
+        ),
+        (
+            "aux.album".to_owned(),
+            "CREATE TABLE aux.album(id INTEGER PRIMARY KEY)".to_owned(),
+        ),
+    ]);
+    let sorted: Vec<_> = sort_by_dependencies(&tables)
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(
+        vec!["Label", "aux.album", "Artist", "aux.Track", "Album"],
+        sorted
+    );
+}
+
+#[rstest]
+fn test_sort_by_dependencies_cyclic() {
+    let tables = BTreeMap::from_iter([
+        (
+            "B".to_owned(),
+            "CREATE TABLE B(id INTEGER PRIMARY KEY, a_id INTEGER REFERENCES A(id))".to_owned(),
+        ),
+        (
+            "A".to_owned(),
+            "CREATE TABLE A(id INTEGER PRIMARY KEY, b_id INTEGER REFERENCES B(id))".to_owned(),
+        ),
+    ]);
+    let sorted: Vec<_> = sort_by_dependencies(&tables)
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(vec!["A", "B"], sorted);
+}
+
+#[cfg(feature = "tui")]
+#[rstest]
+fn test_objects_filter() {
+    let mut state = objects_state();
+    assert_eq!(
+        vec![
+            "Tables",
+            "  album",
+            "  artist",
+            "Indexes",
+            "  album_idx",
+            "Views",
+            "Triggers"
+        ],
+        rendered_objects(&mut state)
+    );
+    assert_eq!(Some("album".to_owned()), state.selected_item());
+    assert_eq!(Some(0), state.selected_index());
+
+    // Matching is case-insensitive and only headers with a matching entry are kept
+    state.set_filter("ART".to_owned());
+    assert_eq!(
+        vec!["Tables", "  artist", "/ART"],
+        rendered_objects(&mut state)
+    );
+    assert_eq!(Some("artist".to_owned()), state.selected_item());
+    assert_eq!(Some(1), state.selected_index());
+
+    // The selection is kept if it still matches
+    state.set_filter("a".to_owned());
+    assert_eq!(Some("artist".to_owned()), state.selected_item());
+
+    state.set_filter("album".to_owned());
+    assert_eq!(
+        vec!["Tables", "  album", "Indexes", "  album_idx", "/album"],
+        rendered_objects(&mut state)
+    );
+    assert_eq!(Some("album".to_owned()), state.selected_item());
+    state.next();
+    assert_eq!(Some("album_idx".to_owned()), state.selected_item());
+    assert_eq!(Some(2), state.selected_index());
+
+    state.set_filter("missing".to_owned());
+    // Only the filter is shown when nothing matches
+    assert_eq!(vec!["/missing"], rendered_objects(&mut state));
+    assert_eq!(None, state.selected_item());
+    assert_eq!(None, state.selected_index());
+    state.next();
+    assert_eq!(None, state.selected_index());
+
+    state.clear_filter();
+    assert_eq!("", state.filter());
+    assert_eq!(7, rendered_objects(&mut state).len());
+    assert_eq!(Some("album".to_owned()), state.selected_item());
+    assert_eq!(Some(0), state.selected_index());
+}
+
+#[cfg(feature = "tui")]
+fn objects_state() -> crate::tui::ObjectsState {
+    use crate::tui::{StyledObject, StyledObjects};
+
+    let styled = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| StyledObject {
+                object: name.to_string(),
+                foreground: ratatui::style::Color::Reset,
+            })
+            .collect::<Vec<_>>()
+    };
+    crate::tui::ObjectsState::new(StyledObjects::from_iter([
+        (ObjectType::Table, styled(&["album", "artist"])),
+        (ObjectType::Index, styled(&["album_idx"])),
+        (ObjectType::View, styled(&[])),
+        (ObjectType::Trigger, styled(&[])),
+    ]))
+}
+
+#[cfg(feature = "tui")]
+fn rendered_objects(state: &mut crate::tui::ObjectsState) -> Vec<String> {
+    use ratatui::{buffer::Buffer, layout::Rect, widgets::StatefulWidget};
+
+    let area = Rect::new(0, 0, 30, 10);
+    let mut buf = Buffer::empty(area);
+    crate::tui::Objects::new(ratatui::widgets::Block::default()).render(area, &mut buf, state);
+    (0..area.height)
+        .map(|y| {
+            (0..area.width)
+                .map(|x| buf.get(x, y).symbol())
+                .collect::<String>()
+                .trim_end()
+                .to_owned()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn strip_ansi(text: &str) -> String {
+    Regex::new(r"\x1b\[[0-9;]*m")
+        .unwrap()
+        .replace_all(text, "")
+        .to_string()
+}
+
 fn get_connection(name: &str) -> Connection {
     Connection::open_with_flags(
         format!("file:memdb{name}"),
@@ -1,36 +1,76 @@
-use std::{collections::BTreeMap, ops::Deref};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Deref,
+};
 
-use regex::Regex;
 use rusqlite::Connection;
+use sha2::{Digest, Sha256};
 use tracing::Level;
 
-use crate::{query, Object, ObjectType, QueryError, SqlPrinter};
+use crate::{
+    get_pragma, normalize_sql, qualify_sql, query, query_params, sort_by_dependencies, Config,
+    Object, ObjectType, QueryError, SqlPrinter,
+};
 
 #[derive(Clone, Debug, Default)]
-pub struct Metadata(BTreeMap<ObjectType, BTreeMap<String, String>>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    objects: BTreeMap<ObjectType, BTreeMap<String, String>>,
+    columns: BTreeMap<String, Vec<ColumnDef>>,
+    user_version: i64,
+    // Hash of the schema text used to generate the metadata, only set for source metadata
+    pub(crate) schema_hash: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColumnDef {
+    pub name: String,
+    pub column_type: String,
+    pub notnull: bool,
+    pub default: Option<String>,
+    // Position of the column in the primary key, or 0 if it isn't part of it
+    pub pk: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+}
 
 impl Deref for Metadata {
     type Target = BTreeMap<ObjectType, BTreeMap<String, String>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.objects
     }
 }
 
 impl Metadata {
+    /// Returns every object that exists in either schema, ordered by type and then by name.
+    /// Objects that exist in both are only included once, using the definition from `self`.
     pub fn unified_objects(&self, other: &Metadata) -> Vec<Object> {
         let mut all: Vec<_> = self
             .all_objects()
-            .iter()
-            .chain(other.all_objects().iter())
-            .map(|k| k.to_owned())
+            .into_iter()
+            .chain(other.all_objects())
             .collect();
-        all.dedup();
+        // The sort is stable, so the first object in each group is from self
+        all.sort();
+        all.dedup_by(|a, b| a.object_type == b.object_type && a.name == b.name);
         all
     }
 
     pub fn all_objects(&self) -> Vec<Object> {
-        self.0
+        self.objects
             .iter()
             .flat_map(|(object_type, objects)| {
                 objects.iter().map(|(name, sql)| Object {
@@ -46,92 +86,301 @@ impl Metadata {
         connection: &Connection,
         log_level: Level,
         msg: &str,
-        ignore: &Option<Regex>,
+        config: &Config,
         sql_printer: &mut SqlPrinter,
     ) -> Result<Metadata, QueryError> {
-        let metadata_sql = |name: &str| {
-            format!("SELECT name, sql from sqlite_master WHERE type = '{name}' and name != 'sqlite_sequence' AND sql IS NOT NULL ORDER BY name")
-        };
+        let mut map = BTreeMap::<ObjectType, BTreeMap<String, String>>::from_iter(
+            [
+                ObjectType::Table,
+                ObjectType::Index,
+                ObjectType::View,
+                ObjectType::Trigger,
+            ]
+            .map(|object_type| (object_type, BTreeMap::new())),
+        );
+        let mut columns = BTreeMap::new();
+        for schema in std::iter::once("main")
+            .chain(config.attachments.iter().map(|(alias, _)| alias.as_str()))
+        {
+            for (object_type, name, sql) in
+                select_metadata(connection, schema, log_level, msg, config, sql_printer)?
+            {
+                if object_type == ObjectType::Table {
+                    let table = if schema == "main" {
+                        &name
+                    } else {
+                        &name[schema.len() + 1..]
+                    };
+                    let table_columns =
+                        select_columns(connection, schema, table, log_level, msg, sql_printer)?;
+                    columns.insert(name.clone(), table_columns);
+                }
+                map.entry(object_type).or_default().insert(name, sql);
+            }
+        }
+
+        let user_version = get_pragma(connection, "user_version", log_level, msg, sql_printer)?;
+
+        Ok(Metadata {
+            objects: map,
+            columns,
+            user_version,
+            schema_hash: None,
+        })
+    }
+
+    /// SQLite identifiers are case-insensitive, so any objects whose names only differ from an
+    /// object in `other` by case are renamed to match `other`.
+    pub(crate) fn match_name_case(mut self, other: &Metadata) -> Self {
+        for (object_type, objects) in self.objects.iter_mut() {
+            let Some(other_objects) = other.objects.get(object_type) else {
+                continue;
+            };
+            let renames: Vec<_> = objects
+                .keys()
+                .filter(|name| !other_objects.contains_key(*name))
+                .filter_map(|name| {
+                    other_objects
+                        .keys()
+                        .find(|other_name| other_name.eq_ignore_ascii_case(name))
+                        .map(|other_name| (name.to_owned(), other_name.to_owned()))
+                })
+                .collect();
+            for (name, other_name) in renames {
+                if let Some(sql) = objects.remove(&name) {
+                    objects.insert(other_name.clone(), sql);
+                }
+                if let Some(columns) = self.columns.remove(&name) {
+                    self.columns.insert(other_name, columns);
+                }
+            }
+        }
+        self
+    }
 
-        let tables = select_metadata(
-            connection,
-            &metadata_sql("table"),
-            log_level,
-            msg,
-            ignore,
-            sql_printer,
-        )?;
-
-        let indexes = select_metadata(
-            connection,
-            &metadata_sql("index"),
-            log_level,
-            msg,
-            ignore,
-            sql_printer,
-        )?;
-
-        let triggers = select_metadata(
-            connection,
-            &metadata_sql("trigger"),
-            log_level,
-            msg,
-            ignore,
-            sql_printer,
-        )?;
-
-        let views = select_metadata(
-            connection,
-            &metadata_sql("view"),
-            log_level,
-            msg,
-            ignore,
-            sql_printer,
-        )?;
-
-        let mut map = BTreeMap::<ObjectType, BTreeMap<String, String>>::new();
-        map.insert(ObjectType::Table, tables);
-        map.insert(ObjectType::Index, indexes);
-        map.insert(ObjectType::View, views);
-        map.insert(ObjectType::Trigger, triggers);
-
-        Ok(Metadata(map))
+    pub fn schema_hash(&self) -> Option<&str> {
+        self.schema_hash.as_deref()
+    }
+
+    pub fn table_columns(&self, name: &str) -> Option<&[ColumnDef]> {
+        self.columns.get(name).map(|c| c.as_slice())
+    }
+
+    /// Lists the objects that differ from `other`, treating `self` as the updated schema.
+    /// Unlike `diff_metadata`, this only compares the SQL as normalized by `config`.
+    pub fn diff_summary(
+        &self,
+        other: &Metadata,
+        config: &Config,
+    ) -> BTreeMap<ObjectType, Vec<(String, ChangeKind)>> {
+        let object_types: BTreeSet<_> = self.objects.keys().chain(other.objects.keys()).collect();
+        object_types
+            .into_iter()
+            .map(|object_type| {
+                let objects = self.objects.get(object_type);
+                let other_objects = other.objects.get(object_type);
+                let names: BTreeSet<_> = objects
+                    .into_iter()
+                    .chain(other_objects)
+                    .flat_map(|o| o.keys())
+                    .collect();
+                let changes = names
+                    .into_iter()
+                    .filter_map(|name| {
+                        let sql = objects.and_then(|o| o.get(name));
+                        let other_sql = other_objects.and_then(|o| o.get(name));
+                        let change_kind = match (sql, other_sql) {
+                            (Some(_), None) => ChangeKind::Added,
+                            (None, _) => ChangeKind::Removed,
+                            (Some(sql), Some(other_sql)) if config.sql_changed(other_sql, sql) => {
+                                ChangeKind::Modified
+                            }
+                            (Some(_), Some(_)) => return None,
+                        };
+                        Some((name.to_owned(), change_kind))
+                    })
+                    .collect();
+                (object_type.to_owned(), changes)
+            })
+            .collect()
+    }
+
+    /// Returns `true` if both schemas contain the same objects with equivalent SQL, ignoring
+    /// differences in whitespace, comments, quoting, and anything removed by the configured
+    /// normalizer
+    pub fn structurally_equal(&self, other: &Metadata, config: &Config) -> bool {
+        self.diff_summary(other, config)
+            .values()
+            .all(|changes| changes.is_empty())
+    }
+
+    /// Returns `true` if there are no tables, indexes, views, or triggers
+    pub fn is_empty(&self) -> bool {
+        self.objects.values().all(|objects| objects.is_empty())
     }
 
     pub fn get(&self, object_type: &ObjectType) -> &BTreeMap<String, String> {
-        self.0.get(object_type).unwrap()
+        self.objects.get(object_type).unwrap()
     }
 
     pub fn tables(&self) -> &BTreeMap<String, String> {
-        self.0.get(&ObjectType::Table).unwrap()
+        self.objects.get(&ObjectType::Table).unwrap()
     }
 
     pub fn indexes(&self) -> &BTreeMap<String, String> {
-        self.0.get(&ObjectType::Index).unwrap()
+        self.objects.get(&ObjectType::Index).unwrap()
     }
 
     pub fn views(&self) -> &BTreeMap<String, String> {
-        self.0.get(&ObjectType::View).unwrap()
+        self.objects.get(&ObjectType::View).unwrap()
     }
 
     pub fn triggers(&self) -> &BTreeMap<String, String> {
-        self.0.get(&ObjectType::Trigger).unwrap()
+        self.objects.get(&ObjectType::Trigger).unwrap()
+    }
+
+    pub(crate) fn remove(&mut self, object_type: &ObjectType, name: &str) {
+        if let Some(objects) = self.objects.get_mut(object_type) {
+            objects.remove(name);
+        }
     }
+
+    pub fn user_version(&self) -> i64 {
+        self.user_version
+    }
+
+    pub(crate) fn create_statements(&self) -> Vec<String> {
+        let mut statements: Vec<String> = sort_by_dependencies(self.tables())
+            .into_iter()
+            .map(|(_, sql)| sql.to_owned())
+            .chain(self.indexes().values().cloned())
+            .chain(self.views().values().cloned())
+            .chain(self.triggers().values().cloned())
+            .collect();
+        if self.user_version != 0 {
+            statements.push(format!("PRAGMA user_version = {}", self.user_version));
+        }
+        statements
+    }
+
+    /// Builds a script that recreates the schema in an empty database. Tables come first, ordered so
+    /// referenced tables are created before the tables that reference them, followed by indexes,
+    /// views, triggers, and the user version. Use this when the script needs to be executed.
+    pub fn to_create_script(&self) -> String {
+        self.create_statements()
+            .iter()
+            .map(|statement| terminate_statement(statement))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Writes every object as a `CREATE` statement, ordered by type and then by name. The output
+    /// is stable for a given schema so it can be committed as a canonical copy of the schema and
+    /// compared between versions. It isn't ordered by dependency and leaves out the user version,
+    /// so use [`Metadata::to_create_script`] when the schema needs to be recreated.
+    pub fn dump(&self) -> String {
+        let mut dump = self
+            .all_objects()
+            .into_iter()
+            .map(|object| terminate_statement(&object.sql))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !dump.is_empty() {
+            dump.push('\n');
+        }
+        dump
+    }
+
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (object_type, objects) in &self.objects {
+            for (name, sql) in objects {
+                hasher.update(format!("{object_type:?}"));
+                hasher.update([0]);
+                hasher.update(name);
+                hasher.update([0]);
+                hasher.update(normalize_sql(sql));
+                hasher.update([0]);
+            }
+        }
+        hasher.update(self.user_version.to_le_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+fn terminate_statement(sql: &str) -> String {
+    let sql = sql.trim().trim_end_matches(';');
+    format!("{sql};")
+}
+
+fn select_columns(
+    connection: &Connection,
+    schema: &str,
+    table: &str,
+    log_level: Level,
+    msg: &str,
+    sql_printer: &mut SqlPrinter,
+) -> Result<Vec<ColumnDef>, QueryError> {
+    query_params(
+        connection,
+        r#"SELECT name, type, "notnull", dflt_value, pk FROM pragma_table_xinfo(?1, ?2) ORDER BY cid"#,
+        [table, schema],
+        log_level,
+        msg,
+        sql_printer,
+        |row| {
+            Ok(ColumnDef {
+                name: row.get(0)?,
+                column_type: row.get(1)?,
+                notnull: row.get(2)?,
+                default: row.get(3)?,
+                pk: row.get(4)?,
+            })
+        },
+    )
 }
 
 fn select_metadata(
     connection: &Connection,
-    sql: &str,
+    schema: &str,
     log_level: Level,
     msg: &str,
-    ignore: &Option<Regex>,
+    config: &Config,
     sql_printer: &mut SqlPrinter,
-) -> Result<BTreeMap<String, String>, QueryError> {
-    let results =
-        query::<(String, String), _>(connection, sql, log_level, msg, sql_printer, |row| {
-            Ok((row.get(0)?, row.get::<_, String>(1)?))
-        })?
-        .into_iter()
-        .filter(|(key, _)| !ignore.as_ref().map(|i| i.is_match(key)).unwrap_or(false));
-    Ok(BTreeMap::from_iter(results))
+) -> Result<Vec<(ObjectType, String, String)>, QueryError> {
+    // Shadow tables are managed by their virtual table, so they're excluded along with the
+    // internal sqlite_ tables
+    let sql = format!(
+        r"SELECT type, name, tbl_name, sql from {schema}.sqlite_master WHERE type IN ('table', 'index', 'view', 'trigger') AND name NOT LIKE 'sqlite\_%' ESCAPE '\' AND name NOT IN (SELECT name FROM pragma_table_list WHERE schema = '{schema}' AND type = 'shadow') AND sql IS NOT NULL ORDER BY name"
+    );
+    let results = query::<(String, String, String, String), _>(
+        connection,
+        &sql,
+        log_level,
+        msg,
+        sql_printer,
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?
+    .into_iter()
+    .filter_map(|(object_type, key, table, sql)| {
+        let object_type = match object_type.as_str() {
+            "table" => ObjectType::Table,
+            "index" => ObjectType::Index,
+            "view" => ObjectType::View,
+            "trigger" => ObjectType::Trigger,
+            _ => return None,
+        };
+        let (key, table, sql) = if schema == "main" {
+            (key, table, sql)
+        } else {
+            (
+                format!("{schema}.{key}"),
+                format!("{schema}.{table}"),
+                qualify_sql(&sql, schema),
+            )
+        };
+        (!config.is_excluded(&object_type, &key, &table)).then_some((object_type, key, sql))
+    })
+    .collect();
+    Ok(results)
 }
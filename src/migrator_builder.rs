@@ -0,0 +1,138 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use regex::Regex;
+use rusqlite::Connection;
+
+use crate::{
+    error::InitializationError, Config, ExtensionSpec, ExtraPolicy, IgnoreRules, Migrator, Options,
+    SqlScript, TransactionBehaviorKind, VacuumMode,
+};
+
+macro_rules! setters {
+    ($field:ident { $($name:ident: $ty:ty),* $(,)? }) => {
+        $(
+            pub fn $name(mut self, $name: $ty) -> Self {
+                self.$field.$name = $name;
+                self
+            }
+        )*
+    };
+}
+
+/// Fluent alternative to [`Migrator::new`] that starts from the default [`Config`] and
+/// [`Options`]
+#[derive(Debug, Clone, Default)]
+pub struct MigratorBuilder {
+    schema: Vec<SqlScript>,
+    config: Config,
+    options: Options,
+}
+
+impl Migrator {
+    pub fn builder() -> MigratorBuilder {
+        MigratorBuilder::default()
+    }
+}
+
+impl MigratorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schema<S: Into<SqlScript>>(mut self, schema: impl IntoIterator<Item = S>) -> Self {
+        self.schema = schema.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn extensions<E: Into<ExtensionSpec>>(
+        mut self,
+        extensions: impl IntoIterator<Item = E>,
+    ) -> Self {
+        self.config.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn before_migration<S: Into<SqlScript>>(
+        mut self,
+        scripts: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.config.before_migration = scripts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn after_migration<S: Into<SqlScript>>(
+        mut self,
+        scripts: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.config.after_migration = scripts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn only(mut self, only: Regex) -> Self {
+        self.config.only = Some(only);
+        self
+    }
+
+    pub fn attachment(mut self, alias: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.config.attachments.push((alias.into(), path.into()));
+        self
+    }
+
+    pub fn target_pragma(mut self, pragma: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config
+            .target_pragmas
+            .push((pragma.into(), value.into()));
+        self
+    }
+
+    pub fn sql_normalizer(
+        mut self,
+        sql_normalizer: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.config.sql_normalizer = Some(Arc::new(sql_normalizer));
+        self
+    }
+
+    setters!(config {
+        ignore: IgnoreRules,
+        only_objects: Vec<String>,
+        treat_hooks_as_schema: bool,
+    });
+
+    pub fn backup_path(mut self, backup_path: impl Into<PathBuf>) -> Self {
+        self.options.backup_path = Some(backup_path.into());
+        self
+    }
+
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.options.busy_timeout = Some(busy_timeout);
+        self
+    }
+
+    setters!(options {
+        allow_deletions: bool,
+        dry_run: bool,
+        vacuum: VacuumMode,
+        analyze: bool,
+        on_extra_objects: ExtraPolicy,
+        pre_integrity_check: bool,
+        checkpoint: bool,
+        transaction_behavior: TransactionBehaviorKind,
+        script_comments: bool,
+        ignore_column_order: bool,
+    });
+
+    pub fn build(self, target_connection: Connection) -> Result<Migrator, InitializationError> {
+        Migrator::new(&self.schema, target_connection, self.config, self.options)
+    }
+}
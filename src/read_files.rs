@@ -1,13 +1,69 @@
 const MAX_PEEK_SIZE: usize = 1024;
 use std::{
+    collections::HashMap,
     io::{self, Read},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use ignore::WalkBuilder;
 
-pub fn read_sql_files(sql_dir: impl AsRef<std::path::Path>) -> Vec<String> {
-    let paths: Vec<_> = ignore::WalkBuilder::new(sql_dir)
+use crate::{
+    error::InitializationError,
+    source_text::{split_statements, OBJECT_NAME_RE},
+    SqlScript,
+};
+
+pub fn read_sql_files(sql_dir: impl AsRef<Path>) -> Vec<SqlScript> {
+    sort_paths(sql_paths(sql_dir))
+}
+
+pub fn read_sql_dirs(sql_dirs: &[impl AsRef<Path>]) -> Result<Vec<SqlScript>, InitializationError> {
+    let mut paths: Vec<(usize, PathBuf)> = sql_dirs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, sql_dir)| sql_paths(sql_dir).into_iter().map(move |p| (i, p)))
+        .filter(|(_, p)| p.is_file())
+        .collect();
+    paths.sort_by_key(|(_, p)| get_sequence(p));
+
+    let mut definitions = HashMap::<String, (usize, PathBuf)>::new();
+    let mut schemas = Vec::with_capacity(paths.len());
+    for (dir_index, path) in paths {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(InitializationError::ReadFailure(path, e)),
+        };
+        // Definitions are matched against the code of each statement so commented out ones are
+        // skipped
+        for statement in split_statements(&contents) {
+            let Some(captures) = OBJECT_NAME_RE.captures(&statement.code) else {
+                continue;
+            };
+            let name = &captures[1];
+            match definitions.get(&name.to_lowercase()) {
+                Some((other_index, other_path)) if *other_index != dir_index => {
+                    return Err(InitializationError::DuplicateDefinition(
+                        name.to_owned(),
+                        other_path.clone(),
+                        path,
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    definitions.insert(name.to_lowercase(), (dir_index, path.clone()));
+                }
+            }
+        }
+        schemas.push(SqlScript {
+            path: Some(path),
+            sql: contents,
+        });
+    }
+    Ok(schemas)
+}
+
+fn sql_paths(sql_dir: impl AsRef<Path>) -> Vec<PathBuf> {
+    ignore::WalkBuilder::new(sql_dir)
         .max_depth(Some(5))
         .filter_entry(|entry| {
             let path = entry.path();
@@ -15,12 +71,10 @@ pub fn read_sql_files(sql_dir: impl AsRef<std::path::Path>) -> Vec<String> {
         })
         .build()
         .filter_map(|dir_result| dir_result.ok().map(|d| d.path().to_path_buf()))
-        .collect();
-
-    sort_paths(paths)
+        .collect()
 }
 
-pub fn sort_paths(mut paths: Vec<PathBuf>) -> Vec<String> {
+pub fn sort_paths(mut paths: Vec<PathBuf>) -> Vec<SqlScript> {
     paths.sort_by(|a, b| {
         let a_seq = get_sequence(a);
         let b_seq = get_sequence(b);
@@ -29,7 +83,10 @@ pub fn sort_paths(mut paths: Vec<PathBuf>) -> Vec<String> {
     paths
         .iter()
         .filter(|p| p.is_file())
-        .map(|p| std::fs::read_to_string(p).unwrap())
+        .map(|p| SqlScript {
+            sql: std::fs::read_to_string(p).unwrap(),
+            path: Some(p.to_owned()),
+        })
         .collect()
 }
 
@@ -52,6 +109,21 @@ pub fn read_extension_dir(extension_dir: impl Into<PathBuf>) -> Result<Vec<PathB
         .filter_map(|r| r.ok().map(|d| d.path().to_path_buf()))
         .collect();
 
+    let libraries: Vec<_> = paths
+        .iter()
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .map(|e| e == std::env::consts::DLL_EXTENSION)
+                    .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+    if !libraries.is_empty() {
+        return Ok(libraries);
+    }
+
+    // Fall back to any binary file if nothing matches the platform's library extension
     Ok(paths
         .iter()
         .filter_map(|p| {
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+pub(crate) static OBJECT_NAME_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)\bCREATE\s+(?:UNIQUE\s+|VIRTUAL\s+)?(?:TABLE|INDEX|VIEW|TRIGGER)\s+(?:IF\s+NOT\s+EXISTS\s+)?["`\[]?(\w+)"#,
+    )
+    .expect("Regex failed to compile")
+});
+
+static TRIGGER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^\s*CREATE\s+(?:TEMP\s+|TEMPORARY\s+)?TRIGGER\b")
+        .expect("Regex failed to compile")
+});
+
+static END_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\bEND\s*$").expect("Regex failed to compile"));
+
+pub(crate) struct Statement {
+    // Original statement text, including comments
+    pub(crate) text: String,
+    // Statement text with comments removed
+    pub(crate) code: String,
+}
+
+/// Maps each object name (lowercased) to the statement that defines it in the schema, including
+/// any comments preceding it
+pub fn source_definitions(schema: &[impl AsRef<str>]) -> BTreeMap<String, String> {
+    let mut definitions = BTreeMap::new();
+    for sql in schema {
+        for statement in split_statements(sql.as_ref()) {
+            if let Some(captures) = OBJECT_NAME_RE.captures(&statement.code) {
+                definitions
+                    .entry(captures[1].to_lowercase())
+                    .or_insert(statement.text);
+            }
+        }
+    }
+    definitions
+}
+
+pub(crate) fn split_statements(sql: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut code = String::new();
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                code.push(c);
+                for (_, c) in chars.by_ref() {
+                    code.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        code.push(c);
+                        break;
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, c) in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                code.push(' ');
+            }
+            // Semicolons inside a trigger body don't terminate the statement
+            ';' if !TRIGGER_RE.is_match(&code) || END_RE.is_match(&code) => {
+                statements.push(Statement {
+                    text: sql[start..=i].trim().to_owned(),
+                    code: std::mem::take(&mut code),
+                });
+                start = i + 1;
+            }
+            c => code.push(c),
+        }
+    }
+    if !code.trim().is_empty() {
+        statements.push(Statement {
+            text: sql[start..].trim().to_owned(),
+            code,
+        });
+    }
+    statements
+}
@@ -1,4 +1,4 @@
-use super::{MigrationMessage, MigrationState, MigratorFactory, SqlState};
+use super::{Help, MigrationMessage, MigrationState, MigratorFactory, SqlState};
 use crate::{
     error::{InitializationError, RefreshError, SqlFormatError},
     Config,
@@ -92,6 +92,10 @@ impl<'a> StatefulWidget for App<'a> {
             3 => state.migration.view(&mut (chunks[1], buf)).unwrap(),
             _ => {}
         }
+
+        if state.show_help {
+            Help::new(state.index).render(area, buf);
+        }
     }
 }
 
@@ -109,6 +113,7 @@ pub struct AppState<'a> {
     target_schema: SqlState<'a>,
     diff_schema: SqlState<'a>,
     migration: MigrationState<'a>,
+    show_help: bool,
 }
 
 impl<'a> AppState<'a> {
@@ -136,8 +141,14 @@ impl<'a> AppState<'a> {
             index: 0,
             source_schema: SqlState::schema("Source", schema.source.clone())?,
             target_schema: SqlState::schema("Target", schema.target.clone())?,
-            diff_schema: SqlState::diff("Diff", schema.clone())?,
+            diff_schema: SqlState::diff(
+                "Diff",
+                schema,
+                migrator_factory.metadata_diff(),
+                migrator_factory.config(),
+            )?,
             migration: MigrationState::new(migrator_factory),
+            show_help: false,
         })
     }
 
@@ -146,8 +157,8 @@ impl<'a> AppState<'a> {
         self.refresh()
     }
 
-    pub fn set_schema_dir(&mut self, dir: PathBuf) -> Result<(), RefreshError> {
-        self.migration.migrator_factory().set_schema_dir(dir);
+    pub fn set_schema_dirs(&mut self, dirs: Vec<PathBuf>) -> Result<(), RefreshError> {
+        self.migration.migrator_factory().set_schema_dirs(dirs);
         self.refresh()
     }
 
@@ -172,12 +183,25 @@ impl<'a> AppState<'a> {
             .map_err(RefreshError::SqlFormatFailure)?;
 
         self.diff_schema
-            .refresh_diff(schema.clone())
+            .refresh_diff(
+                schema,
+                migrator_factory.metadata_diff(),
+                migrator_factory.config(),
+            )
             .map_err(RefreshError::SqlFormatFailure)?;
 
         Ok(())
     }
 
+    fn filtering(&self) -> bool {
+        match self.index {
+            0 => self.source_schema.filtering(),
+            1 => self.target_schema.filtering(),
+            2 => self.diff_schema.filtering(),
+            _ => false,
+        }
+    }
+
     pub fn next_tab(&mut self) {
         self.index = (self.index + 1).rem_euclid(self.titles.len() as i32);
     }
@@ -194,9 +218,19 @@ impl<'a> AppState<'a> {
         use crossterm::event::{Event, KeyCode, KeyEventKind};
 
         if let Event::Key(key) = event {
-            if key.kind == KeyEventKind::Press {
+            if key.kind == KeyEventKind::Press && self.show_help {
+                match key.code {
+                    KeyCode::Char('q') => return Ok(ControlFlow::Quit),
+                    KeyCode::Char('?') | KeyCode::Esc => self.show_help = false,
+                    _ => {}
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            // Keys are sent to the filter input while it's open
+            if key.kind == KeyEventKind::Press && !self.filtering() {
                 match (key.code, self.index) {
                     (KeyCode::Char('q'), _) => return Ok(ControlFlow::Quit),
+                    (KeyCode::Char('?'), _) => self.show_help = true,
                     (KeyCode::Right, _) if !(self.index == 3 && self.migration.popup_active()) => {
                         self.next_tab()
                     }
@@ -224,7 +258,10 @@ impl<'a> Model for AppState<'a> {
     fn update(&mut self, msg: Rc<elm_ui::Message>) -> Result<OptionalCommand, Self::Error> {
         let mut cmds = vec![];
 
+        // The help overlay captures all input while it's open
+        let help_active = self.show_help && matches!(msg.as_ref(), Message::TermEvent(_));
         match self.index {
+            _ if help_active => {}
             0 => {
                 if let Some(cmd) = self.source_schema.update(msg.clone()).unwrap() {
                     cmds.push(cmd);
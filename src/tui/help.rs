@@ -0,0 +1,94 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget},
+};
+
+const SCHEMA_KEYS: &[(&str, &str)] = &[
+    ("↑/↓", "Select object"),
+    ("Tab", "Switch panel focus"),
+    ("/", "Filter objects"),
+    ("y", "Copy selected SQL"),
+];
+
+const DIFF_KEYS: &[(&str, &str)] = &[("s", "Toggle split view")];
+
+const MIGRATE_KEYS: &[(&str, &str)] = &[
+    ("↑/↓", "Select action"),
+    ("Tab", "Switch panel focus"),
+    ("Enter", "Run selected action"),
+    ("Esc", "Close dialog or cancel migration"),
+];
+
+const GLOBAL_KEYS: &[(&str, &str)] = &[("←/→", "Switch tabs"), ("?", "Toggle help"), ("q", "Quit")];
+
+pub struct Help {
+    keys: Vec<(&'static str, &'static str)>,
+}
+
+impl Help {
+    pub fn new(tab_index: i32) -> Self {
+        let tab_keys: &[&[(&str, &str)]] = match tab_index {
+            0 | 1 => &[SCHEMA_KEYS],
+            2 => &[SCHEMA_KEYS, DIFF_KEYS],
+            3 => &[MIGRATE_KEYS],
+            _ => &[],
+        };
+        Self {
+            keys: tab_keys
+                .iter()
+                .flat_map(|keys| keys.iter())
+                .chain(GLOBAL_KEYS.iter())
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+impl Widget for Help {
+    fn render(self, area: Rect, buf: &mut ratatui::buffer::Buffer) {
+        let key_width = self
+            .keys
+            .iter()
+            .map(|(key, _)| key.chars().count())
+            .max()
+            .unwrap_or_default();
+        let lines: Vec<_> = self
+            .keys
+            .iter()
+            .map(|(key, description)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!(" {key:key_width$}  "),
+                        Style::default().fg(Color::Cyan),
+                    ),
+                    Span::from(*description),
+                ])
+            })
+            .collect();
+        let content_width = lines.iter().map(|l| l.width()).max().unwrap_or_default() as u16;
+
+        // Leave room for the borders
+        let width = (content_width + 3).min(area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+
+        let block = Block::default()
+            .title(Span::styled(
+                "Help",
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan));
+
+        Clear.render(area, buf);
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     error::{InitializationError, SqlFormatError},
-    Options,
+    CancellationToken, Migrator, Options,
 };
 use ansi_to_tui::IntoText;
 use chrono::Local;
@@ -13,9 +13,9 @@ use ratatui::{
     text::{Line, Span, Text},
     widgets::{Block, BorderType, Borders, Clear, Paragraph, StatefulWidget, Widget, Wrap},
 };
-use std::{marker::PhantomData, rc::Rc};
+use std::{fs, marker::PhantomData, path::PathBuf, rc::Rc};
 use tokio_stream::wrappers::BroadcastStream;
-use tracing::error;
+use tracing::{error, info};
 
 use super::{
     panel, BiPanel, BiPanelState, BroadcastWriter, Button, MigratorFactory, Scrollable,
@@ -64,10 +64,18 @@ impl<'a> StatefulWidget for MigrationView<'a> {
                     .build(),
             ),
             Line::from(""),
+            Line::from(
+                Button::new("   Save Script     ")
+                    .fg(Color::Blue)
+                    .selected(state.selected == 2)
+                    .enabled(state.controls_enabled)
+                    .build(),
+            ),
+            Line::from(""),
             Line::from(
                 Button::new("   Migrate         ")
                     .fg(Color::Green)
-                    .selected(state.selected == 2)
+                    .selected(state.selected == 3)
                     .enabled(state.controls_enabled)
                     .build(),
             ),
@@ -75,7 +83,7 @@ impl<'a> StatefulWidget for MigrationView<'a> {
             Line::from(
                 Button::new("   Clear Output     ")
                     .fg(Color::Magenta)
-                    .selected(state.selected == 3)
+                    .selected(state.selected == 4)
                     .enabled(state.controls_enabled)
                     .build(),
             ),
@@ -171,6 +179,7 @@ pub struct MigrationState<'a> {
     scroller: ScrollableState,
     bipanel_state: BiPanelState,
     controls_enabled: bool,
+    cancellation_token: Option<CancellationToken>,
     migrator_factory: MigratorFactory,
     _phantom: PhantomData<&'a ()>,
 }
@@ -181,7 +190,7 @@ impl<'a> MigrationState<'a> {
             migrator_factory,
             selected: 0,
             scroller: ScrollableState::new(0),
-            num_buttons: 4,
+            num_buttons: 5,
             show_popup: false,
             popup_button_index: 0,
             logs: "".to_owned(),
@@ -189,6 +198,7 @@ impl<'a> MigrationState<'a> {
             formatted_logs: Text::default(),
             log_start_time: None,
             controls_enabled: true,
+            cancellation_token: None,
             _phantom: Default::default(),
         }
     }
@@ -210,7 +220,15 @@ impl<'a> MigrationState<'a> {
         &mut self,
         event: &crossterm::event::Event,
     ) -> Result<Option<Box<dyn FnOnce() -> MigrationMessage + Send>>, InitializationError> {
-        use crossterm::event::{Event, KeyCode, KeyEventKind};
+        use crossterm::event::{Event, KeyCode, KeyEventKind, MouseEventKind};
+
+        if let Event::Mouse(mouse) = event {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => self.scroller.scroll_up(),
+                MouseEventKind::ScrollDown => self.scroller.scroll_down(),
+                _ => {}
+            }
+        }
 
         if let Event::Key(key) = event {
             if key.kind == KeyEventKind::Press {
@@ -222,6 +240,8 @@ impl<'a> MigrationState<'a> {
                     }
                     KeyCode::Tab => self.toggle_focus(),
                     KeyCode::Enter => return self.execute(),
+                    KeyCode::Esc if self.popup_active() => self.dismiss_popup(),
+                    KeyCode::Esc => self.cancel(),
                     _ => {}
                 }
             }
@@ -248,7 +268,9 @@ impl<'a> MigrationState<'a> {
                 let migrator = self.migrator_factory.create_migrator(Options {
                     allow_deletions: true,
                     dry_run: false,
+                    ..Default::default()
                 })?;
+                let migrator = self.cancellable(migrator);
 
                 self.controls_enabled = false;
                 return Ok(Some(Box::new(move || {
@@ -267,7 +289,9 @@ impl<'a> MigrationState<'a> {
                     let migrator = self.migrator_factory.create_migrator(Options {
                         allow_deletions: true,
                         dry_run: true,
+                        ..Default::default()
                     })?;
+                    let migrator = self.cancellable(migrator);
 
                     self.controls_enabled = false;
                     return Ok(Some(Box::new(move || {
@@ -284,7 +308,9 @@ impl<'a> MigrationState<'a> {
                     let migrator = self.migrator_factory.create_migrator(Options {
                         allow_deletions: true,
                         dry_run: true,
+                        ..Default::default()
                     })?;
+                    let migrator = self.cancellable(migrator);
 
                     self.controls_enabled = false;
                     return Ok(Some(Box::new(move || {
@@ -299,9 +325,44 @@ impl<'a> MigrationState<'a> {
                     })));
                 }
                 2 => {
-                    self.show_popup = true;
+                    self.clear_logs();
+                    BroadcastWriter::enable();
+                    self.log_start_time = Some(chrono::Local::now());
+
+                    let migrator = self.migrator_factory.create_migrator(Options {
+                        allow_deletions: true,
+                        dry_run: true,
+                        ..Default::default()
+                    })?;
+                    let mut migrator = self.cancellable(migrator);
+
+                    self.controls_enabled = false;
+                    return Ok(Some(Box::new(move || {
+                        // Statements passed to the migration callback are formatted for display,
+                        // so the plain script is saved instead to keep the file runnable
+                        let script = match migrator.migrate_to_sql() {
+                            Ok(script) => script,
+                            Err(e) => {
+                                error!("{e}");
+                                return MigrationMessage::ProcessCompleted;
+                            }
+                        };
+
+                        let path = PathBuf::from(format!(
+                            "migration-{}.sql",
+                            Local::now().format("%Y%m%d%H%M%S")
+                        ));
+                        match fs::write(&path, script) {
+                            Ok(()) => info!("Migration script saved to {}", path.display()),
+                            Err(e) => error!("Failed to write {}: {e}", path.display()),
+                        }
+                        MigrationMessage::ProcessCompleted
+                    })));
                 }
                 3 => {
+                    self.show_popup = true;
+                }
+                4 => {
                     self.clear_logs();
                 }
                 _ => {}
@@ -311,10 +372,27 @@ impl<'a> MigrationState<'a> {
         Ok(None)
     }
 
+    fn cancellable(&mut self, migrator: Migrator) -> Migrator {
+        let cancellation_token = CancellationToken::new();
+        self.cancellation_token = Some(cancellation_token.clone());
+        migrator.with_cancellation_token(cancellation_token)
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(cancellation_token) = &self.cancellation_token {
+            cancellation_token.cancel();
+        }
+    }
+
     pub fn popup_active(&self) -> bool {
         self.show_popup
     }
 
+    pub fn dismiss_popup(&mut self) {
+        self.show_popup = false;
+        self.popup_button_index = 0;
+    }
+
     pub fn toggle_popup_confirm(&mut self) {
         self.popup_button_index = (self.popup_button_index + 1) % 2;
     }
@@ -408,6 +486,7 @@ impl<'a> Model for MigrationState<'a> {
                         MigrationMessage::ProcessCompleted
                         | MigrationMessage::MigrationCompleted => {
                             self.controls_enabled = true;
+                            self.cancellation_token = None;
                             BroadcastWriter::disable();
                         }
                     }
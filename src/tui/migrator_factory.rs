@@ -1,31 +1,88 @@
 use crate::{
-    error::InitializationError, read_sql_files, Config, MigrationMetadata, Migrator, Options,
+    connection::{PristineConnection, TargetConnection},
+    diff_metadata,
+    error::InitializationError,
+    read_sql_dirs, schema_hash, Config, MigrationMetadata, Migrator, Options, SchemaDiff, Settings,
+    SqlScript,
 };
 use rusqlite::{Connection, OpenFlags};
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Target path that creates a new in-memory database instead of opening a file
+pub const MEMORY_TARGET: &str = ":memory:";
+
+static MEMORY_TARGET_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
+struct MemoryTarget {
+    uri: String,
+    // Shared in-memory databases are deleted once the last connection closes
+    _connection: Arc<Mutex<Connection>>,
+}
+
+impl MemoryTarget {
+    fn new() -> Self {
+        let uri = format!(
+            "file:slite_memdb{}",
+            MEMORY_TARGET_COUNT.fetch_add(1, Ordering::SeqCst)
+        );
+        let connection = Connection::open_with_flags(&uri, memory_open_flags(OpenFlags::default()))
+            .expect("Failed to open in-memory database");
+        Self {
+            uri,
+            _connection: Arc::new(Mutex::new(connection)),
+        }
+    }
+}
+
+fn memory_open_flags(open_flags: OpenFlags) -> OpenFlags {
+    open_flags
+        | OpenFlags::SQLITE_OPEN_MEMORY
+        | OpenFlags::SQLITE_OPEN_SHARED_CACHE
+        | OpenFlags::SQLITE_OPEN_URI
+}
+
+fn memory_target(path: &Path) -> Option<MemoryTarget> {
+    (path == Path::new(MEMORY_TARGET)).then(MemoryTarget::new)
+}
 
 #[derive(Debug, Clone)]
 pub struct MigratorFactory {
-    schema_dir: PathBuf,
-    schemas: Vec<String>,
+    schema_dirs: Vec<PathBuf>,
+    schemas: Vec<SqlScript>,
     target_db_path: PathBuf,
     metadata: MigrationMetadata,
+    // Computed once per refresh so every view shares the same diff
+    metadata_diff: SchemaDiff,
+    // Hash of the schema files used to parse the cached source metadata
+    source_hash: Option<String>,
     open_flags: OpenFlags,
     config: Config,
+    memory_target: Option<MemoryTarget>,
 }
 
 impl MigratorFactory {
     pub fn new(
-        schema_dir: impl Into<PathBuf>,
+        schema_dirs: Vec<PathBuf>,
         target_db_path: impl Into<PathBuf>,
         config: Config,
     ) -> Result<Self, InitializationError> {
+        let target_db_path = target_db_path.into();
         let mut factory = Self {
             schemas: vec![],
-            schema_dir: schema_dir.into(),
-            target_db_path: target_db_path.into(),
+            schema_dirs,
+            memory_target: memory_target(&target_db_path),
+            target_db_path,
             open_flags: OpenFlags::default(),
             metadata: MigrationMetadata::default(),
+            metadata_diff: SchemaDiff::default(),
+            source_hash: None,
             config,
         };
         factory.update_schemas()?;
@@ -38,45 +95,87 @@ impl MigratorFactory {
 
     pub fn set_config(&mut self, config: Config) {
         self.config = config;
+        // Extensions, hooks, and ignore rules all affect the source metadata
+        self.source_hash = None;
     }
 
-    pub fn set_schema_dir(&mut self, dir: PathBuf) {
-        self.schema_dir = dir;
+    pub fn set_schema_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.schema_dirs = dirs;
     }
 
     pub fn set_target_path(&mut self, path: PathBuf) {
+        self.memory_target = memory_target(&path);
         self.target_db_path = path;
     }
 
     pub fn create_migrator(&self, options: Options) -> Result<Migrator, InitializationError> {
         Migrator::new(
             &self.schemas,
-            Connection::open_with_flags(&self.target_db_path, self.open_flags).unwrap(),
+            self.open_target(),
             self.config.clone(),
             options,
         )
     }
 
-    pub fn schema_dir(&self) -> &PathBuf {
-        &self.schema_dir
+    fn open_target(&self) -> Connection {
+        match &self.memory_target {
+            Some(memory_target) => {
+                Connection::open_with_flags(&memory_target.uri, memory_open_flags(self.open_flags))
+                    .unwrap()
+            }
+            None => Connection::open_with_flags(&self.target_db_path, self.open_flags).unwrap(),
+        }
+    }
+
+    pub fn schema_dirs(&self) -> &[PathBuf] {
+        &self.schema_dirs
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
     pub fn metadata(&self) -> &MigrationMetadata {
         &self.metadata
     }
 
-    pub fn update_schemas(&mut self) -> Result<(), InitializationError> {
-        self.schemas = read_sql_files(&self.schema_dir);
+    pub fn metadata_diff(&self) -> &SchemaDiff {
+        &self.metadata_diff
+    }
 
-        self.metadata = self
-            .create_migrator(Options {
-                allow_deletions: false,
+    pub fn update_schemas(&mut self) -> Result<(), InitializationError> {
+        self.schemas = read_sql_dirs(&self.schema_dirs)?;
+        let settings = Settings {
+            config: self.config.clone(),
+            options: Options {
                 dry_run: true,
-            })?
+                ..Default::default()
+            },
+        };
+
+        let source_hash = schema_hash(&self.schemas);
+        if self.source_hash.as_ref() != Some(&source_hash) {
+            let mut pristine = PristineConnection::new(settings.clone())?;
+            pristine.initialize_schema(
+                self.config
+                    .before_migration
+                    .iter()
+                    .chain(self.schemas.iter())
+                    .chain(self.config.after_migration.iter()),
+            )?;
+            self.metadata.source = pristine.parse_metadata().map_err(|e| {
+                InitializationError::QueryFailure("Failed to parse metadata".to_owned(), e)
+            })?;
+            self.source_hash = Some(source_hash);
+        }
+
+        self.metadata.target = TargetConnection::new(self.open_target(), settings)?
             .parse_metadata()
             .map_err(|e| {
                 InitializationError::QueryFailure("Failed to parse metadata".to_owned(), e)
-            })?;
+            })?
+            .match_name_case(&self.metadata.source);
+        self.metadata_diff = diff_metadata(self.metadata.clone());
         Ok(())
     }
 }
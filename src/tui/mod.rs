@@ -12,6 +12,8 @@ mod panel;
 pub use panel::*;
 mod button;
 pub use button::*;
+mod help;
+pub use help::*;
 mod app;
 pub use app::*;
 mod migrator_factory;
@@ -1,9 +1,13 @@
 use std::collections::BTreeMap;
 
 use ratatui::{
+    layout::Alignment,
     style::{Color, Modifier, Style},
     text::Text,
-    widgets::{Block, List, ListItem, ListState, StatefulWidget},
+    widgets::{
+        block::{Position, Title},
+        Block, List, ListItem, ListState, StatefulWidget,
+    },
 };
 
 use crate::ObjectType;
@@ -30,6 +34,15 @@ impl<'a> StatefulWidget for Objects<'a> {
     ) {
         let items: Vec<ListItem> = state.objects.iter().map(|i| i.clone().into()).collect();
         let selected_color = state.selected_color().unwrap_or(Color::Reset);
+        let block = if state.filtering || !state.filter.is_empty() {
+            self.block.title(
+                Title::from(format!("/{}", state.filter))
+                    .position(Position::Bottom)
+                    .alignment(Alignment::Left),
+            )
+        } else {
+            self.block
+        };
         List::new(items)
             .highlight_style(
                 Style::default()
@@ -37,7 +50,7 @@ impl<'a> StatefulWidget for Objects<'a> {
                     .bg(Color::Black)
                     .add_modifier(Modifier::BOLD),
             )
-            .block(self.block)
+            .block(block)
             .render(area, buf, &mut state.state);
     }
 }
@@ -69,14 +82,17 @@ impl From<ListItemType> for ListItem<'static> {
 pub struct ObjectsState {
     state: ListState,
     object_view_width: usize,
+    all_objects: Vec<ListItemType>,
     objects: Vec<ListItemType>,
+    // Position of each visible entry in the unfiltered list, or None for headers
+    entry_indexes: Vec<Option<usize>>,
     has_items: bool,
     adjusted_index: i32,
-    adjusted_size: i32,
+    filter: String,
+    filtering: bool,
 }
 
 const LIST_PADDING: usize = 5;
-const NUM_HEADERS: i32 = 4;
 
 pub struct StyledObject {
     pub object: String,
@@ -121,7 +137,6 @@ impl From<&StyledObject> for ListItemType {
 
 impl ObjectsState {
     pub fn new(objects: StyledObjects) -> ObjectsState {
-        let has_items = !objects.is_empty();
         let list_items: Vec<_> = vec![]
             .into_iter()
             .chain([ListItemType::Header("Tables".to_owned())])
@@ -143,27 +158,35 @@ impl ObjectsState {
             .max()
             .unwrap_or_default();
 
-        let mut state = ListState::default();
-        if has_items {
-            state.select(Some(1));
-        }
-        ObjectsState {
-            state,
-            adjusted_size: list_items.len() as i32 - NUM_HEADERS,
-            objects: list_items,
+        let mut state = ObjectsState {
+            state: ListState::default(),
+            all_objects: list_items,
+            objects: vec![],
+            entry_indexes: vec![],
             object_view_width: max_length,
-            has_items,
+            has_items: false,
             adjusted_index: 0,
-        }
+            filter: String::new(),
+            filtering: false,
+        };
+        state.apply_filter();
+        state
     }
 
     pub fn next(&mut self) {
+        self.move_selection(1);
+    }
+
+    pub fn previous(&mut self) {
+        self.move_selection(-1);
+    }
+
+    fn move_selection(&mut self, step: i32) {
         if !self.has_items {
             return;
         }
-        self.adjusted_index = (self.adjusted_index + 1).rem_euclid(self.adjusted_size);
 
-        let mut next_index = (self.state.selected().expect("Item not selected") as i32 + 1)
+        let mut next_index = (self.state.selected().expect("Item not selected") as i32 + step)
             .rem_euclid(self.objects.len() as i32);
         let real_index = loop {
             match self.objects.get(next_index as usize) {
@@ -171,40 +194,109 @@ impl ObjectsState {
                     break next_index;
                 }
                 Some(ListItemType::Header(_)) => {
-                    next_index = (next_index + 1).rem_euclid(self.objects.len() as i32);
+                    next_index = (next_index + step).rem_euclid(self.objects.len() as i32);
                 }
                 None => unreachable!(),
             }
         };
 
-        self.state.select(Some(real_index as usize));
+        self.select_index(real_index as usize);
     }
 
-    pub fn previous(&mut self) {
-        if !self.has_items {
-            return;
-        }
-        self.adjusted_index = (self.adjusted_index - 1).rem_euclid(self.adjusted_size);
+    fn select_index(&mut self, index: usize) {
+        self.state.select(Some(index));
+        self.adjusted_index = self.entry_indexes[index].expect("Header selected") as i32;
+    }
 
-        let mut next_index = (self.state.selected().expect("Item not selected") as i32 - 1)
-            .rem_euclid(self.objects.len() as i32);
-        let real_index = loop {
-            match self.objects.get(next_index as usize) {
-                Some(ListItemType::Entry { .. }) => {
-                    break next_index;
+    pub fn filtering(&self) -> bool {
+        self.filtering
+    }
+
+    pub fn start_filter(&mut self) {
+        self.filtering = true;
+    }
+
+    pub fn stop_filter(&mut self) {
+        self.filtering = false;
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filtering = false;
+        self.set_filter(String::new());
+    }
+
+    pub fn push_filter(&mut self, c: char) {
+        let mut filter = self.filter.clone();
+        filter.push(c);
+        self.set_filter(filter);
+    }
+
+    pub fn pop_filter(&mut self) {
+        let mut filter = self.filter.clone();
+        filter.pop();
+        self.set_filter(filter);
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.apply_filter();
+    }
+
+    fn apply_filter(&mut self) {
+        let selected = self.selected_item();
+        let filter = self.filter.to_lowercase();
+
+        let mut objects = vec![];
+        let mut entry_indexes = vec![];
+        let mut entry_index = 0;
+        let mut header = None;
+        for object in &self.all_objects {
+            match object {
+                ListItemType::Header(_) => {
+                    header = Some(object.clone());
+                    // Headers are only shown if one of their entries matches, except when
+                    // there's no filter so empty sections are still displayed
+                    if filter.is_empty() {
+                        objects.extend(header.take());
+                        entry_indexes.push(None);
+                    }
                 }
-                Some(ListItemType::Header(_)) => {
-                    next_index = (next_index - 1).rem_euclid(self.objects.len() as i32);
+                ListItemType::Entry(name, _) => {
+                    if name.to_lowercase().contains(&filter) {
+                        if let Some(header) = header.take() {
+                            objects.push(header);
+                            entry_indexes.push(None);
+                        }
+                        objects.push(object.clone());
+                        entry_indexes.push(Some(entry_index));
+                    }
+                    entry_index += 1;
                 }
-                None => unreachable!(),
             }
-        };
+        }
 
-        self.state.select(Some(real_index as usize));
+        self.objects = objects;
+        self.entry_indexes = entry_indexes;
+        self.has_items = self.entry_indexes.iter().any(|i| i.is_some());
+        self.state.select(None);
+        self.adjusted_index = 0;
+        if let Some(selected) = selected {
+            self.select(&selected);
+        }
+        if self.state.selected().is_none() {
+            if let Some(first) = self.entry_indexes.iter().position(|i| i.is_some()) {
+                self.select_index(first);
+            }
+        }
     }
 
-    pub fn selected_index(&self) -> usize {
-        self.adjusted_index as usize
+    /// Position of the selected entry in the unfiltered list, or None if nothing is selected
+    pub fn selected_index(&self) -> Option<usize> {
+        self.state.selected().map(|_| self.adjusted_index as usize)
     }
 
     pub fn selected_item(&self) -> Option<String> {
@@ -230,17 +322,12 @@ impl ObjectsState {
     }
 
     pub fn select(&mut self, entry: &str) {
-        let mut skip = 0;
-        for (i, object) in self.objects.iter().enumerate() {
-            match object {
-                ListItemType::Header(_) => skip += 1,
-                ListItemType::Entry(val, _) => {
-                    if val == entry {
-                        self.state.select(Some(i));
-                        self.adjusted_index = (i - skip) as i32;
-                    }
-                }
-            }
+        let index = self
+            .objects
+            .iter()
+            .position(|object| matches!(object, ListItemType::Entry(val, _) if val == entry));
+        if let Some(index) = index {
+            self.select_index(index);
         }
     }
 
@@ -4,16 +4,23 @@ use super::{
     panel, BiPanel, BiPanelState, Objects, ObjectsState, Scrollable, ScrollableState, StyledObject,
     StyledObjects,
 };
-use crate::{diff_metadata, error::SqlFormatError, Metadata, MigrationMetadata, SqlPrinter};
+use crate::{
+    error::SqlFormatError, split_diff, ChangeKind, Config, DiffAlgorithm, Metadata,
+    MigrationMetadata, SchemaDiff, SqlPrinter,
+};
 use ansi_to_tui::IntoText;
 use elm_ui::{Message, Model, OptionalCommand};
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::Color,
     text::Text,
-    widgets::{Paragraph, StatefulWidget, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Block, Paragraph, StatefulWidget, Wrap,
+    },
 };
+use tracing::warn;
 
 #[derive(Debug, Clone)]
 pub struct SqlView<'a> {
@@ -49,24 +56,65 @@ impl<'a> StatefulWidget for SqlView<'a> {
             &mut state.state,
         );
 
-        Scrollable::new(
-            Paragraph::new(
-                state
-                    .sql
-                    .get(state.state.selected_index())
-                    .cloned()
-                    .unwrap_or_default(),
+        let status_block = |block: Block<'a>| match &state.status {
+            Some(status) => block.title(
+                Title::from(status.clone())
+                    .position(Position::Bottom)
+                    .alignment(Alignment::Right),
+            ),
+            None => block,
+        };
+        let sql_block = status_block(state.bipanel_state.right_block("SQL"));
+        let source_block = status_block(state.bipanel_state.right_block("Source"));
+        let target_block = status_block(state.bipanel_state.right_block("Target"));
+
+        if state.split {
+            state.load_split();
+            let (source, target) = state
+                .state
+                .selected_index()
+                .and_then(|index| state.split_sql.get(index).cloned().flatten())
+                .unwrap_or_default();
+            let split_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(chunks[1]);
+            // Lines aren't wrapped so both sides stay aligned
+            Scrollable::new(Paragraph::new(source).block(source_block)).render(
+                split_chunks[0],
+                buf,
+                &mut state.scroller,
+            );
+            Scrollable::new(Paragraph::new(target).block(target_block)).render(
+                split_chunks[1],
+                buf,
+                &mut state.scroller,
+            );
+        } else {
+            Scrollable::new(
+                Paragraph::new(
+                    state
+                        .state
+                        .selected_index()
+                        .and_then(|index| state.sql.get(index).cloned())
+                        .unwrap_or_default(),
+                )
+                .wrap(Wrap { trim: false })
+                .block(sql_block),
             )
-            .wrap(Wrap { trim: false })
-            .block(state.bipanel_state.right_block("SQL")),
-        )
-        .render(chunks[1], buf, &mut state.scroller);
+            .render(chunks[1], buf, &mut state.scroller);
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct SqlState<'a> {
     sql: Vec<Text<'a>>,
+    raw_sql: Vec<String>,
+    status: Option<String>,
+    split_sql: Vec<Option<(Text<'a>, Text<'a>)>>,
+    split_source: Vec<(String, String)>,
+    split: bool,
     title: &'a str,
     state: ObjectsState,
     scroller: ScrollableState,
@@ -74,35 +122,32 @@ pub struct SqlState<'a> {
 }
 
 impl<'a> SqlState<'a> {
-    pub fn diff(title: &'a str, schemas: MigrationMetadata) -> Result<Self, SqlFormatError> {
-        let diffs = diff_metadata(schemas);
+    pub fn diff(
+        title: &'a str,
+        metadata: &MigrationMetadata,
+        diffs: &SchemaDiff,
+        config: &Config,
+    ) -> Result<Self, SqlFormatError> {
+        let summary = metadata.source.diff_summary(&metadata.target, config);
 
         let objects = diffs.iter().map(|(object_type, objects)| {
+            let changes = summary.get(object_type);
             (
                 object_type.to_owned(),
                 objects
-                    .iter()
-                    .map(|(name, diff)| {
-                        if diff.diff_text.is_empty() {
-                            StyledObject {
-                                object: name.to_owned(),
-                                foreground: Color::Reset,
-                            }
-                        } else if diff.original_text.is_empty() {
-                            StyledObject {
-                                object: name.to_owned(),
-                                foreground: Color::Red,
-                            }
-                        } else if diff.new_text.is_empty() {
-                            StyledObject {
-                                object: name.to_owned(),
-                                foreground: Color::Green,
-                            }
-                        } else {
-                            StyledObject {
-                                object: name.to_owned(),
-                                foreground: Color::Yellow,
-                            }
+                    .keys()
+                    .map(|name| {
+                        let change_kind = changes
+                            .and_then(|c| c.iter().find(|(n, _)| n == name))
+                            .map(|(_, change_kind)| *change_kind);
+                        StyledObject {
+                            object: name.to_owned(),
+                            foreground: match change_kind {
+                                Some(ChangeKind::Added) => Color::Green,
+                                Some(ChangeKind::Removed) => Color::Red,
+                                Some(ChangeKind::Modified) => Color::Yellow,
+                                Some(ChangeKind::Unchanged) | None => Color::Reset,
+                            },
                         }
                     })
                     .collect::<Vec<_>>(),
@@ -126,9 +171,38 @@ impl<'a> SqlState<'a> {
             })
             .collect();
 
+        let split_source: Vec<_> = diffs
+            .iter()
+            .flat_map(|(object_type, objects)| {
+                objects.keys().map(move |name| {
+                    let sql = |m: &Metadata| m.get(object_type).get(name).cloned();
+                    (
+                        sql(&metadata.source).unwrap_or_default(),
+                        sql(&metadata.target).unwrap_or_default(),
+                    )
+                })
+            })
+            .collect();
+
         let state = ObjectsState::new(styled);
 
-        Ok(Self::new(title, list_items?, state))
+        let raw_sql = diffs
+            .iter()
+            .flat_map(|(object_type, objects)| {
+                objects.keys().map(move |name| {
+                    [&metadata.source, &metadata.target]
+                        .iter()
+                        .find_map(|m| m.get(object_type).get(name))
+                        .cloned()
+                        .unwrap_or_default()
+                })
+            })
+            .collect();
+
+        let mut sql_state = Self::new(title, list_items?, raw_sql, state);
+        sql_state.split_sql = vec![None; split_source.len()];
+        sql_state.split_source = split_source;
+        Ok(sql_state)
     }
 
     pub fn schema(title: &'a str, schema: Metadata) -> Result<Self, SqlFormatError> {
@@ -160,14 +234,29 @@ impl<'a> SqlState<'a> {
             })
             .collect();
 
-        Ok(Self::new(title, list_items?, state))
+        let raw_sql = schema
+            .values()
+            .flat_map(|objects| objects.values().cloned())
+            .collect();
+
+        Ok(Self::new(title, list_items?, raw_sql, state))
     }
 
-    fn new(title: &'a str, sql: Vec<Text<'static>>, state: ObjectsState) -> Self {
+    fn new(
+        title: &'a str,
+        sql: Vec<Text<'static>>,
+        raw_sql: Vec<String>,
+        state: ObjectsState,
+    ) -> Self {
         let height = sql.first().map(|s| s.height()).unwrap_or(0) as u16;
         let scroller = ScrollableState::new(height);
         Self {
             sql,
+            raw_sql,
+            status: None,
+            split_sql: vec![],
+            split_source: vec![],
+            split: false,
             title,
             state,
             scroller,
@@ -187,10 +276,101 @@ impl<'a> SqlState<'a> {
         self.bipanel_state.toggle_focus();
     }
 
+    pub fn toggle_split(&mut self) {
+        if self.split_source.is_empty() {
+            return;
+        }
+        self.split = !self.split;
+        self.reset_scroll();
+    }
+
+    // The side-by-side view is only highlighted once an object is viewed with it since doing so
+    // for every object on each refresh is expensive
+    fn load_split(&mut self) {
+        let Some(index) = self.state.selected_index() else {
+            return;
+        };
+        let (Some(None), Some((source, target))) =
+            (self.split_sql.get(index), self.split_source.get(index))
+        else {
+            return;
+        };
+        let (source, target): (Vec<_>, Vec<_>) =
+            split_diff(source, target, DiffAlgorithm::default())
+                .into_iter()
+                .map(|line| {
+                    (
+                        line.source.unwrap_or_default(),
+                        line.target.unwrap_or_default(),
+                    )
+                })
+                .unzip();
+        let to_text = |lines: Vec<String>| {
+            let text = lines.join("\n");
+            text.into_text().unwrap_or_else(|e| {
+                warn!("Failed to format diff: {e}");
+                Text::raw(text)
+            })
+        };
+        self.split_sql[index] = Some((to_text(source), to_text(target)));
+    }
+
+    fn reset_scroll(&mut self) {
+        if self.split {
+            self.load_split();
+        }
+        self.scroller.set_content_height(self.content_height());
+        self.scroller.scroll_to_top();
+    }
+
+    pub fn copy_selected(&mut self) {
+        let Some(sql) = self
+            .state
+            .selected_index()
+            .and_then(|index| self.raw_sql.get(index))
+        else {
+            return;
+        };
+        let result = arboard::Clipboard::new().and_then(|mut c| c.set_text(sql.to_owned()));
+        self.status = Some(match result {
+            Ok(()) => "Copied to clipboard".to_owned(),
+            Err(e) => {
+                warn!("Failed to copy to clipboard: {e}");
+                "Clipboard unavailable".to_owned()
+            }
+        });
+    }
+
+    fn content_height(&self) -> u16 {
+        let Some(index) = self.state.selected_index() else {
+            return 0;
+        };
+        if self.split {
+            self.split_sql
+                .get(index)
+                .and_then(|split| split.as_ref())
+                .map(|(source, target)| source.height().max(target.height()))
+                .unwrap_or(0) as u16
+        } else {
+            self.sql.get(index).map(|s| s.height()).unwrap_or(0) as u16
+        }
+    }
+
     pub fn selected_item(&self) -> Option<String> {
         self.state.selected_item()
     }
 
+    pub fn filtering(&self) -> bool {
+        self.state.filtering()
+    }
+
+    fn restore_filter(&mut self, previous: &ObjectsState) {
+        self.state.set_filter(previous.filter().to_owned());
+        if previous.filtering() {
+            self.state.start_filter();
+        }
+    }
+
     pub fn select(&mut self, item: &str) {
         self.state.select(item);
     }
@@ -198,6 +378,7 @@ impl<'a> SqlState<'a> {
     pub fn refresh_schema(&mut self, metadata: Metadata) -> Result<(), SqlFormatError> {
         let selected = self.selected_item();
         let mut new_state = SqlState::schema(self.title, metadata)?;
+        new_state.restore_filter(&self.state);
         if let Some(selected) = selected {
             new_state.select(&selected);
         }
@@ -205,26 +386,58 @@ impl<'a> SqlState<'a> {
         Ok(())
     }
 
-    pub fn refresh_diff(&mut self, metadata: MigrationMetadata) -> Result<(), SqlFormatError> {
+    pub fn refresh_diff(
+        &mut self,
+        metadata: &MigrationMetadata,
+        diffs: &SchemaDiff,
+        config: &Config,
+    ) -> Result<(), SqlFormatError> {
         let selected = self.selected_item();
-        let mut new_state = SqlState::diff(self.title, metadata)?;
+        let mut new_state = SqlState::diff(self.title, metadata, diffs, config)?;
+        new_state.restore_filter(&self.state);
         if let Some(selected) = selected {
             new_state.select(&selected);
         }
+        if self.split {
+            new_state.toggle_split();
+        }
         std::mem::swap(self, &mut new_state);
         Ok(())
     }
 
     #[cfg(feature = "crossterm-events")]
     pub fn handle_event(&mut self, event: &crossterm::event::Event) {
-        use crossterm::event::{Event, KeyCode, KeyEventKind};
+        use crossterm::event::{Event, KeyCode, KeyEventKind, MouseEventKind};
+
+        if let Event::Mouse(mouse) = event {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => self.scroller.scroll_up(),
+                MouseEventKind::ScrollDown => self.scroller.scroll_down(),
+                _ => {}
+            }
+        }
 
         if let Event::Key(key) = event {
             if key.kind == KeyEventKind::Press {
+                self.status = None;
+            }
+            if key.kind == KeyEventKind::Press && self.state.filtering() {
+                match key.code {
+                    KeyCode::Char(c) => self.state.push_filter(c),
+                    KeyCode::Backspace => self.state.pop_filter(),
+                    KeyCode::Enter => self.state.stop_filter(),
+                    KeyCode::Esc => self.state.clear_filter(),
+                    _ => {}
+                }
+                self.reset_scroll();
+            } else if key.kind == KeyEventKind::Press {
                 match key.code {
+                    KeyCode::Char('/') => self.state.start_filter(),
                     KeyCode::Up => self.previous(),
                     KeyCode::Down => self.next(),
                     KeyCode::Tab => self.toggle_focus(),
+                    KeyCode::Char('s') => self.toggle_split(),
+                    KeyCode::Char('y') => self.copy_selected(),
                     _ => {}
                 }
             }
@@ -239,9 +452,7 @@ impl<'a> BiPanel for SqlState<'a> {
         }
 
         self.state.next();
-        self.scroller
-            .set_content_height(self.sql.get(self.state.selected_index()).unwrap().height() as u16);
-        self.scroller.scroll_to_top();
+        self.reset_scroll();
     }
 
     fn right_next(&mut self) {
@@ -254,9 +465,7 @@ impl<'a> BiPanel for SqlState<'a> {
         }
 
         self.state.previous();
-        self.scroller
-            .set_content_height(self.sql.get(self.state.selected_index()).unwrap().height() as u16);
-        self.scroller.scroll_to_top();
+        self.reset_scroll();
     }
 
     fn right_previous(&mut self) {
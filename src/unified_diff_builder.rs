@@ -7,7 +7,7 @@ use imara_diff::Sink;
 use owo_colors::OwoColorize;
 use tracing::error;
 
-use crate::{Color, SqlPrinter};
+use crate::{Color, DiffHunk, DiffLine, SqlPrinter};
 
 pub struct UnifiedDiffBuilder<'a, W, T>
 where
@@ -19,6 +19,7 @@ where
     interner: &'a Interner<T>,
 
     pos: u32,
+    context_lines: u32,
     before_hunk_start: u32,
     after_hunk_start: u32,
     before_hunk_len: u32,
@@ -26,22 +27,32 @@ where
 
     buffer: String,
     dst: W,
+    lines: Vec<DiffLine>,
+    hunks: Vec<DiffHunk>,
 
     sql_printer: SqlPrinter,
 }
 
+pub struct UnifiedDiff<W> {
+    pub text: W,
+    pub hunks: Vec<DiffHunk>,
+}
+
 impl<'a, T> UnifiedDiffBuilder<'a, String, T>
 where
     T: Hash + Eq + Display,
 {
-    pub fn new(input: &'a InternedInput<T>) -> Self {
+    pub fn new(input: &'a InternedInput<T>, context_lines: u32) -> Self {
         Self {
+            context_lines,
             before_hunk_start: 0,
             after_hunk_start: 0,
             before_hunk_len: 0,
             after_hunk_len: 0,
             buffer: String::with_capacity(8),
             dst: String::new(),
+            lines: Vec::new(),
+            hunks: Vec::new(),
             interner: &input.interner,
             before: &input.before,
             after: &input.after,
@@ -69,6 +80,12 @@ where
     ) -> Result<(), std::fmt::Error> {
         for &token in tokens {
             let raw_token = &self.interner[token];
+            let raw_line = raw_token.to_string().trim_end_matches('\n').to_owned();
+            self.lines.push(match diff_type {
+                DiffType::Add => DiffLine::Added(raw_line),
+                DiffType::Remove => DiffLine::Removed(raw_line),
+                DiffType::None => DiffLine::Context(raw_line),
+            });
             let line = match diff_type {
                 DiffType::Add => format!(
                     "{}{}",
@@ -101,11 +118,11 @@ where
             // If the first diff comes before the start of the text, print the text before the diff
             self.print_tokens(&self.before[0..before.start as usize], DiffType::None)?;
         }
-        if before.start - self.pos > 6 {
+        if before.start - self.pos > 2 * self.context_lines {
             self.flush()?;
-            self.pos = before.start - 3;
+            self.pos = before.start - self.context_lines;
             self.before_hunk_start = self.pos;
-            self.after_hunk_start = after.start - 3;
+            self.after_hunk_start = after.start.saturating_sub(self.context_lines);
         }
         self.update_pos(before.start, before.end)?;
         self.before_hunk_len += before.end - before.start;
@@ -127,7 +144,7 @@ where
             return Ok(());
         }
 
-        let end = (self.pos + 3).min(self.before.len() as u32);
+        let end = (self.pos + self.context_lines).min(self.before.len() as u32);
         self.update_pos(end, end)?;
 
         if self.pos < self.before.len() as u32 {
@@ -150,6 +167,13 @@ where
         writeln!(&mut self.dst, "{header}")?;
         write!(&mut self.dst, "{}", &self.buffer)?;
         self.buffer.clear();
+        self.hunks.push(DiffHunk {
+            before_start: self.before_hunk_start + 1,
+            before_len: self.before_hunk_len,
+            after_start: self.after_hunk_start + 1,
+            after_len: self.after_hunk_len,
+            lines: std::mem::take(&mut self.lines),
+        });
         self.before_hunk_len = 0;
         self.after_hunk_len = 0;
         Ok(())
@@ -174,7 +198,7 @@ where
     W: Write,
     T: Hash + Eq + Display,
 {
-    type Out = W;
+    type Out = UnifiedDiff<W>;
 
     fn process_change(&mut self, before: Range<u32>, after: Range<u32>) {
         if let Err(e) = self.process_change(before, after) {
@@ -186,6 +210,9 @@ where
         if let Err(e) = self.flush() {
             error!("Error flushing: {e}");
         }
-        self.dst
+        UnifiedDiff {
+            text: self.dst,
+            hunks: self.hunks,
+        }
     }
 }